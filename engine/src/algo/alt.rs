@@ -0,0 +1,541 @@
+//! ALT: A*, landmarks and the triangle inequality.
+//!
+//! Lower bounds are derived from precomputed shortest distances from and to a small set of landmarks.
+//! For each landmark `l`, both `dist(l, t) - dist(l, v)` and `dist(v, l) - dist(t, l)` are lower bounds of `dist(v, t)`.
+//! Landmarks can be selected with the avoid, farthest or planar strategy.
+//! `ALTPotential` implements `Potential`, so it can be used with the CH-Potentials query server.
+//! `Server` is a bidirectional A* with consistent average potentials.
+
+use super::*;
+use crate::{
+    algo::{
+        ch_potentials::Potential,
+        dijkstra::{generic_dijkstra::*, Label},
+    },
+    as_slice::AsSlice,
+    io::*,
+};
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::cmp::max;
+
+type BorrowedGraph<'a> = FirstOutGraph<&'a [EdgeId], &'a [NodeId], &'a [Weight]>;
+
+fn borrowed<F, H, W>(graph: &FirstOutGraph<F, H, W>) -> BorrowedGraph<'_>
+where
+    F: AsSlice<EdgeId>,
+    H: AsSlice<NodeId>,
+    W: AsSlice<Weight>,
+{
+    FirstOutGraph::new(graph.first_out(), graph.head(), graph.weight())
+}
+
+// Dijkstras for distances from and to a single node
+struct OneToAll<'a> {
+    forward: StandardDijkstra<BorrowedGraph<'a>>,
+    backward: StandardDijkstra<BorrowedGraph<'a>>,
+}
+
+impl<'a> OneToAll<'a> {
+    fn new(graph: BorrowedGraph<'a>, reversed: &'a OwnedGraph) -> Self {
+        OneToAll {
+            forward: StandardDijkstra::new(graph),
+            backward: StandardDijkstra::new(borrowed(reversed)),
+        }
+    }
+
+    fn run(dijkstra: &mut StandardDijkstra<BorrowedGraph<'a>>, node: NodeId) -> Vec<Weight> {
+        let n = dijkstra.graph().num_nodes();
+        dijkstra.initialize_query(Query { from: node, to: n as NodeId });
+        while dijkstra.next().is_some() {}
+        (0..n).map(|other| *dijkstra.tentative_distance(other as NodeId)).collect()
+    }
+
+    // (distances from node, distances to node)
+    fn distances(&mut self, node: NodeId) -> (Vec<Weight>, Vec<Weight>) {
+        (Self::run(&mut self.forward, node), Self::run(&mut self.backward, node))
+    }
+}
+
+/// Landmarks with precomputed distances from and to every node.
+#[derive(Debug, Clone)]
+pub struct Landmarks {
+    landmarks: Vec<NodeId>,
+    // distances from each landmark, the values of all landmarks are stored consecutively for each node
+    forward: Vec<Weight>,
+    // distances to each landmark, same layout as `forward`
+    backward: Vec<Weight>,
+}
+
+impl Landmarks {
+    /// Compute distances from and to the given landmarks (in parallel).
+    pub fn new<F, H, W>(graph: &FirstOutGraph<F, H, W>, landmarks: Vec<NodeId>) -> Self
+    where
+        F: AsSlice<EdgeId>,
+        H: AsSlice<NodeId>,
+        W: AsSlice<Weight>,
+    {
+        let graph = borrowed(graph);
+        let reversed = OwnedGraph::reversed(&graph);
+        let columns: Vec<(Vec<Weight>, Vec<Weight>)> = landmarks
+            .par_iter()
+            .map(|&landmark| OneToAll::new(borrowed(&graph), &reversed).distances(landmark))
+            .collect();
+        Self::from_columns(landmarks, &columns)
+    }
+
+    fn from_columns(landmarks: Vec<NodeId>, columns: &[(Vec<Weight>, Vec<Weight>)]) -> Self {
+        let n = columns.first().map(|(forward, _)| forward.len()).unwrap_or(0);
+        let mut forward = Vec::with_capacity(n * landmarks.len());
+        let mut backward = Vec::with_capacity(n * landmarks.len());
+        for node in 0..n {
+            forward.extend(columns.iter().map(|(from_landmark, _)| from_landmark[node]));
+            backward.extend(columns.iter().map(|(_, to_landmark)| to_landmark[node]));
+        }
+        Landmarks { landmarks, forward, backward }
+    }
+
+    pub fn landmarks(&self) -> &[NodeId] {
+        &self.landmarks
+    }
+
+    pub fn num_landmarks(&self) -> usize {
+        self.landmarks.len()
+    }
+
+    /// Lower bound of the distance from `from` to `to`.
+    /// `None` if the landmark distances prove that `to` is not reachable from `from`.
+    pub fn lower_bound(&self, from: NodeId, to: NodeId) -> Option<Weight> {
+        let k = self.landmarks.len();
+        let (from, to) = (from as usize * k, to as usize * k);
+        let mut bound = 0;
+
+        for i in 0..k {
+            let (landmark_to_from, landmark_to_to) = (self.forward[from + i], self.forward[to + i]);
+            let (from_to_landmark, to_to_landmark) = (self.backward[from + i], self.backward[to + i]);
+
+            // otherwise, there would be paths from -> to -> landmark or landmark -> from -> to
+            if (from_to_landmark >= INFINITY && to_to_landmark < INFINITY) || (landmark_to_from < INFINITY && landmark_to_to >= INFINITY) {
+                return None;
+            }
+            if from_to_landmark < INFINITY && to_to_landmark < INFINITY {
+                bound = max(bound, from_to_landmark.saturating_sub(to_to_landmark));
+            }
+            if landmark_to_from < INFINITY && landmark_to_to < INFINITY {
+                bound = max(bound, landmark_to_to.saturating_sub(landmark_to_from));
+            }
+        }
+
+        Some(bound)
+    }
+}
+
+impl Deconstruct for Landmarks {
+    fn store_each(&self, store: &dyn Fn(&str, &dyn Store) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("landmarks", &self.landmarks)?;
+        store("landmark_forward_distances", &self.forward)?;
+        store("landmark_backward_distances", &self.backward)?;
+        Ok(())
+    }
+}
+
+impl Reconstruct for Landmarks {
+    fn reconstruct_with(loader: Loader) -> std::io::Result<Self> {
+        let landmarks: Vec<NodeId> = loader.load("landmarks")?;
+        let forward: Vec<Weight> = loader.load("landmark_forward_distances")?;
+        let backward: Vec<Weight> = loader.load("landmark_backward_distances")?;
+        let invalid_data = |msg| Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+        if forward.len() != backward.len() {
+            return invalid_data("landmark forward and backward distances differ in length");
+        }
+        // one distance per node and landmark
+        if landmarks.is_empty() || forward.is_empty() || forward.len() % landmarks.len() != 0 {
+            return invalid_data("landmark distances do not match the number of landmarks");
+        }
+        let num_nodes = forward.len() / landmarks.len();
+        if landmarks.iter().any(|&landmark| landmark as usize >= num_nodes) {
+            return invalid_data("landmark is not a node of the graph");
+        }
+        Ok(Landmarks { landmarks, forward, backward })
+    }
+}
+
+/// Farthest landmark selection.
+/// Each landmark is the node farthest away from the already selected ones, where a node is as far away from a set
+/// as the minimum over the distances from and to each node in the set.
+/// The first landmark is the node farthest from `start`.
+/// Nodes unreachable in both directions are never selected, so this might return less than `num_landmarks` landmarks.
+pub fn farthest_landmarks<F, H, W>(graph: &FirstOutGraph<F, H, W>, num_landmarks: usize, start: NodeId) -> Vec<NodeId>
+where
+    F: AsSlice<EdgeId>,
+    H: AsSlice<NodeId>,
+    W: AsSlice<Weight>,
+{
+    let reversed = OwnedGraph::reversed(graph);
+    let mut one_to_all = OneToAll::new(borrowed(graph), &reversed);
+    let mut set_distance = vec![INFINITY; graph.num_nodes()];
+    let mut landmarks = Vec::with_capacity(num_landmarks);
+    let mut next = start;
+
+    loop {
+        let (from_node, to_node) = one_to_all.distances(next);
+        for ((distance, from_node), to_node) in set_distance.iter_mut().zip(from_node).zip(to_node) {
+            *distance = std::cmp::min(*distance, std::cmp::min(from_node, to_node));
+        }
+
+        if landmarks.len() == num_landmarks {
+            break;
+        }
+        match set_distance
+            .iter()
+            .enumerate()
+            .filter(|&(_, &distance)| distance > 0 && distance < INFINITY)
+            .max_by_key(|&(_, &distance)| distance)
+        {
+            Some((node, _)) => next = node as NodeId,
+            None => break,
+        }
+        landmarks.push(next);
+        if landmarks.len() == 1 {
+            // start is not a landmark
+            set_distance.iter_mut().for_each(|distance| *distance = INFINITY);
+        }
+    }
+
+    landmarks
+}
+
+/// Avoid landmark selection (Goldberg and Werneck).
+/// For each new landmark, grow a shortest path tree from a random root and weigh each node by the difference of its distance
+/// to the root and the lower bound obtained from the landmarks selected so far.
+/// Subtrees containing a landmark are ignored.
+/// Starting at the root, descend into the child with the heaviest subtree until reaching a leaf, which becomes the next landmark.
+/// Might return less than `num_landmarks` landmarks if no more suitable nodes can be found.
+pub fn avoid_landmarks<F, H, W>(graph: &FirstOutGraph<F, H, W>, num_landmarks: usize, rng: &mut impl Rng) -> Vec<NodeId>
+where
+    F: AsSlice<EdgeId>,
+    H: AsSlice<NodeId>,
+    W: AsSlice<Weight>,
+{
+    let n = graph.num_nodes();
+    let reversed = OwnedGraph::reversed(graph);
+    let mut one_to_all = OneToAll::new(borrowed(graph), &reversed);
+
+    let mut landmarks = Vec::with_capacity(num_landmarks);
+    let mut columns: Vec<(Vec<Weight>, Vec<Weight>)> = Vec::with_capacity(num_landmarks);
+    let mut is_landmark = vec![false; n];
+    let mut subtree_size = vec![0u64; n];
+    let mut contains_landmark = vec![false; n];
+    let mut heaviest_child = vec![n as NodeId; n];
+    let mut settled = Vec::new();
+
+    let mut attempts = 0;
+    while landmarks.len() < num_landmarks && attempts < 4 * num_landmarks && n > 0 {
+        attempts += 1;
+        let root = rng.gen_range(0, n as NodeId);
+
+        let dijkstra = &mut one_to_all.forward;
+        dijkstra.initialize_query(Query { from: root, to: n as NodeId });
+        settled.clear();
+        for node in dijkstra.by_ref() {
+            settled.push(node);
+            subtree_size[node as usize] = 0;
+            contains_landmark[node as usize] = is_landmark[node as usize];
+            heaviest_child[node as usize] = n as NodeId;
+        }
+
+        // children are always settled after their parents
+        for &node in settled.iter().rev() {
+            let distance = *dijkstra.tentative_distance(node);
+            let lower_bound = columns
+                .iter()
+                .map(|(from_landmark, to_landmark)| {
+                    let (root, node) = (root as usize, node as usize);
+                    let mut bound = 0;
+                    if from_landmark[root] < INFINITY && from_landmark[node] < INFINITY {
+                        bound = max(bound, from_landmark[node].saturating_sub(from_landmark[root]));
+                    }
+                    if to_landmark[root] < INFINITY && to_landmark[node] < INFINITY {
+                        bound = max(bound, to_landmark[root].saturating_sub(to_landmark[node]));
+                    }
+                    bound
+                })
+                .max()
+                .unwrap_or(0);
+
+            subtree_size[node as usize] += u64::from(distance - std::cmp::min(lower_bound, distance));
+            if contains_landmark[node as usize] {
+                subtree_size[node as usize] = 0;
+            }
+
+            if node != root {
+                let parent = dijkstra.predecessor(node) as usize;
+                subtree_size[parent] += subtree_size[node as usize];
+                contains_landmark[parent] |= contains_landmark[node as usize];
+                let heaviest = heaviest_child[parent];
+                if subtree_size[node as usize] > 0 && (heaviest == n as NodeId || subtree_size[heaviest as usize] < subtree_size[node as usize]) {
+                    heaviest_child[parent] = node;
+                }
+            }
+        }
+
+        if heaviest_child[root as usize] == n as NodeId {
+            continue;
+        }
+        let mut landmark = root;
+        while heaviest_child[landmark as usize] != n as NodeId {
+            landmark = heaviest_child[landmark as usize];
+        }
+
+        is_landmark[landmark as usize] = true;
+        landmarks.push(landmark);
+        columns.push(one_to_all.distances(landmark));
+    }
+
+    landmarks
+}
+
+/// Planar landmark selection (Goldberg and Harrelson).
+/// The plane is divided into `num_landmarks` sectors of equal angle around the node closest to the geometric center.
+/// In each sector, the node farthest away from the center node is selected.
+/// Sectors without any node reachable from the center are skipped.
+pub fn planar_landmarks<F, H, W>(graph: &FirstOutGraph<F, H, W>, num_landmarks: usize, lat: &[f32], lng: &[f32]) -> Vec<NodeId>
+where
+    F: AsSlice<EdgeId>,
+    H: AsSlice<NodeId>,
+    W: AsSlice<Weight>,
+{
+    let n = graph.num_nodes();
+    if n == 0 || num_landmarks == 0 {
+        return Vec::new();
+    }
+
+    let center_lat = lat.iter().map(|&lat| f64::from(lat)).sum::<f64>() / n as f64;
+    let center_lng = lng.iter().map(|&lng| f64::from(lng)).sum::<f64>() / n as f64;
+    let squared_distance = |node: usize| (f64::from(lat[node]) - center_lat).powi(2) + (f64::from(lng[node]) - center_lng).powi(2);
+    let center = (0..n).min_by(|&a, &b| squared_distance(a).total_cmp(&squared_distance(b))).unwrap();
+
+    let mut dijkstra = StandardDijkstra::new(borrowed(graph));
+    let distances = OneToAll::run(&mut dijkstra, center as NodeId);
+
+    let mut farthest: Vec<Option<(Weight, NodeId)>> = vec![None; num_landmarks];
+    for node in (0..n).filter(|&node| node != center && distances[node] < INFINITY) {
+        let angle = (f64::from(lat[node]) - f64::from(lat[center])).atan2(f64::from(lng[node]) - f64::from(lng[center]));
+        let sector = std::cmp::min(
+            ((angle + std::f64::consts::PI) / (2.0 * std::f64::consts::PI) * num_landmarks as f64) as usize,
+            num_landmarks - 1,
+        );
+        if farthest[sector].map(|(distance, _)| distance < distances[node]).unwrap_or(true) {
+            farthest[sector] = Some((distances[node], node as NodeId));
+        }
+    }
+
+    farthest.into_iter().flatten().map(|(_, node)| node).collect()
+}
+
+/// ALT lower bounds as a potential for (unidirectional) A*.
+#[derive(Debug)]
+pub struct ALTPotential<'a> {
+    landmarks: &'a Landmarks,
+    target: NodeId,
+    num_pot_evals: usize,
+}
+
+impl<'a> ALTPotential<'a> {
+    pub fn new(landmarks: &'a Landmarks) -> Self {
+        ALTPotential {
+            landmarks,
+            target: 0,
+            num_pot_evals: 0,
+        }
+    }
+}
+
+impl<'a> Potential for ALTPotential<'a> {
+    fn init(&mut self, target: NodeId) {
+        self.target = target;
+        self.num_pot_evals = 0;
+    }
+
+    fn potential(&mut self, node: NodeId) -> Option<Weight> {
+        self.num_pot_evals += 1;
+        self.landmarks.lower_bound(node, self.target)
+    }
+
+    fn num_pot_evals(&self) -> usize {
+        self.num_pot_evals
+    }
+}
+
+/// Distance label for bidirectional ALT.
+/// The queue key is the doubled distance, so the average of two potentials can be added without rounding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DoubledKeyWeight(pub Weight);
+
+impl Label for DoubledKeyWeight {
+    type Key = u64;
+
+    fn neutral() -> Self {
+        DoubledKeyWeight(INFINITY)
+    }
+
+    #[inline(always)]
+    fn key(&self) -> Self::Key {
+        2 * u64::from(self.0)
+    }
+}
+
+impl GenQuery<DoubledKeyWeight> for Query {
+    fn from(&self) -> NodeId {
+        self.from
+    }
+    fn to(&self) -> NodeId {
+        self.to
+    }
+    fn initial_state(&self) -> DoubledKeyWeight {
+        DoubledKeyWeight(0)
+    }
+    fn permutate(&mut self, order: &NodeOrder) {
+        self.from = order.rank(self.from);
+        self.to = order.rank(self.to);
+    }
+}
+
+#[derive(Default)]
+pub struct DoubledKeyOps();
+
+impl<G> DijkstraOps<G> for DoubledKeyOps {
+    type Label = DoubledKeyWeight;
+    type Arc = Link;
+    type LinkResult = Weight;
+
+    #[inline(always)]
+    fn link(&mut self, _graph: &G, label: &DoubledKeyWeight, link: &Link) -> Self::LinkResult {
+        label.0 + link.weight
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, label: &mut DoubledKeyWeight, linked: Self::LinkResult) -> bool {
+        if linked < label.0 {
+            label.0 = linked;
+            return true;
+        }
+        false
+    }
+}
+
+/// Bidirectional A* with ALT lower bounds.
+/// Uses the average potentials `(pi_t(v) - pi_s(v)) / 2` for the forward and `(pi_s(v) - pi_t(v)) / 2` for the backward search,
+/// where `pi_t` are lower bounds to the target and `pi_s` lower bounds from the source.
+/// These are consistent, so the search may stop as soon as the sum of the minimum keys of both queues exceeds the tentative distance.
+pub struct Server<'a, G: for<'b> LinkIterGraph<'b>, H: for<'b> LinkIterGraph<'b>> {
+    forward_dijkstra: GenericDijkstra<DoubledKeyOps, G>,
+    backward_dijkstra: GenericDijkstra<DoubledKeyOps, H>,
+    landmarks: &'a Landmarks,
+    tentative_distance: Weight,
+    meeting_node: NodeId,
+}
+
+impl<'a, G: for<'b> LinkIterGraph<'b>> Server<'a, G, OwnedGraph> {
+    pub fn new(graph: G, landmarks: &'a Landmarks) -> Self {
+        let reversed = OwnedGraph::reversed(&graph);
+
+        Server {
+            forward_dijkstra: GenericDijkstra::new(graph),
+            backward_dijkstra: GenericDijkstra::new(reversed),
+            landmarks,
+            tentative_distance: INFINITY,
+            meeting_node: 0,
+        }
+    }
+}
+
+impl<'a, G: for<'b> LinkIterGraph<'b>, H: for<'b> LinkIterGraph<'b>> Server<'a, G, H> {
+    fn distance(&mut self, from: NodeId, to: NodeId) -> Option<Weight> {
+        self.tentative_distance = INFINITY;
+        let landmarks = self.landmarks;
+
+        // Doubled average potentials, shifted by INFINITY to keep them positive.
+        // The shift is the same for all nodes and does not affect the order of the queues.
+        let offset = u64::from(INFINITY);
+        let forward_potential = |node| {
+            let (to_target, from_source) = (landmarks.lower_bound(node, to)?, landmarks.lower_bound(from, node)?);
+            Some(offset + u64::from(to_target) - u64::from(from_source))
+        };
+        let backward_potential = |node| {
+            let (to_target, from_source) = (landmarks.lower_bound(node, to)?, landmarks.lower_bound(from, node)?);
+            Some(offset + u64::from(from_source) - u64::from(to_target))
+        };
+
+        forward_potential(from)?;
+
+        self.forward_dijkstra.initialize_query(Query { from, to });
+        self.backward_dijkstra.initialize_query(Query { from: to, to: from });
+
+        while let (Some(forward), Some(backward)) = (self.forward_dijkstra.queue().peek(), self.backward_dijkstra.queue().peek()) {
+            let (forward_min, backward_min) = (forward.key, backward.key);
+            if forward_min + backward_min >= 2 * (u64::from(self.tentative_distance) + offset) {
+                break;
+            }
+
+            if forward_min <= backward_min {
+                let node = self.forward_dijkstra.next_step_with_potential(forward_potential).unwrap();
+                let distance = self.forward_dijkstra.tentative_distance(node).0 + self.backward_dijkstra.tentative_distance(node).0;
+                if distance < self.tentative_distance {
+                    self.tentative_distance = distance;
+                    self.meeting_node = node;
+                }
+            } else {
+                let node = self.backward_dijkstra.next_step_with_potential(backward_potential).unwrap();
+                let distance = self.forward_dijkstra.tentative_distance(node).0 + self.backward_dijkstra.tentative_distance(node).0;
+                if distance < self.tentative_distance {
+                    self.tentative_distance = distance;
+                    self.meeting_node = node;
+                }
+            }
+        }
+
+        match self.tentative_distance {
+            INFINITY => None,
+            dist => Some(dist),
+        }
+    }
+
+    fn path(&self, query: Query) -> Vec<NodeId> {
+        let mut path = Vec::new();
+        path.push(self.meeting_node);
+
+        while *path.last().unwrap() != query.from {
+            let next = self.forward_dijkstra.predecessor(*path.last().unwrap());
+            path.push(next);
+        }
+
+        path.reverse();
+
+        while *path.last().unwrap() != query.to {
+            let next = self.backward_dijkstra.predecessor(*path.last().unwrap());
+            path.push(next);
+        }
+
+        path
+    }
+}
+
+pub struct PathServerWrapper<'s, 'a, G: for<'b> LinkIterGraph<'b>, H: for<'b> LinkIterGraph<'b>>(&'s Server<'a, G, H>, Query);
+
+impl<'s, 'a, G: for<'b> LinkIterGraph<'b>, H: for<'b> LinkIterGraph<'b>> PathServer for PathServerWrapper<'s, 'a, G, H> {
+    type NodeInfo = NodeId;
+
+    fn path(&mut self) -> Vec<Self::NodeInfo> {
+        Server::path(self.0, self.1)
+    }
+}
+
+impl<'s, 'a: 's, G: 's + for<'b> LinkIterGraph<'b>, H: 's + for<'b> LinkIterGraph<'b>> QueryServer<'s> for Server<'a, G, H> {
+    type P = PathServerWrapper<'s, 'a, G, H>;
+
+    fn query(&'s mut self, query: Query) -> Option<QueryResult<'s, Self::P, Weight>> {
+        self.distance(query.from, query.to)
+            .map(move |distance| QueryResult::new(distance, PathServerWrapper(self, query)))
+    }
+}
@@ -0,0 +1,56 @@
+//! Many-to-many and one-to-many distance tables based on upward dijkstra searches.
+
+use super::*;
+use crate::algo::many_to_many::*;
+
+type SearchGraph<'s> = FirstOutGraph<&'s [EdgeId], &'s [NodeId], &'s [Weight]>;
+
+impl<Graph: for<'a> LinkIterGraph<'a>> UpwardSearch for StandardDijkstra<Graph> {
+    fn search(&mut self, from: NodeId, mut settled: impl FnMut(NodeId, Weight)) {
+        self.initialize_query(Query { from, to: from });
+        while let Some(node) = self.next() {
+            settled(node, *self.tentative_distance(node));
+        }
+    }
+}
+
+/// Server for distance tables on a CH.
+pub struct Server {
+    forward: OwnedGraph,
+    backward: OwnedGraph,
+    order: NodeOrder,
+}
+
+impl Server {
+    pub fn new(ch: ContractionHierarchy, order: NodeOrder) -> Server {
+        Server {
+            forward: ch.forward,
+            backward: ch.backward,
+            order,
+        }
+    }
+
+    /// Distances from all `sources` to all `targets` as a dense row major matrix with one row per source.
+    /// Unreachable pairs have distance `INFINITY`.
+    pub fn many_to_many(&self, sources: &[NodeId], targets: &[NodeId]) -> Vec<Weight> {
+        let sources: Vec<NodeId> = sources.iter().map(|&node| self.order.rank(node)).collect();
+        let targets: Vec<NodeId> = targets.iter().map(|&node| self.order.rank(node)).collect();
+
+        bucket_many_to_many(
+            self.forward.num_nodes(),
+            &sources,
+            &targets,
+            || StandardDijkstra::new(borrow_graph(&self.forward)),
+            || StandardDijkstra::new(borrow_graph(&self.backward)),
+        )
+    }
+
+    /// Distances from `source` to all `targets`, `INFINITY` for unreachable targets.
+    pub fn one_to_many(&self, source: NodeId, targets: &[NodeId]) -> Vec<Weight> {
+        self.many_to_many(&[source], targets)
+    }
+}
+
+fn borrow_graph(graph: &OwnedGraph) -> SearchGraph<'_> {
+    FirstOutGraph::new(graph.first_out(), graph.head(), graph.weight())
+}
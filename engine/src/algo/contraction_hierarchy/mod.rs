@@ -1,13 +1,17 @@
 //! Experimental prototype implementation of Contraction Hierarchies in rust.
 //!
 //! Not tuned for performance yet.
-//! No node ordering implemented yet, depends on getting a precalculated order.
+//! The order can either be precalculated or determined with `order_and_contract`.
 
 use super::*;
 use crate::algo::dijkstra::generic_dijkstra::*;
 use crate::datastr::node_order::NodeOrder;
 
+pub mod many_to_many;
+mod ordering;
+pub mod phast;
 pub mod query;
+pub use ordering::order_and_contract;
 
 /// Struct for a Contraction Hierarchy, that is the completely preprocessed
 /// graph augmented by shortcuts and split in an upwards and downward part,
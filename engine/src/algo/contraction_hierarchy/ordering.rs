@@ -0,0 +1,205 @@
+//! Bottom-up node ordering for Contraction Hierarchies.
+//!
+//! Nodes are kept in a priority queue and the node with the lowest priority is contracted next.
+//! The priority is a linear combination of the edge difference (number of shortcuts required minus the number of removed edges),
+//! the number of already contracted neighbors and the depth of the search space below the node.
+//! Priorities are updated for all neighbors after each contraction and additionally recalculated lazily when a node is popped from the queue.
+//! The shortcuts are inserted while ordering, so we get the complete CH as a by-product.
+
+use super::*;
+use crate::algo::dijkstra::State;
+use crate::datastr::index_heap::*;
+use std::mem::take;
+
+const EDGE_DIFFERENCE_WEIGHT: i64 = 2;
+const DELETED_NEIGHBORS_WEIGHT: i64 = 1;
+const SEARCH_SPACE_DEPTH_WEIGHT: i64 = 1;
+
+/// Calculate a node order and perform CH preprocessing in one go.
+pub fn order_and_contract<Graph: for<'a> LinkIterGraph<'a>>(graph: &Graph) -> (NodeOrder, ContractionHierarchy) {
+    let n = graph.num_nodes();
+    let mut contraction_graph = ContractionGraph::new(graph, NodeOrder::identity(n));
+
+    let order = {
+        let mut ordering = Ordering::new(contraction_graph.partial_graph());
+
+        let mut queue = IndexdMinHeap::new(n);
+        for node in 0..n as NodeId {
+            queue.push(State {
+                key: ordering.priority(node),
+                node,
+            });
+        }
+
+        let mut order = Vec::with_capacity(n);
+        let mut neighbors = Vec::new();
+
+        while let Some(State { node, .. }) = queue.pop() {
+            // lazy update: the priority might have become outdated, so reevaluate it
+            // and put the node back if it is no longer the minimum
+            let priority = ordering.priority(node);
+            if let Some(&State { key: next_priority, .. }) = queue.peek() {
+                if priority > next_priority {
+                    queue.push(State { key: priority, node });
+                    continue;
+                }
+            }
+
+            ordering.contract(node);
+            order.push(node);
+
+            neighbors.clear();
+            neighbors.extend(
+                ordering.graph.nodes[node as usize]
+                    .outgoing
+                    .iter()
+                    .chain(ordering.graph.nodes[node as usize].incoming.iter())
+                    .map(|&(Link { node, .. }, _)| node),
+            );
+            neighbors.sort_unstable();
+            neighbors.dedup();
+
+            for &neighbor in &neighbors {
+                ordering.deleted_neighbors[neighbor as usize] += 1;
+                ordering.depth[neighbor as usize] = std::cmp::max(ordering.depth[neighbor as usize], ordering.depth[node as usize] + 1);
+            }
+            for &neighbor in &neighbors {
+                let key = ordering.priority(neighbor);
+                let current = queue.get(neighbor as usize).unwrap().key;
+                if key < current {
+                    queue.decrease_key(State { key, node: neighbor });
+                } else if key > current {
+                    queue.increase_key(State { key, node: neighbor });
+                }
+            }
+        }
+
+        NodeOrder::from_node_order(order)
+    };
+
+    // After the ordering, every node still holds exactly its links to higher ranked nodes.
+    // We only need to translate everything into rank space to build the CH.
+    let mut nodes = contraction_graph.nodes;
+    let to_rank = |(Link { node, weight }, middle): (Link, NodeId)| {
+        (
+            Link {
+                node: order.rank(node),
+                weight,
+            },
+            if middle == n as NodeId { middle } else { order.rank(middle) },
+        )
+    };
+    let nodes = order
+        .order()
+        .iter()
+        .map(|&node| {
+            let node = &mut nodes[node as usize];
+            Node {
+                outgoing: take(&mut node.outgoing).into_iter().map(to_rank).collect(),
+                incoming: take(&mut node.incoming).into_iter().map(to_rank).collect(),
+            }
+        })
+        .collect();
+
+    let ch = ContractionGraph { nodes, order: order.clone() }.into_first_out_graphs();
+    (order, ch)
+}
+
+// State of the ordering process.
+// The graph always contains all nodes with their original ids (no offset).
+// Contracted nodes are detached from the remaining graph but keep their links to their (higher ranked) neighbors.
+// Since no remaining node links to them, witness searches can never reach them.
+struct Ordering<'a> {
+    graph: PartialContractionGraph<'a>,
+    recycled: Option<(Trash<Weight>, Trash<Weight>)>,
+    deleted_neighbors: Vec<i64>,
+    depth: Vec<i64>,
+}
+
+impl<'a> Ordering<'a> {
+    fn new(graph: PartialContractionGraph<'a>) -> Self {
+        debug_assert_eq!(graph.id_offset, 0);
+        let n = graph.nodes.len();
+        let recycled = Some((
+            StandardDijkstra::new(ForwardWrapper { graph: &graph }).recycle(),
+            StandardDijkstra::new(BackwardWrapper { graph: &graph }).recycle(),
+        ));
+        Ordering {
+            graph,
+            recycled,
+            deleted_neighbors: vec![0; n],
+            depth: vec![0; n],
+        }
+    }
+
+    // simulate the contraction of a node and calculate its priority
+    fn priority(&mut self, node: NodeId) -> i64 {
+        let links = self.graph.disconnect(node);
+
+        let mut shortcut_count = 0;
+        for &(Link { node: from, weight: from_wght }, _) in &links.incoming {
+            for &(Link { node: to, weight: to_wght }, _) in &links.outgoing {
+                if self.shortcut_required(from, to, from_wght + to_wght) {
+                    shortcut_count += 1;
+                }
+            }
+        }
+        let edge_difference = shortcut_count - (links.incoming.len() + links.outgoing.len()) as i64;
+
+        self.graph.reconnect(node, links);
+
+        EDGE_DIFFERENCE_WEIGHT * edge_difference
+            + DELETED_NEIGHBORS_WEIGHT * self.deleted_neighbors[node as usize]
+            + SEARCH_SPACE_DEPTH_WEIGHT * self.depth[node as usize]
+    }
+
+    // actually contract a node - insert all required shortcuts and detach it from the remaining graph
+    fn contract(&mut self, node: NodeId) {
+        let links = self.graph.disconnect(node);
+
+        for &(Link { node: from, weight: from_wght }, _) in &links.incoming {
+            for &(Link { node: to, weight: to_wght }, _) in &links.outgoing {
+                if self.shortcut_required(from, to, from_wght + to_wght) {
+                    self.graph.insert_or_decrease(from, to, from_wght + to_wght, node);
+                }
+            }
+        }
+
+        // keep the links to the remaining nodes, these will be the upward and downward edges of the CH
+        self.graph.nodes[node as usize] = links;
+    }
+
+    fn shortcut_required(&mut self, from: NodeId, to: NodeId, shortcut_weight: Weight) -> bool {
+        let (required, recycled) = self.graph.shortcut_required(from, to, shortcut_weight, self.recycled.take().unwrap());
+        self.recycled = Some(recycled);
+        required
+    }
+}
+
+impl<'a> PartialContractionGraph<'a> {
+    // remove all links of a node and the links of other nodes to it
+    fn disconnect(&mut self, node: NodeId) -> Node {
+        let links = Node {
+            outgoing: take(&mut self.nodes[node as usize].outgoing),
+            incoming: take(&mut self.nodes[node as usize].incoming),
+        };
+        for &(Link { node: from, .. }, _) in &links.incoming {
+            self.nodes[from as usize].remove_outgoing(node);
+        }
+        for &(Link { node: to, .. }, _) in &links.outgoing {
+            self.nodes[to as usize].remove_incmoing(node);
+        }
+        links
+    }
+
+    // undo `disconnect`
+    fn reconnect(&mut self, node: NodeId, links: Node) {
+        for &(Link { node: from, weight }, middle) in &links.incoming {
+            self.nodes[from as usize].outgoing.push((Link { node, weight }, middle));
+        }
+        for &(Link { node: to, weight }, middle) in &links.outgoing {
+            self.nodes[to as usize].incoming.push((Link { node, weight }, middle));
+        }
+        self.nodes[node as usize] = links;
+    }
+}
@@ -0,0 +1,11 @@
+//! PHAST and RPHAST with dijkstra upward searches on a CH.
+
+use super::*;
+
+pub type Server = crate::algo::phast::Server<StandardDijkstra<OwnedGraph>, OwnedGraph, NodeOrder>;
+
+impl Server {
+    pub fn new(ch: ContractionHierarchy, order: NodeOrder) -> Self {
+        Self::from_parts(StandardDijkstra::new(ch.forward), ch.backward, order)
+    }
+}
@@ -5,10 +5,14 @@ use std::cell::RefCell;
 mod parallelization;
 use parallelization::*;
 pub mod ftd;
+mod partial;
+pub use partial::*;
 
 // One mapping of node id to weight for each thread during the scope of the customization.
 scoped_thread_local!(static UPWARD_WORKSPACE: RefCell<Vec<Weight>>);
 scoped_thread_local!(static DOWNWARD_WORKSPACE: RefCell<Vec<Weight>>);
+// Mapping of head node to the edge id of the current node for perfect customization, one for both directions.
+scoped_thread_local!(static PERFECT_WORKSPACE: RefCell<Vec<InRangeOption<EdgeId>>>);
 
 /// Execute second phase, that is metric dependent preprocessing.
 /// `metric` has to have the same topology as the original graph used for first phase preprocessing.
@@ -32,6 +36,20 @@ where
     customize_basic(cch, upward_weights, downward_weights)
 }
 
+/// Execute basic and then perfect customization.
+/// After perfect customization, the weight of each edge is the shortest distance between its endpoints
+/// and no longer only the shortest distance over lower ranked nodes.
+/// Arcs whose weight changed during the perfect customization are never part of shortest paths,
+/// see `CCH::into_perfect_directed_cch` to get rid of them.
+pub fn customize_perfect<'c, Graph>(cch: &'c CCH, metric: &Graph) -> Customized<'c, CCH>
+where
+    Graph: for<'a> LinkIterGraph<'a> + RandomLinkAccessGraph + Sync,
+{
+    let mut customized = customize(cch, metric);
+    perfect_customization(cch, &mut customized.upward, &mut customized.downward);
+    customized
+}
+
 /// Same as [customize], except with a `DirectedCCH`
 pub fn customize_directed<'c, Graph>(cch: &'c DirectedCCH, metric: &Graph) -> Customized<'c, DirectedCCH>
 where
@@ -240,6 +258,64 @@ fn customize_basic(cch: &CCH, mut upward_weights: Vec<Weight>, mut downward_weig
     }
 }
 
+// Relax upper and intermediate triangles of basic customized weights, processing nodes top-down.
+// Same as the perfect precustomization of CATCHUp, just with static weights.
+pub fn perfect_customization(cch: &CCH, upward_weights: &mut [Weight], downward_weights: &mut [Weight]) {
+    let n = cch.num_nodes();
+
+    // The pointers are necessary because the triangles of a node reach into the edges of higher nodes,
+    // see `SeperatorBasedPerfectParallelCustomization`.
+    let customize_perfect = |nodes: Range<usize>, upward: *mut Weight, downward: *mut Weight| {
+        PERFECT_WORKSPACE.with(|node_edge_ids| {
+            let mut node_edge_ids = node_edge_ids.borrow_mut();
+
+            // processing nodes in reverse order
+            for current_node in nodes.rev() {
+                let current_node = current_node as NodeId;
+                // store mapping of head node to corresponding outgoing edge id
+                for (node, edge_id) in cch.neighbor_iter(current_node).zip(cch.neighbor_edge_indices(current_node)) {
+                    node_edge_ids[node as usize] = InRangeOption::new(Some(edge_id));
+                }
+
+                for (node, edge_id) in cch.neighbor_iter(current_node).zip(cch.neighbor_edge_indices(current_node)) {
+                    for (target, shortcut_edge_id) in cch.neighbor_iter(node).zip(cch.neighbor_edge_indices(node)) {
+                        if let Some(other_edge_id) = node_edge_ids[target as usize].value() {
+                            // Here we have both an intermediate and an upper triangle
+                            // depending on which edge we take as the base
+                            // Relax all them.
+                            unsafe {
+                                let relax = |weights: *mut Weight, edge_id: EdgeId, first: Weight, second: Weight| {
+                                    *weights.add(edge_id as usize) = std::cmp::min(*weights.add(edge_id as usize), first + second);
+                                };
+                                let up = |edge_id: EdgeId| *upward.add(edge_id as usize);
+                                let down = |edge_id: EdgeId| *downward.add(edge_id as usize);
+
+                                relax(upward, other_edge_id, up(edge_id), up(shortcut_edge_id));
+                                relax(upward, edge_id, up(other_edge_id), down(shortcut_edge_id));
+                                relax(downward, other_edge_id, down(edge_id), down(shortcut_edge_id));
+                                relax(downward, edge_id, down(other_edge_id), up(shortcut_edge_id));
+                            }
+                        }
+                    }
+                }
+
+                // reset the mapping
+                for node in cch.neighbor_iter(current_node) {
+                    node_edge_ids[node as usize] = InRangeOption::new(None);
+                }
+            }
+        });
+    };
+
+    let customization = SeperatorBasedPerfectParallelCustomization::new(cch, customize_perfect, customize_perfect);
+
+    report_time_with_key("CCH Perfect Customization", "perfect_customization", || {
+        customization.customize(upward_weights, downward_weights, |cb| {
+            PERFECT_WORKSPACE.set(&RefCell::new(vec![InRangeOption::new(None); n]), cb);
+        });
+    });
+}
+
 fn customize_directed_basic(cch: &DirectedCCH, mut upward_weights: Vec<Weight>, mut downward_weights: Vec<Weight>) -> Customized<DirectedCCH> {
     let n = cch.num_nodes() as NodeId;
 
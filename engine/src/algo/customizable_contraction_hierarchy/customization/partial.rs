@@ -0,0 +1,153 @@
+//! Partial customization for small metric updates.
+//!
+//! Instead of running the complete customization again, only the CCH edges which may be affected by the changed arcs are recomputed.
+//! These are the edges the changed arcs are mapped to and, transitively, all edges where a changed edge is part of a lower triangle.
+//! Edges are processed by increasing lower node (which is also increasing edge id),
+//! so all lower triangles of an edge are final when its weights get recomputed.
+
+use super::*;
+use crate::report::*;
+use std::collections::BTreeSet;
+
+/// Statistics on the work done by `customize_partial`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialCustomizationStats {
+    /// Too many arcs changed, so the metric was customized from scratch.
+    pub full_customization: bool,
+    /// Number of CCH edges whose weights were recomputed.
+    pub touched_shortcuts: usize,
+    /// Number of CCH edges where the upward or downward weight actually changed.
+    pub changed_shortcuts: usize,
+}
+
+/// Update `customized` after the weights of `changed_arcs` in `metric` changed.
+/// `metric` has to contain the complete new metric, with the same topology as the original graph used for first phase preprocessing.
+/// When more than `max_changed_arcs` arcs changed, a full customization will be run instead.
+/// The result is the same as `customize(cch, metric)`.
+pub fn customize_partial<Graph>(customized: &mut Customized<CCH>, metric: &Graph, changed_arcs: &[EdgeId], max_changed_arcs: usize) -> PartialCustomizationStats
+where
+    Graph: for<'a> LinkIterGraph<'a> + RandomLinkAccessGraph + Sync,
+{
+    let cch = customized.cch;
+
+    if changed_arcs.len() > max_changed_arcs {
+        let updated = customize(cch, metric);
+        let changed_shortcuts = updated
+            .upward
+            .iter()
+            .zip(updated.downward.iter())
+            .zip(customized.upward.iter().zip(customized.downward.iter()))
+            .filter(|(new, old)| new.0 != old.0 || new.1 != old.1)
+            .count();
+        *customized = updated;
+
+        return PartialCustomizationStats {
+            full_customization: true,
+            touched_shortcuts: cch.num_arcs(),
+            changed_shortcuts,
+        };
+    }
+
+    let (touched_shortcuts, changed_shortcuts) = report_time_with_key("CCH Partial Customization", "partial_customization", || {
+        // ordered by edge id, so we always continue with the edge with the lowest lower node
+        let mut queue = BTreeSet::new();
+        for &arc in changed_arcs {
+            let from = cch.node_order.rank(arc_tail(metric, arc));
+            let to = cch.node_order.rank(metric.link(arc).node);
+            if let Some(edge) = cch.edge_id(std::cmp::min(from, to), std::cmp::max(from, to)) {
+                queue.insert(edge);
+            }
+        }
+
+        let mut touched_shortcuts = 0;
+        let mut changed_shortcuts = 0;
+
+        while let Some(edge) = queue.iter().next().cloned() {
+            queue.remove(&edge);
+            touched_shortcuts += 1;
+
+            let (upward_weight, downward_weight) = recompute_edge(cch, &customized.upward, &customized.downward, metric, edge);
+            if upward_weight == customized.upward[edge as usize] && downward_weight == customized.downward[edge as usize] {
+                continue;
+            }
+            customized.upward[edge as usize] = upward_weight;
+            customized.downward[edge as usize] = downward_weight;
+            changed_shortcuts += 1;
+
+            // the edge is a lower edge of the triangles with all other upward neighbors of its tail
+            let low_node = cch.tail[edge as usize];
+            let high_node = cch.head[edge as usize];
+            for other in cch.neighbor_iter(low_node).filter(|&other| other != high_node) {
+                let affected = cch
+                    .edge_id(std::cmp::min(other, high_node), std::cmp::max(other, high_node))
+                    .expect("upward neighbors of a node in the chordal supergraph are adjacent");
+                queue.insert(affected);
+            }
+        }
+
+        (touched_shortcuts, changed_shortcuts)
+    });
+
+    report!("num_touched_shortcuts", touched_shortcuts);
+    report!("num_changed_shortcuts", changed_shortcuts);
+
+    PartialCustomizationStats {
+        full_customization: false,
+        touched_shortcuts,
+        changed_shortcuts,
+    }
+}
+
+// Compute the weights of an edge from scratch, that is the minimum of the original arc weights and all lower triangles.
+fn recompute_edge<Graph: RandomLinkAccessGraph>(cch: &CCH, upward: &[Weight], downward: &[Weight], metric: &Graph, edge: EdgeId) -> (Weight, Weight) {
+    let (up_arc, down_arc) = cch.cch_edge_to_orig_arc[edge as usize];
+    let mut upward_weight = up_arc.value().map(|arc| metric.link(arc).weight).unwrap_or(INFINITY);
+    let mut downward_weight = down_arc.value().map(|arc| metric.link(arc).weight).unwrap_or(INFINITY);
+
+    // coordinated linear sweep over the (ascending) downward neighborhoods of both end nodes
+    let mut low_iter = LinkIterable::<Link>::link_iter(&cch.inverted, cch.tail[edge as usize]).peekable();
+    let mut high_iter = LinkIterable::<Link>::link_iter(&cch.inverted, cch.head[edge as usize]).peekable();
+
+    while let (
+        Some(&Link {
+            node: low_lower,
+            weight: low_edge,
+        }),
+        Some(&Link {
+            node: high_lower,
+            weight: high_edge,
+        }),
+    ) = (low_iter.peek(), high_iter.peek())
+    {
+        match low_lower.cmp(&high_lower) {
+            Ordering::Less => {
+                low_iter.next();
+            }
+            Ordering::Greater => {
+                high_iter.next();
+            }
+            Ordering::Equal => {
+                upward_weight = std::cmp::min(upward_weight, downward[low_edge as usize] + upward[high_edge as usize]);
+                downward_weight = std::cmp::min(downward_weight, upward[low_edge as usize] + downward[high_edge as usize]);
+                low_iter.next();
+                high_iter.next();
+            }
+        }
+    }
+
+    (upward_weight, downward_weight)
+}
+
+// The last node whose outgoing arcs start at or before `arc` is its tail.
+fn arc_tail<Graph: RandomLinkAccessGraph>(graph: &Graph, arc: EdgeId) -> NodeId {
+    let (mut low, mut high) = (0, graph.num_nodes() as NodeId);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if graph.neighbor_edge_indices(mid).start <= arc {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
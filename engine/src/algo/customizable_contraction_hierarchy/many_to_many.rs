@@ -0,0 +1,61 @@
+//! Many-to-many and one-to-many distance tables based on elimination tree upward searches.
+
+use super::query::stepped_elimination_tree::SteppedEliminationTree;
+use super::*;
+use crate::algo::many_to_many::*;
+
+type SearchGraph<'s> = FirstOutGraph<&'s [EdgeId], &'s [NodeId], &'s [Weight]>;
+
+impl<'b, Graph: for<'a> LinkIterGraph<'a>> UpwardSearch for SteppedEliminationTree<'b, Graph> {
+    fn search(&mut self, from: NodeId, mut settled: impl FnMut(NodeId, Weight)) {
+        self.initialize_query(from);
+        while let QueryProgress::Settled(State { key, node }) = self.next_step() {
+            // nodes on the path to the root are not necessarily reachable
+            if key < INFINITY {
+                settled(node, key);
+            }
+        }
+    }
+}
+
+/// Server for distance tables on a customized CCH.
+#[derive(Debug)]
+pub struct Server<'a, CCH> {
+    forward: FirstOutGraph<&'a [EdgeId], &'a [NodeId], Vec<Weight>>,
+    backward: FirstOutGraph<&'a [EdgeId], &'a [NodeId], Vec<Weight>>,
+    cch: &'a CCH,
+}
+
+impl<'a, CCH: CCHT + Sync> Server<'a, CCH> {
+    pub fn new(customized: Customized<'a, CCH>) -> Self {
+        let cch = customized.cch;
+        let (forward, backward) = customized.into_ch_graphs();
+        Server { forward, backward, cch }
+    }
+
+    /// Distances from all `sources` to all `targets` as a dense row major matrix with one row per source.
+    /// Unreachable pairs have distance `INFINITY`.
+    pub fn many_to_many(&self, sources: &[NodeId], targets: &[NodeId]) -> Vec<Weight> {
+        let order = self.cch.node_order();
+        let sources: Vec<NodeId> = sources.iter().map(|&node| order.rank(node)).collect();
+        let targets: Vec<NodeId> = targets.iter().map(|&node| order.rank(node)).collect();
+        let elimination_tree = self.cch.elimination_tree();
+
+        bucket_many_to_many(
+            self.forward.num_nodes(),
+            &sources,
+            &targets,
+            || SteppedEliminationTree::new(borrow_graph(&self.forward), elimination_tree),
+            || SteppedEliminationTree::new(borrow_graph(&self.backward), elimination_tree),
+        )
+    }
+
+    /// Distances from `source` to all `targets`, `INFINITY` for unreachable targets.
+    pub fn one_to_many(&self, source: NodeId, targets: &[NodeId]) -> Vec<Weight> {
+        self.many_to_many(&[source], targets)
+    }
+}
+
+fn borrow_graph<'s>(graph: &'s FirstOutGraph<&[EdgeId], &[NodeId], Vec<Weight>>) -> SearchGraph<'s> {
+    FirstOutGraph::new(graph.first_out(), graph.head(), graph.weight())
+}
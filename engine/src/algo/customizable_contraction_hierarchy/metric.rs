@@ -0,0 +1,304 @@
+//! Metrics blended from several arc attributes.
+//!
+//! A metric is a weighted sum of attribute files (e.g. `travel_time`, `geo_distance`) plus penalties
+//! for arcs with certain `arc_category` bits, where an `INFINITY` penalty blocks the arcs completely.
+//! Metrics can be parsed from and formatted to expressions like `travel_time + 0.5*geo_distance + penalty(4, 60000) + block(2)`.
+//! The formatted expression of the canonical form (terms and penalties sorted and merged) is used as key to cache customizations.
+//! Factors have to be non-negative, so no arc gets a weight below zero.
+
+use super::*;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt,
+    io::{ErrorKind, Result},
+    path::Path,
+    str::FromStr,
+};
+
+/// `arc_category` bit of tunnels (as exported by RoutingKit)
+pub const TUNNEL_BIT: u8 = 1;
+/// `arc_category` bit of freeways (as exported by RoutingKit)
+pub const FREEWAY_BIT: u8 = 2;
+
+/// Builder for a linear combination of attributes with category penalties.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MetricBuilder {
+    // attribute name and factor
+    terms: Vec<(String, f64)>,
+    // arcs with any of the bits of the mask set get the penalty
+    penalties: Vec<(u8, Weight)>,
+}
+
+impl MetricBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add `factor` times the weights in the attribute file `attribute`.
+    /// Panics if `factor` is negative or not finite.
+    pub fn term(mut self, attribute: &str, factor: f64) -> Self {
+        assert!(valid_factor(factor), "metric factors have to be non-negative");
+        self.terms.push((attribute.to_string(), factor));
+        self
+    }
+
+    /// Add `penalty` to all arcs with any of the bits in `category_mask` set in their `arc_category`.
+    pub fn penalty(mut self, category_mask: u8, penalty: Weight) -> Self {
+        self.penalties.push((category_mask, penalty));
+        self
+    }
+
+    /// Block all arcs with any of the bits in `category_mask` set in their `arc_category`.
+    pub fn block(self, category_mask: u8) -> Self {
+        self.penalty(category_mask, INFINITY)
+    }
+
+    /// Canonical expression of this metric, suitable as a cache key.
+    /// Metrics which only differ in the order of their terms and penalties
+    /// or in how factors and penalties of the same attribute or category mask are split up get the same key.
+    pub fn key(&self) -> String {
+        self.canonical().to_string()
+    }
+
+    /// `key` for use in file and directory names, e.g. for stored customizations.
+    /// All bytes except ASCII letters, digits, `_` and `.` are escaped as `-` followed by two hex digits,
+    /// so the name is portable, distinct for distinct keys and unchanged for single attributes like `travel_time`.
+    pub fn dir_name(&self) -> String {
+        let mut name = String::new();
+        for byte in self.key().bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'.' {
+                name.push(byte as char);
+            } else {
+                name.push_str(&format!("-{:02x}", byte));
+            }
+        }
+        name
+    }
+
+    // Equivalent metric with one term per attribute and one penalty per mask, each sorted.
+    fn canonical(&self) -> MetricBuilder {
+        let mut terms: BTreeMap<&str, f64> = BTreeMap::new();
+        for (name, factor) in &self.terms {
+            *terms.entry(name).or_insert(0.0) += factor;
+        }
+        let mut penalties: BTreeMap<u8, Weight> = BTreeMap::new();
+        for &(mask, penalty) in &self.penalties {
+            let merged = penalties.entry(mask).or_insert(0);
+            *merged = std::cmp::min(merged.saturating_add(penalty), INFINITY);
+        }
+
+        MetricBuilder {
+            terms: terms.into_iter().map(|(name, factor)| (name.to_string(), factor)).collect(),
+            penalties: penalties.into_iter().collect(),
+        }
+    }
+
+    /// Load all attribute files (and `arc_category` if necessary) from `dir` and compute the weights.
+    pub fn build(&self, dir: &Path) -> Result<Vec<Weight>> {
+        self.build_with(|attribute| Vec::load_from(dir.join(attribute)), || Vec::load_from(dir.join("arc_category")))
+    }
+
+    /// Compute the weights with the attributes provided by the callbacks.
+    /// `arc_category` will only be called if there are any penalties.
+    /// Weights are rounded and capped at `INFINITY`, arcs with an `INFINITY` attribute stay blocked.
+    pub fn build_with(&self, mut attribute: impl FnMut(&str) -> Result<Vec<Weight>>, arc_category: impl FnOnce() -> Result<Vec<u8>>) -> Result<Vec<Weight>> {
+        let mut combined: Option<Vec<f64>> = None;
+
+        for (name, factor) in &self.terms {
+            let values = attribute(name)?;
+            let combined = combined.get_or_insert_with(|| vec![0.0; values.len()]);
+            if values.len() != combined.len() {
+                return Err(std::io::Error::new(ErrorKind::InvalidData, format!("attribute {} has wrong length", name)));
+            }
+            for (sum, &value) in combined.iter_mut().zip(values.iter()) {
+                *sum += if value >= INFINITY { f64::INFINITY } else { factor * f64::from(value) };
+            }
+        }
+
+        let mut weights: Vec<Weight> = combined
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "metric without attributes"))?
+            .into_iter()
+            .map(|sum| if sum >= f64::from(INFINITY) { INFINITY } else { sum.round() as Weight })
+            .collect();
+
+        if !self.penalties.is_empty() {
+            let arc_category = arc_category()?;
+            if arc_category.len() != weights.len() {
+                return Err(std::io::Error::new(ErrorKind::InvalidData, "arc_category has wrong length"));
+            }
+            for (weight, category) in weights.iter_mut().zip(arc_category) {
+                for &(mask, penalty) in &self.penalties {
+                    if category & mask != 0 {
+                        *weight = std::cmp::min(weight.saturating_add(penalty), INFINITY);
+                    }
+                }
+            }
+        }
+
+        Ok(weights)
+    }
+}
+
+impl fmt::Display for MetricBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts: Vec<String> = self
+            .terms
+            .iter()
+            .map(|(name, factor)| {
+                if (factor - 1.0).abs() < std::f64::EPSILON {
+                    name.clone()
+                } else {
+                    format!("{}*{}", factor, name)
+                }
+            })
+            .collect();
+        parts.extend(self.penalties.iter().map(|&(mask, penalty)| {
+            if penalty >= INFINITY {
+                format!("block({})", mask)
+            } else {
+                format!("penalty({},{})", mask, penalty)
+            }
+        }));
+        write!(f, "{}", parts.join("+"))
+    }
+}
+
+#[derive(Debug)]
+pub struct MetricParseError(String);
+
+impl fmt::Display for MetricParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Metric expression could not be parsed: {}", self.0)
+    }
+}
+
+impl Error for MetricParseError {}
+
+impl FromStr for MetricBuilder {
+    type Err = MetricParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let expression: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let err = |part: &str| MetricParseError(part.to_string());
+        let mut metric = MetricBuilder::new();
+
+        for part in expression.split('+') {
+            if let Some(mask) = part.strip_prefix("block(").and_then(|rest| rest.strip_suffix(')')) {
+                metric = metric.block(mask.parse().map_err(|_| err(part))?);
+            } else if let Some(args) = part.strip_prefix("penalty(").and_then(|rest| rest.strip_suffix(')')) {
+                let mut args = args.split(',');
+                let (mask, penalty) = match (args.next(), args.next(), args.next()) {
+                    (Some(mask), Some(penalty), None) => (mask.parse().map_err(|_| err(part))?, penalty.parse().map_err(|_| err(part))?),
+                    _ => return Err(err(part)),
+                };
+                metric = metric.penalty(mask, penalty);
+            } else {
+                let (factor, name) = match part.find('*') {
+                    Some(idx) => (part[..idx].parse().map_err(|_| err(part))?, &part[idx + 1..]),
+                    None => (1.0, part),
+                };
+                if !valid_factor(factor) || name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(err(part));
+                }
+                metric = metric.term(name, factor);
+            }
+        }
+
+        if metric.terms.is_empty() {
+            return Err(err(s));
+        }
+        Ok(metric)
+    }
+}
+
+fn valid_factor(factor: f64) -> bool {
+    factor.is_finite() && factor >= 0.0
+}
+
+/// Customizations of one CCH for several metrics, computed on demand and cached by the metric key.
+#[derive(Debug)]
+pub struct MetricCache<'c, 'g> {
+    cch: &'c CCH,
+    first_out: &'g [EdgeId],
+    head: &'g [NodeId],
+    dir: &'g Path,
+    customized: HashMap<String, Customized<'c, CCH>>,
+}
+
+impl<'c, 'g> MetricCache<'c, 'g> {
+    /// `first_out` and `head` of the graph the CCH was built for, attributes are loaded from `dir`.
+    pub fn new(cch: &'c CCH, first_out: &'g [EdgeId], head: &'g [NodeId], dir: &'g Path) -> Self {
+        MetricCache {
+            cch,
+            first_out,
+            head,
+            dir,
+            customized: HashMap::new(),
+        }
+    }
+
+    /// The customization for `metric`, customizing it first if it is not cached yet.
+    pub fn get(&mut self, metric: &MetricBuilder) -> Result<&Customized<'c, CCH>> {
+        let key = metric.key();
+        if !self.customized.contains_key(&key) {
+            let weights = metric.build(self.dir)?;
+            let customized = customize(self.cch, &FirstOutGraph::new(self.first_out, self.head, weights));
+            self.customized.insert(key.clone(), customized);
+        }
+        Ok(&self.customized[&key])
+    }
+
+    pub fn contains(&self, metric: &MetricBuilder) -> bool {
+        self.customized.contains_key(&metric.key())
+    }
+
+    /// Drop all cached customizations, e.g. after attribute files changed.
+    pub fn clear(&mut self) {
+        self.customized.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expressions_are_parsed() {
+        let metric: MetricBuilder = "travel_time + 0.5 * geo_distance + penalty(4, 100) + block(2)".parse().unwrap();
+        assert_eq!(
+            metric,
+            MetricBuilder::new()
+                .term("travel_time", 1.0)
+                .term("geo_distance", 0.5)
+                .penalty(4, 100)
+                .block(FREEWAY_BIT)
+        );
+        assert_eq!(metric.key(), "0.5*geo_distance+travel_time+block(2)+penalty(4,100)");
+        assert_eq!(metric.key().parse::<MetricBuilder>().unwrap().key(), metric.key());
+        assert_eq!("travel_time".parse::<MetricBuilder>().unwrap().key(), "travel_time");
+        assert_eq!(metric.dir_name(), "0.5-2ageo_distance-2btravel_time-2bblock-282-29-2bpenalty-284-2c100-29");
+        assert_eq!("travel_time".parse::<MetricBuilder>().unwrap().dir_name(), "travel_time");
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        assert!("".parse::<MetricBuilder>().is_err());
+        assert!("block(2)".parse::<MetricBuilder>().is_err());
+        assert!("travel_time + penalty(4)".parse::<MetricBuilder>().is_err());
+        assert!("x*travel_time".parse::<MetricBuilder>().is_err());
+        assert!("-1*travel_time".parse::<MetricBuilder>().is_err());
+        assert!("travel_time + -0.5*geo_distance".parse::<MetricBuilder>().is_err());
+        assert!("NaN*travel_time".parse::<MetricBuilder>().is_err());
+    }
+
+    #[test]
+    fn equivalent_metrics_have_the_same_key() {
+        let key = |expression: &str| expression.parse::<MetricBuilder>().unwrap().key();
+        assert_eq!(key("geo_distance + travel_time"), key("travel_time + geo_distance"));
+        assert_eq!(key("travel_time + 0.5*geo_distance + 0.5*geo_distance"), key("geo_distance + travel_time"));
+        assert_eq!(key("travel_time + penalty(4, 100) + penalty(4, 50)"), key("penalty(4, 150) + travel_time"));
+        assert_eq!(key("travel_time + block(2) + penalty(2, 100)"), key("travel_time + block(2)"));
+        assert_ne!(key("travel_time + penalty(4, 100)"), key("travel_time + penalty(2, 100)"));
+    }
+}
@@ -13,12 +13,19 @@ mod contraction;
 use contraction::*;
 mod customization;
 pub use customization::ftd as ftd_cch;
-pub use customization::{customize, customize_directed};
+pub use customization::{customize, customize_directed, customize_partial, customize_perfect, PartialCustomizationStats};
 mod separator_decomposition;
 use separator_decomposition::*;
 mod reorder;
 pub use reorder::*;
+mod ordering;
+pub use ordering::nested_dissection;
+pub mod many_to_many;
+pub mod metric;
+mod persistence;
+pub mod phast;
 pub mod query;
+pub mod weighted_sum;
 
 /// Execute first phase, that is metric independent preprocessing.
 pub fn contract<Graph: for<'a> LinkIterable<'a, NodeId> + RandomLinkAccessGraph>(graph: &Graph, node_order: NodeOrder) -> CCH {
@@ -30,23 +37,18 @@ pub fn contract<Graph: for<'a> LinkIterable<'a, NodeId> + RandomLinkAccessGraph>
 /// several other structures like the elimination tree, a mapping from cch edge ids to original edge ids and the inverted graph.
 #[derive(Debug)]
 pub struct CCH {
-    first_out: Vec<EdgeId>,
-    head: Vec<NodeId>,
-    tail: Vec<NodeId>,
+    first_out: VecOrMmap<EdgeId>,
+    head: VecOrMmap<NodeId>,
+    tail: VecOrMmap<NodeId>,
     node_order: NodeOrder,
     cch_edge_to_orig_arc: Vec<(InRangeOption<EdgeId>, InRangeOption<EdgeId>)>,
     elimination_tree: Vec<InRangeOption<NodeId>>,
     inverted: OwnedGraph,
 }
 
-impl Deconstruct for CCH {
-    fn store_each(&self, store: &dyn Fn(&str, &dyn Store) -> std::io::Result<()>) -> std::io::Result<()> {
-        store("cch_first_out", &self.first_out)?;
-        store("cch_head", &self.head)?;
-        Ok(())
-    }
-}
-
+/// Additional data to load a CCH from only the chordal supergraph (`cch_first_out` and `cch_head`).
+/// All other structures will be rebuilt from the original graph.
+/// To load a CCH stored with all its structures, use `CCH::reconstruct_from`.
 #[derive(Debug)]
 pub struct CCHReconstrctor<'g, Graph> {
     pub original_graph: &'g Graph,
@@ -98,12 +100,12 @@ impl CCH {
         let (first_out, head, _) = contracted_graph.decompose();
 
         CCH {
-            first_out,
-            head,
+            first_out: first_out.into(),
+            head: head.into(),
             node_order,
             cch_edge_to_orig_arc,
             elimination_tree,
-            tail,
+            tail: tail.into(),
             inverted,
         }
     }
@@ -155,12 +157,50 @@ impl CCH {
         self.head[range].iter().cloned()
     }
 
+    // Find the id of the edge from `from` to a higher ranked node `to` if it exists.
+    fn edge_id(&self, from: NodeId, to: NodeId) -> Option<EdgeId> {
+        let range = self.neighbor_edge_indices_usize(from);
+        self.head[range.clone()].binary_search(&to).ok().map(|idx| (range.start + idx) as EdgeId)
+    }
+
     /// Transform into a directed CCH which is more efficient
     /// for turn expanded graphs because many edges can be removed.
     pub fn into_directed_cch(self) -> DirectedCCH {
         // identify arcs which are always infinity and can be removed
         let (forward, backward) = customization::always_infinity(&self).into_ch_graphs();
-
+        let forward_required: Vec<bool> = forward.weight().iter().map(|&weight| weight < INFINITY).collect();
+        let backward_required: Vec<bool> = backward.weight().iter().map(|&weight| weight < INFINITY).collect();
+
+        self.into_directed_cch_with(&forward_required, &backward_required)
+    }
+
+    /// Transform into a directed CCH which only contains arcs that may be part of shortest paths for `metric`.
+    /// Arcs with infinite weight and arcs for which the perfect customization found a shorter path over higher ranked nodes are removed.
+    /// Customizing the result with the same metric (using `customize_directed`) yields the perfect weights of the remaining arcs.
+    /// Elimination tree queries on it only relax arcs which are shortest paths between their endpoints, so no stalling is necessary.
+    /// The result is only valid for `metric`. When the metric changes, the full `CCH` has to be used again.
+    pub fn into_perfect_directed_cch<Graph>(self, metric: &Graph) -> DirectedCCH
+    where
+        Graph: for<'a> LinkIterGraph<'a> + RandomLinkAccessGraph + Sync,
+    {
+        let basic = customize(&self, metric);
+        let mut perfect = basic.clone();
+        customization::perfect_customization(&self, &mut perfect.upward, &mut perfect.downward);
+        let required = |basic: &[Weight], perfect: &[Weight]| -> Vec<bool> {
+            basic
+                .iter()
+                .zip(perfect.iter())
+                .map(|(&basic, &perfect)| basic < INFINITY && perfect == basic)
+                .collect()
+        };
+        let forward_required = required(&basic.upward, &perfect.upward);
+        let backward_required = required(&basic.downward, &perfect.downward);
+
+        self.into_directed_cch_with(&forward_required, &backward_required)
+    }
+
+    // Build a directed CCH with only the upward and downward arcs marked as required.
+    fn into_directed_cch_with(self, forward_required: &[bool], backward_required: &[bool]) -> DirectedCCH {
         let mut forward_first_out = Vec::with_capacity(self.first_out.len());
         forward_first_out.push(0);
         let mut forward_head = Vec::with_capacity(self.head.len());
@@ -175,17 +215,20 @@ impl CCH {
         let mut backward_edge_counter = 0;
 
         for node in 0..self.num_nodes() as NodeId {
-            let orig_arcs = &self.cch_edge_to_orig_arc[self.neighbor_edge_indices_usize(node)];
-            for (link, &(forward_orig_arc, _)) in LinkIterable::<Link>::link_iter(&forward, node).zip(orig_arcs.iter()) {
-                if link.weight < INFINITY {
-                    forward_head.push(link.node);
+            let edges = self.neighbor_edge_indices_usize(node);
+            for (((head, &(forward_orig_arc, backward_orig_arc)), &forward_required), &backward_required) in self
+                .neighbor_iter(node)
+                .zip(&self.cch_edge_to_orig_arc[edges.clone()])
+                .zip(&forward_required[edges.clone()])
+                .zip(&backward_required[edges])
+            {
+                if forward_required {
+                    forward_head.push(head);
                     forward_cch_edge_to_orig_arc.push(forward_orig_arc);
                     forward_edge_counter += 1;
                 }
-            }
-            for (link, &(_, backward_orig_arc)) in LinkIterable::<Link>::link_iter(&backward, node).zip(orig_arcs.iter()) {
-                if link.weight < INFINITY {
-                    backward_head.push(link.node);
+                if backward_required {
+                    backward_head.push(head);
                     backward_cch_edge_to_orig_arc.push(backward_orig_arc);
                     backward_edge_counter += 1;
                 }
@@ -210,12 +253,12 @@ impl CCH {
         let backward_inverted = inverted_with_orig_edge_ids_as_weights(&FirstOutGraph::new(&backward_first_out[..], &backward_head[..], &backward_head[..]));
 
         DirectedCCH {
-            forward_first_out,
-            forward_head,
-            forward_tail,
-            backward_first_out,
-            backward_head,
-            backward_tail,
+            forward_first_out: forward_first_out.into(),
+            forward_head: forward_head.into(),
+            forward_tail: forward_tail.into(),
+            backward_first_out: backward_first_out.into(),
+            backward_head: backward_head.into(),
+            backward_tail: backward_tail.into(),
             node_order: self.node_order,
             forward_cch_edge_to_orig_arc,
             backward_cch_edge_to_orig_arc,
@@ -279,6 +322,20 @@ pub trait CCHT {
     /// Borrow node order
     fn node_order(&self) -> &NodeOrder;
 
+    /// Checksum of the chordal supergraph topology and the node order.
+    /// Used to make sure that data loaded from disk actually belongs to this CCH.
+    fn topology_checksum(&self) -> u64 {
+        [
+            self.forward_first_out().data_bytes(),
+            self.forward_head().data_bytes(),
+            self.backward_first_out().data_bytes(),
+            self.backward_head().data_bytes(),
+            self.node_order().order().data_bytes(),
+        ]
+        .iter()
+        .fold(FNV_OFFSET_BASIS, |hash, bytes| fnv1a(hash, bytes))
+    }
+
     /// Check for a node pair and a weight if there is a corresponding lower triangle.
     /// If so, return the id of the middle node and the weights of both lower edges.
     fn unpack_arc(&self, from: NodeId, to: NodeId, weight: Weight, upward: &[Weight], downward: &[Weight]) -> Option<(NodeId, Weight, Weight)> {
@@ -357,6 +414,17 @@ pub struct Customized<'c, CCH> {
     downward: Vec<Weight>,
 }
 
+// manual impl, because deriving would require `CCH: Clone`
+impl<'c, CCH> Clone for Customized<'c, CCH> {
+    fn clone(&self) -> Self {
+        Customized {
+            cch: self.cch,
+            upward: self.upward.clone(),
+            downward: self.downward.clone(),
+        }
+    }
+}
+
 impl<'c, CCH: CCHT> Customized<'c, CCH> {
     /// Decompose into an upward and a downward graph which could be used for a CH query.
     #[allow(clippy::type_complexity)]
@@ -373,14 +441,87 @@ impl<'c, CCH: CCHT> Customized<'c, CCH> {
     }
 }
 
+/// A customized metric together with the metric it was customized with.
+/// Stores the customization and checksums of the CCH and of the metric,
+/// so a stale customization is rejected when the metric changed since (see `CustomizedReconstrctor`).
+#[derive(Debug)]
+pub struct CustomizedMetric<'a, 'c, CCH> {
+    pub customized: &'a Customized<'c, CCH>,
+    pub metric: &'a [Weight],
+}
+
+impl<'a, 'c, CCH: CCHT> Deconstruct for CustomizedMetric<'a, 'c, CCH> {
+    fn store_each(&self, store: &dyn Fn(&str, &dyn Store) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("cch_checksum", &vec![self.customized.cch.topology_checksum()])?;
+        store("metric_checksum", &vec![metric_checksum(self.metric)])?;
+        store("upward", &self.customized.upward)?;
+        store("downward", &self.customized.downward)?;
+        Ok(())
+    }
+}
+
+/// Additional data to load customized metrics back from disk.
+/// Loading fails, if the stored metric was customized for a different CCH or with different weights than `metric`.
+#[derive(Debug)]
+pub struct CustomizedReconstrctor<'c, 'm, CCH> {
+    pub cch: &'c CCH,
+    pub metric: &'m [Weight],
+}
+
+impl<'c, 'm, CCH: CCHT> ReconstructPrepared<Customized<'c, CCH>> for CustomizedReconstrctor<'c, 'm, CCH> {
+    fn reconstruct_with(self, loader: Loader) -> std::io::Result<Customized<'c, CCH>> {
+        let checksum: Vec<u64> = loader.load("cch_checksum")?;
+        if checksum[..] != [self.cch.topology_checksum()] {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "customized metric does not belong to this CCH",
+            ));
+        }
+        let checksum: Vec<u64> = loader.load("metric_checksum")?;
+        if checksum[..] != [metric_checksum(self.metric)] {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "customized metric was customized with different weights",
+            ));
+        }
+
+        let upward: Vec<Weight> = loader.load("upward")?;
+        let downward: Vec<Weight> = loader.load("downward")?;
+        if upward.len() != self.cch.forward_head().len() || downward.len() != self.cch.backward_head().len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "number of customized weights does not match number of CCH edges",
+            ));
+        }
+
+        Ok(Customized {
+            cch: self.cch,
+            upward,
+            downward,
+        })
+    }
+}
+
+fn metric_checksum(metric: &[Weight]) -> u64 {
+    fnv1a(FNV_OFFSET_BASIS, metric.data_bytes())
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// 64 bit FNV-1a hash - simple and, in contrast to the std hashers, guaranteed to be stable across compiler versions
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME))
+}
+
 #[derive(Debug)]
 pub struct DirectedCCH {
-    forward_first_out: Vec<EdgeId>,
-    forward_head: Vec<NodeId>,
-    forward_tail: Vec<NodeId>,
-    backward_first_out: Vec<EdgeId>,
-    backward_head: Vec<NodeId>,
-    backward_tail: Vec<NodeId>,
+    forward_first_out: VecOrMmap<EdgeId>,
+    forward_head: VecOrMmap<NodeId>,
+    forward_tail: VecOrMmap<NodeId>,
+    backward_first_out: VecOrMmap<EdgeId>,
+    backward_head: VecOrMmap<NodeId>,
+    backward_tail: VecOrMmap<NodeId>,
     node_order: NodeOrder,
     forward_cch_edge_to_orig_arc: Vec<InRangeOption<EdgeId>>,
     backward_cch_edge_to_orig_arc: Vec<InRangeOption<EdgeId>>,
@@ -399,7 +540,7 @@ impl DirectedCCH {
     }
 
     fn backward(&self) -> Slcs<EdgeId, NodeId> {
-        Slcs::new(&self.forward_first_out, &self.forward_head)
+        Slcs::new(&self.backward_first_out, &self.backward_head)
     }
 
     /// Reconstruct the separators of the nested dissection order.
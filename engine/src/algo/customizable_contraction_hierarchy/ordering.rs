@@ -0,0 +1,397 @@
+//! Nested dissection orders for CCHs without external tools.
+//!
+//! This is a simple pure rust alternative to `InertialFlowCutter`.
+//! Cells are recursively bisected using inertial flow:
+//! The nodes of a cell are projected onto a few geographic directions.
+//! For each direction, the first and last nodes along the direction are used as sources and sinks
+//! and a minimum node separator between them is obtained through a unit capacity max flow computation.
+//! The smallest separator over all directions is removed from the cell and the remaining components are ordered recursively.
+//! Separator nodes are always ranked higher than all nodes in the cells they separate.
+
+use super::*;
+use crate::datastr::graph::first_out_graph::degrees_to_first_out;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+/// Fraction of the nodes of a cell which will be used as sources and sinks, respectively.
+/// This is also the minimum balance of the resulting separators.
+const SOURCE_SINK_FRACTION: f64 = 0.25;
+/// Cells with at most this many nodes will not be dissected any further.
+const MAX_UNDISSECTED_CELL_SIZE: usize = 16;
+/// Cells smaller than this will be processed sequentially.
+const MIN_PARALLEL_CELL_SIZE: usize = 10_000;
+
+/// Compute a nested dissection order for the given graph using inertial flow bisection.
+/// The graph will be treated as undirected, loops and multi arcs are ignored.
+/// `latitude` and `longitude` have to contain coordinates for every node.
+pub fn nested_dissection<Graph: for<'a> LinkIterable<'a, NodeId>>(graph: &Graph, latitude: &[f32], longitude: &[f32]) -> NodeOrder {
+    let n = graph.num_nodes();
+    assert_eq!(latitude.len(), n);
+    assert_eq!(longitude.len(), n);
+
+    let cell = report_time("Build undirected graph for ordering", || {
+        let mut adjacency_lists = vec![Vec::new(); n];
+        for node in 0..n as NodeId {
+            for neighbor in graph.link_iter(node) {
+                if neighbor != node {
+                    adjacency_lists[node as usize].push(neighbor);
+                    adjacency_lists[neighbor as usize].push(node);
+                }
+            }
+        }
+        for neighbors in &mut adjacency_lists {
+            neighbors.sort_unstable();
+            neighbors.dedup();
+        }
+
+        Cell {
+            graph: UnweightedOwnedGraph::from_adjancecy_lists(adjacency_lists),
+            original_ids: (0..n as NodeId).collect(),
+            coords: latitude.iter().zip(longitude.iter()).map(|(&lat, &lng)| (lat, lng)).collect(),
+        }
+    });
+
+    let order = report_time("Nested dissection", || cell.order());
+    debug_assert_eq!(order.len(), n);
+
+    NodeOrder::from_node_order(order)
+}
+
+// A subgraph with local node ids `0..k` and a mapping back to the ids in the original graph.
+#[derive(Debug)]
+struct Cell {
+    graph: UnweightedOwnedGraph,
+    original_ids: Vec<NodeId>,
+    coords: Vec<(f32, f32)>,
+}
+
+impl Cell {
+    fn num_nodes(&self) -> usize {
+        self.original_ids.len()
+    }
+
+    // Order the nodes of this cell ascending by importance.
+    fn order(self) -> Vec<NodeId> {
+        let mut components = self.components();
+
+        if components.len() > 1 {
+            // Order bigger components first, so the order works well with the separator based parallelization of the customization.
+            components.sort_by_key(|component| std::cmp::Reverse(component.len()));
+            let cells: Vec<Cell> = components.iter().map(|component| self.subcell(component)).collect();
+            return Self::order_all(cells);
+        }
+
+        if self.num_nodes() <= MAX_UNDISSECTED_CELL_SIZE {
+            return self.original_ids;
+        }
+
+        let separator = self.separator();
+        debug_assert!(!separator.is_empty());
+
+        let mut is_separator_node = vec![false; self.num_nodes()];
+        for &node in &separator {
+            is_separator_node[node as usize] = true;
+        }
+        let remaining: Vec<NodeId> = (0..self.num_nodes() as NodeId).filter(|&node| !is_separator_node[node as usize]).collect();
+
+        let mut order = self.subcell(&remaining).order();
+        order.extend(separator.iter().map(|&node| self.original_ids[node as usize]));
+        order
+    }
+
+    fn order_all(cells: Vec<Cell>) -> Vec<NodeId> {
+        if cells.iter().map(Cell::num_nodes).sum::<usize>() < MIN_PARALLEL_CELL_SIZE || cfg!(feature = "cch-disable-par") {
+            cells.into_iter().flat_map(Cell::order).collect()
+        } else {
+            let orders: Vec<Vec<NodeId>> = cells.into_par_iter().map(Cell::order).collect();
+            orders.into_iter().flatten().collect()
+        }
+    }
+
+    // Connected components as lists of local node ids.
+    fn components(&self) -> Vec<Vec<NodeId>> {
+        let n = self.num_nodes();
+        let mut visited = vec![false; n];
+        let mut components = Vec::new();
+        let mut stack = Vec::new();
+
+        for start in 0..n {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            stack.push(start as NodeId);
+            let mut component = Vec::new();
+
+            while let Some(node) = stack.pop() {
+                component.push(node);
+                for neighbor in LinkIterable::<NodeId>::link_iter(&self.graph, node) {
+                    if !visited[neighbor as usize] {
+                        visited[neighbor as usize] = true;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+
+            component.sort_unstable();
+            components.push(component);
+        }
+
+        components
+    }
+
+    // Build the induced subgraph of the given (sorted) local node ids.
+    fn subcell(&self, nodes: &[NodeId]) -> Cell {
+        let mut new_ids = vec![self.num_nodes() as NodeId; self.num_nodes()];
+        for (new_id, &node) in nodes.iter().enumerate() {
+            new_ids[node as usize] = new_id as NodeId;
+        }
+
+        let mut head = Vec::new();
+        let first_out = degrees_to_first_out(nodes.iter().map(|&node| {
+            let prev_len = head.len();
+            head.extend(
+                LinkIterable::<NodeId>::link_iter(&self.graph, node)
+                    .map(|neighbor| new_ids[neighbor as usize])
+                    .filter(|&neighbor| neighbor < nodes.len() as NodeId),
+            );
+            (head.len() - prev_len) as EdgeId
+        }))
+        .collect();
+
+        Cell {
+            graph: UnweightedOwnedGraph::new(first_out, head),
+            original_ids: nodes.iter().map(|&node| self.original_ids[node as usize]).collect(),
+            coords: nodes.iter().map(|&node| self.coords[node as usize]).collect(),
+        }
+    }
+
+    // Find a small balanced separator by trying several directions and picking the best one.
+    fn separator(&self) -> Vec<NodeId> {
+        let directions: [fn((f32, f32)) -> f32; 4] = [|(lat, _)| lat, |(_, lng)| lng, |(lat, lng)| lat + lng, |(lat, lng)| lat - lng];
+
+        let candidates = |direction: &fn((f32, f32)) -> f32| self.inertial_flow_cut(*direction);
+        let cuts: Vec<InertialFlowCut> = if self.num_nodes() < MIN_PARALLEL_CELL_SIZE || cfg!(feature = "cch-disable-par") {
+            directions.iter().map(candidates).collect()
+        } else {
+            directions.par_iter().map(candidates).collect()
+        };
+
+        cuts.into_iter()
+            .min_by_key(|cut| (cut.separator.len(), std::cmp::Reverse(cut.smaller_side)))
+            .unwrap()
+            .separator
+    }
+
+    fn inertial_flow_cut(&self, direction: fn((f32, f32)) -> f32) -> InertialFlowCut {
+        let n = self.num_nodes();
+        let mut projected: Vec<NodeId> = (0..n as NodeId).collect();
+        projected.sort_by(|&n1, &n2| {
+            direction(self.coords[n1 as usize])
+                .partial_cmp(&direction(self.coords[n2 as usize]))
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let num_terminals = std::cmp::max(1, (n as f64 * SOURCE_SINK_FRACTION) as usize);
+        let sources = &projected[..num_terminals];
+        let sinks = &projected[n - num_terminals..];
+
+        let mut network = FlowNetwork::node_split(&self.graph, sources, sinks);
+        network.max_flow();
+        let source_side = network.source_side();
+
+        // A node is a separator node if its entry is reachable from the source in the residual network but not its exit.
+        let separator: Vec<NodeId> = (0..n as NodeId)
+            .filter(|&node| source_side[FlowNetwork::entry(node)] && !source_side[FlowNetwork::exit(node)])
+            .collect();
+        let source_side_size = (0..n as NodeId).filter(|&node| source_side[FlowNetwork::exit(node)]).count();
+        let sink_side_size = n - source_side_size - separator.len();
+
+        InertialFlowCut {
+            separator,
+            smaller_side: std::cmp::min(source_side_size, sink_side_size),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct InertialFlowCut {
+    separator: Vec<NodeId>,
+    smaller_side: usize,
+}
+
+const INFINITE_CAPACITY: i32 = i32::MAX / 2;
+const UNREACHED: u32 = u32::MAX;
+
+// Residual network for the unit node capacity max flow.
+// Each node `v` is split into an entry `2v` and an exit `2v + 1` with a unit capacity arc in between.
+// Arcs are stored in pairs, so the reverse of arc `a` is always `a ^ 1`.
+#[derive(Debug)]
+struct FlowNetwork {
+    first_out: Vec<EdgeId>,
+    arcs: Vec<EdgeId>,
+    head: Vec<NodeId>,
+    residual_capacity: Vec<i32>,
+    source: NodeId,
+    sink: NodeId,
+}
+
+impl FlowNetwork {
+    fn entry(node: NodeId) -> usize {
+        2 * node as usize
+    }
+
+    fn exit(node: NodeId) -> usize {
+        2 * node as usize + 1
+    }
+
+    fn node_split(graph: &UnweightedOwnedGraph, sources: &[NodeId], sinks: &[NodeId]) -> Self {
+        let n = graph.num_nodes();
+        let source = 2 * n as NodeId;
+        let sink = source + 1;
+
+        let mut head = Vec::with_capacity(2 * (n + graph.num_arcs() + sources.len() + sinks.len()));
+        let mut residual_capacity = Vec::with_capacity(head.capacity());
+        let mut add_arc = |from: usize, to: usize, capacity: i32| {
+            // the tail of each arc is the head of its reverse
+            head.push(to as NodeId);
+            residual_capacity.push(capacity);
+            head.push(from as NodeId);
+            residual_capacity.push(0);
+        };
+
+        for node in 0..n as NodeId {
+            add_arc(Self::entry(node), Self::exit(node), 1);
+            for neighbor in LinkIterable::<NodeId>::link_iter(graph, node) {
+                add_arc(Self::exit(node), Self::entry(neighbor), INFINITE_CAPACITY);
+            }
+        }
+        for &node in sources {
+            add_arc(source as usize, Self::entry(node), INFINITE_CAPACITY);
+        }
+        for &node in sinks {
+            add_arc(Self::exit(node), sink as usize, INFINITE_CAPACITY);
+        }
+
+        // bucket arcs by their tail
+        let num_flow_nodes = 2 * n + 2;
+        let mut degrees = vec![0 as EdgeId; num_flow_nodes];
+        for arc in 0..head.len() {
+            degrees[head[arc ^ 1] as usize] += 1;
+        }
+        let first_out: Vec<EdgeId> = degrees_to_first_out(degrees.into_iter()).collect();
+        let mut insert_pos = first_out.clone();
+        let mut arcs = vec![0; head.len()];
+        for arc in 0..head.len() {
+            let tail = head[arc ^ 1] as usize;
+            arcs[insert_pos[tail] as usize] = arc as EdgeId;
+            insert_pos[tail] += 1;
+        }
+
+        FlowNetwork {
+            first_out,
+            arcs,
+            head,
+            residual_capacity,
+            source,
+            sink,
+        }
+    }
+
+    fn outgoing_arcs(&self, node: NodeId) -> &[EdgeId] {
+        &self.arcs[self.first_out[node as usize] as usize..self.first_out[node as usize + 1] as usize]
+    }
+
+    // Dinic: compute BFS levels from the source and augment a blocking flow along level increasing paths
+    // until the sink becomes unreachable.
+    // With unit node capacities, this needs only few phases.
+    fn max_flow(&mut self) {
+        let num_flow_nodes = self.first_out.len() - 1;
+        let mut queue = VecDeque::new();
+        let mut current_arc = vec![0; num_flow_nodes];
+        let mut path: Vec<EdgeId> = Vec::new();
+
+        loop {
+            let mut level = vec![UNREACHED; num_flow_nodes];
+            level[self.source as usize] = 0;
+            queue.push_back(self.source);
+            while let Some(node) = queue.pop_front() {
+                for &arc in self.outgoing_arcs(node) {
+                    let next = self.head[arc as usize];
+                    if self.residual_capacity[arc as usize] > 0 && level[next as usize] == UNREACHED {
+                        level[next as usize] = level[node as usize] + 1;
+                        queue.push_back(next);
+                    }
+                }
+            }
+
+            if level[self.sink as usize] == UNREACHED {
+                return;
+            }
+
+            current_arc.copy_from_slice(&self.first_out[..num_flow_nodes]);
+            path.clear();
+            let mut node = self.source;
+
+            // iterative DFS, paths may get very long
+            loop {
+                if node == self.sink {
+                    let bottleneck = path.iter().map(|&arc| self.residual_capacity[arc as usize]).min().unwrap();
+                    for &arc in &path {
+                        self.residual_capacity[arc as usize] -= bottleneck;
+                        self.residual_capacity[arc as usize ^ 1] += bottleneck;
+                    }
+                    // continue from the tail of the first saturated arc
+                    let saturated = path.iter().position(|&arc| self.residual_capacity[arc as usize] == 0).unwrap();
+                    node = self.head[path[saturated] as usize ^ 1];
+                    path.truncate(saturated);
+                    continue;
+                }
+
+                let end = self.first_out[node as usize + 1];
+                while current_arc[node as usize] < end {
+                    let arc = self.arcs[current_arc[node as usize] as usize];
+                    let next = self.head[arc as usize];
+                    if self.residual_capacity[arc as usize] > 0 && level[next as usize] == level[node as usize] + 1 {
+                        break;
+                    }
+                    current_arc[node as usize] += 1;
+                }
+
+                if current_arc[node as usize] < end {
+                    let arc = self.arcs[current_arc[node as usize] as usize];
+                    path.push(arc);
+                    node = self.head[arc as usize];
+                } else {
+                    // dead end, retreat
+                    level[node as usize] = UNREACHED;
+                    if let Some(arc) = path.pop() {
+                        node = self.head[arc as usize ^ 1];
+                        current_arc[node as usize] += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // All flow nodes reachable from the source in the residual network.
+    fn source_side(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.first_out.len() - 1];
+        let mut stack = vec![self.source];
+        reachable[self.source as usize] = true;
+
+        while let Some(node) = stack.pop() {
+            for &arc in self.outgoing_arcs(node) {
+                let next = self.head[arc as usize];
+                if self.residual_capacity[arc as usize] > 0 && !reachable[next as usize] {
+                    reachable[next as usize] = true;
+                    stack.push(next);
+                }
+            }
+        }
+
+        reachable
+    }
+}
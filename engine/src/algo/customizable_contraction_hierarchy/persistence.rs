@@ -0,0 +1,242 @@
+//! Complete on-disk format for `CCH` and `DirectedCCH`.
+//!
+//! In contrast to `CCHReconstrctor`, which only loads the chordal supergraph and rebuilds everything else from the original graph,
+//! this stores all auxiliary structures, so a hierarchy can be loaded ready to query without any recomputation.
+//! The topology arrays (`first_out`, `head` and `tail` of each direction) are memory mapped rather than read into memory.
+//! The remaining structures are comparatively small and loaded into owned buffers.
+//! A header with a format version and the dimensions of the hierarchy is stored along and checked on load.
+//! Additionally, the consistency of the loaded data is validated in linear time.
+
+use super::*;
+use std::io::{Error, ErrorKind, Result};
+
+const FORMAT_VERSION: u64 = 1;
+const UNDIRECTED: u64 = 0;
+const DIRECTED: u64 = 1;
+
+impl Deconstruct for CCH {
+    fn store_each(&self, store: &dyn Fn(&str, &dyn Store) -> Result<()>) -> Result<()> {
+        let m = self.num_arcs() as u64;
+        store("cch_header", &vec![FORMAT_VERSION, UNDIRECTED, self.num_nodes() as u64, m, m])?;
+        store("cch_first_out", &self.first_out)?;
+        store("cch_head", &self.head)?;
+        store("cch_tail", &self.tail)?;
+        let (forward_orig_arc, backward_orig_arc): (Vec<_>, Vec<_>) = self.cch_edge_to_orig_arc.iter().cloned().unzip();
+        store("cch_forward_orig_arc", &forward_orig_arc)?;
+        store("cch_backward_orig_arc", &backward_orig_arc)?;
+        store("cch_elimination_tree", &self.elimination_tree)?;
+        store("cch_inverted_first_out", &self.inverted.first_out())?;
+        store("cch_inverted_head", &self.inverted.head())?;
+        store("cch_inverted_edge_ids", &self.inverted.weight())?;
+        self.node_order.store_each(store)
+    }
+}
+
+impl Reconstruct for CCH {
+    fn reconstruct_with(loader: Loader) -> Result<Self> {
+        let (n, m, _) = load_header(&loader, UNDIRECTED)?;
+
+        let first_out: MmapSlice<EdgeId> = loader.map("cch_first_out")?;
+        let head: MmapSlice<NodeId> = loader.map("cch_head")?;
+        let tail: MmapSlice<NodeId> = loader.map("cch_tail")?;
+        let inverted = OwnedGraph::new(
+            loader.load("cch_inverted_first_out")?,
+            loader.load("cch_inverted_head")?,
+            loader.load("cch_inverted_edge_ids")?,
+        );
+        check_len(head.len(), m, "cch_head")?;
+        validate_direction(n, &first_out, &head, &tail, &inverted)?;
+
+        let forward_orig_arc: Vec<InRangeOption<EdgeId>> = loader.load("cch_forward_orig_arc")?;
+        let backward_orig_arc: Vec<InRangeOption<EdgeId>> = loader.load("cch_backward_orig_arc")?;
+        check_len(forward_orig_arc.len(), m, "cch_forward_orig_arc")?;
+        check_len(backward_orig_arc.len(), m, "cch_backward_orig_arc")?;
+
+        let elimination_tree: Vec<InRangeOption<NodeId>> = loader.load("cch_elimination_tree")?;
+        validate_elimination_tree(n, &elimination_tree)?;
+        // for undirected CCHs, the parent is always the lowest upward neighbor
+        for node in 0..n {
+            if elimination_tree[node].value() != head[first_out[node] as usize..first_out[node + 1] as usize].iter().min().cloned() {
+                return Err(invalid_data("cch_elimination_tree does not match cch graph"));
+            }
+        }
+
+        let node_order = load_node_order(&loader, n)?;
+
+        Ok(CCH {
+            first_out: first_out.into(),
+            head: head.into(),
+            tail: tail.into(),
+            node_order,
+            cch_edge_to_orig_arc: forward_orig_arc.into_iter().zip(backward_orig_arc).collect(),
+            elimination_tree,
+            inverted,
+        })
+    }
+}
+
+impl Deconstruct for DirectedCCH {
+    fn store_each(&self, store: &dyn Fn(&str, &dyn Store) -> Result<()>) -> Result<()> {
+        store(
+            "cch_header",
+            &vec![
+                FORMAT_VERSION,
+                DIRECTED,
+                self.num_nodes() as u64,
+                self.forward_head.len() as u64,
+                self.backward_head.len() as u64,
+            ],
+        )?;
+        store("cch_forward_first_out", &self.forward_first_out)?;
+        store("cch_forward_head", &self.forward_head)?;
+        store("cch_forward_tail", &self.forward_tail)?;
+        store("cch_forward_orig_arc", &self.forward_cch_edge_to_orig_arc)?;
+        store("cch_forward_inverted_first_out", &self.forward_inverted.first_out())?;
+        store("cch_forward_inverted_head", &self.forward_inverted.head())?;
+        store("cch_forward_inverted_edge_ids", &self.forward_inverted.weight())?;
+        store("cch_backward_first_out", &self.backward_first_out)?;
+        store("cch_backward_head", &self.backward_head)?;
+        store("cch_backward_tail", &self.backward_tail)?;
+        store("cch_backward_orig_arc", &self.backward_cch_edge_to_orig_arc)?;
+        store("cch_backward_inverted_first_out", &self.backward_inverted.first_out())?;
+        store("cch_backward_inverted_head", &self.backward_inverted.head())?;
+        store("cch_backward_inverted_edge_ids", &self.backward_inverted.weight())?;
+        store("cch_elimination_tree", &self.elimination_tree)?;
+        self.node_order.store_each(store)
+    }
+}
+
+impl Reconstruct for DirectedCCH {
+    fn reconstruct_with(loader: Loader) -> Result<Self> {
+        let (n, forward_m, backward_m) = load_header(&loader, DIRECTED)?;
+
+        let forward_first_out: MmapSlice<EdgeId> = loader.map("cch_forward_first_out")?;
+        let forward_head: MmapSlice<NodeId> = loader.map("cch_forward_head")?;
+        let forward_tail: MmapSlice<NodeId> = loader.map("cch_forward_tail")?;
+        let forward_inverted = OwnedGraph::new(
+            loader.load("cch_forward_inverted_first_out")?,
+            loader.load("cch_forward_inverted_head")?,
+            loader.load("cch_forward_inverted_edge_ids")?,
+        );
+        check_len(forward_head.len(), forward_m, "cch_forward_head")?;
+        validate_direction(n, &forward_first_out, &forward_head, &forward_tail, &forward_inverted)?;
+        let forward_cch_edge_to_orig_arc: Vec<InRangeOption<EdgeId>> = loader.load("cch_forward_orig_arc")?;
+        check_len(forward_cch_edge_to_orig_arc.len(), forward_m, "cch_forward_orig_arc")?;
+
+        let backward_first_out: MmapSlice<EdgeId> = loader.map("cch_backward_first_out")?;
+        let backward_head: MmapSlice<NodeId> = loader.map("cch_backward_head")?;
+        let backward_tail: MmapSlice<NodeId> = loader.map("cch_backward_tail")?;
+        let backward_inverted = OwnedGraph::new(
+            loader.load("cch_backward_inverted_first_out")?,
+            loader.load("cch_backward_inverted_head")?,
+            loader.load("cch_backward_inverted_edge_ids")?,
+        );
+        check_len(backward_head.len(), backward_m, "cch_backward_head")?;
+        validate_direction(n, &backward_first_out, &backward_head, &backward_tail, &backward_inverted)?;
+        let backward_cch_edge_to_orig_arc: Vec<InRangeOption<EdgeId>> = loader.load("cch_backward_orig_arc")?;
+        check_len(backward_cch_edge_to_orig_arc.len(), backward_m, "cch_backward_orig_arc")?;
+
+        let elimination_tree: Vec<InRangeOption<NodeId>> = loader.load("cch_elimination_tree")?;
+        validate_elimination_tree(n, &elimination_tree)?;
+
+        let node_order = load_node_order(&loader, n)?;
+
+        Ok(DirectedCCH {
+            forward_first_out: forward_first_out.into(),
+            forward_head: forward_head.into(),
+            forward_tail: forward_tail.into(),
+            backward_first_out: backward_first_out.into(),
+            backward_head: backward_head.into(),
+            backward_tail: backward_tail.into(),
+            node_order,
+            forward_cch_edge_to_orig_arc,
+            backward_cch_edge_to_orig_arc,
+            elimination_tree,
+            forward_inverted,
+            backward_inverted,
+        })
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+fn check_len(len: usize, expected: usize, name: &str) -> Result<()> {
+    if len != expected {
+        return Err(invalid_data(format!("{} has length {} but {} was expected", name, len, expected)));
+    }
+    Ok(())
+}
+
+// returns number of nodes, number of forward arcs and number of backward arcs
+fn load_header(loader: &Loader, kind: u64) -> Result<(usize, usize, usize)> {
+    let header: Vec<u64> = loader.load("cch_header")?;
+    match header[..] {
+        [FORMAT_VERSION, header_kind, n, forward_m, backward_m] if header_kind == kind => Ok((n as usize, forward_m as usize, backward_m as usize)),
+        [FORMAT_VERSION, _, _, _, _] => Err(invalid_data("stored hierarchy is of the wrong kind (directed vs undirected)")),
+        [FORMAT_VERSION, ..] => Err(invalid_data(format!("malformed cch_header with {} fields", header.len()))),
+        [version, ..] => Err(invalid_data(format!("unsupported cch format version {}", version))),
+        [] => Err(invalid_data("empty cch_header")),
+    }
+}
+
+fn load_node_order(loader: &Loader, n: usize) -> Result<NodeOrder> {
+    let ranks: Vec<NodeId> = loader.load("ranks")?;
+    check_len(ranks.len(), n, "ranks")?;
+    let mut seen = vec![false; n];
+    for &rank in &ranks {
+        if rank as usize >= n || seen[rank as usize] {
+            return Err(invalid_data("ranks are not a permutation"));
+        }
+        seen[rank as usize] = true;
+    }
+    Ok(NodeOrder::from_ranks(ranks))
+}
+
+fn validate_first_out(first_out: &[EdgeId], n: usize, m: usize, name: &str) -> Result<()> {
+    check_len(first_out.len(), n + 1, name)?;
+    if first_out[0] != 0 || first_out[n] as usize != m || first_out.windows(2).any(|w| w[0] > w[1]) {
+        return Err(invalid_data(format!("{} is not a valid first_out array", name)));
+    }
+    Ok(())
+}
+
+// check a single direction of the chordal supergraph together with its tail and inverted graph
+fn validate_direction(n: usize, first_out: &[EdgeId], head: &[NodeId], tail: &[NodeId], inverted: &OwnedGraph) -> Result<()> {
+    let m = head.len();
+    validate_first_out(first_out, n, m, "cch first_out")?;
+    check_len(tail.len(), m, "cch tail")?;
+    for node in 0..n {
+        for edge in first_out[node] as usize..first_out[node + 1] as usize {
+            if tail[edge] as usize != node || head[edge] as usize <= node || head[edge] as usize >= n {
+                return Err(invalid_data("cch arcs have to go from lower to higher ranked nodes"));
+            }
+        }
+    }
+
+    validate_first_out(inverted.first_out(), n, m, "cch inverted first_out")?;
+    check_len(inverted.head().len(), m, "cch inverted head")?;
+    check_len(inverted.weight().len(), m, "cch inverted edge ids")?;
+    for node in 0..n as NodeId {
+        for Link { node: lower, weight: edge } in LinkIterable::<Link>::link_iter(inverted, node) {
+            if edge as usize >= m || head[edge as usize] != node || tail[edge as usize] != lower {
+                return Err(invalid_data("cch inverted graph does not match cch graph"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_elimination_tree(n: usize, elimination_tree: &[InRangeOption<NodeId>]) -> Result<()> {
+    check_len(elimination_tree.len(), n, "cch_elimination_tree")?;
+    for (node, parent) in elimination_tree.iter().enumerate() {
+        if let Some(parent) = parent.value() {
+            if parent as usize <= node || parent as usize >= n {
+                return Err(invalid_data("cch_elimination_tree parents have to be higher ranked"));
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,16 @@
+//! PHAST and RPHAST with elimination tree upward searches on a customized CCH.
+
+use super::query::stepped_elimination_tree::SteppedEliminationTree;
+use super::*;
+
+type CHGraph<'a> = FirstOutGraph<&'a [EdgeId], &'a [NodeId], Vec<Weight>>;
+
+pub type Server<'a> = crate::algo::phast::Server<SteppedEliminationTree<'a, CHGraph<'a>>, CHGraph<'a>, &'a NodeOrder>;
+
+impl<'a> Server<'a> {
+    pub fn new<CCH: CCHT>(customized: Customized<'a, CCH>) -> Self {
+        let cch = customized.cch;
+        let (forward, backward) = customized.into_ch_graphs();
+        Self::from_parts(SteppedEliminationTree::new(forward, cch.elimination_tree()), backward, cch.node_order())
+    }
+}
@@ -1,13 +1,21 @@
 //! CCH query based on elimination tree
 
 use super::*;
+use crate::as_slice::AsSlice;
+pub mod concurrent;
 pub mod stepped_elimination_tree;
 use stepped_elimination_tree::SteppedEliminationTree;
+mod alternatives;
+pub use alternatives::*;
+pub mod multi_metric;
+pub mod turns;
 
+/// Elimination tree query server.
+/// By default, the server owns the customized weights, but it can also work on weights shared with other servers, see `concurrent`.
 #[derive(Debug)]
-pub struct Server<'a, CCH> {
-    forward: SteppedEliminationTree<'a, FirstOutGraph<&'a [EdgeId], &'a [NodeId], Vec<Weight>>>,
-    backward: SteppedEliminationTree<'a, FirstOutGraph<&'a [EdgeId], &'a [NodeId], Vec<Weight>>>,
+pub struct Server<'a, CCH, Weights: AsSlice<Weight> = Vec<Weight>> {
+    forward: SteppedEliminationTree<'a, FirstOutGraph<&'a [EdgeId], &'a [NodeId], Weights>>,
+    backward: SteppedEliminationTree<'a, FirstOutGraph<&'a [EdgeId], &'a [NodeId], Weights>>,
     cch: &'a CCH,
     tentative_distance: Weight,
     meeting_node: NodeId,
@@ -15,24 +23,33 @@ pub struct Server<'a, CCH> {
 
 impl<'a, CCH: CCHT> Server<'a, CCH> {
     pub fn new(customized: Customized<'a, CCH>) -> Self {
-        let cch = customized.cch;
-        let (forward, backward) = customized.into_ch_graphs();
-        let forward = SteppedEliminationTree::new(forward, cch.elimination_tree());
-        let backward = SteppedEliminationTree::new(backward, cch.elimination_tree());
+        Self::with_weights(customized.cch, customized.upward, customized.downward)
+    }
+
+    // Update the metric using a new customization result
+    pub fn update(&mut self, mut customized: Customized<'a, CCH>) {
+        self.swap_weights(&mut customized.upward, &mut customized.downward);
+    }
+}
+
+impl<'a, CCH: CCHT, Weights: AsSlice<Weight>> Server<'a, CCH, Weights> {
+    fn with_weights(cch: &'a CCH, upward: Weights, downward: Weights) -> Self {
+        let forward = FirstOutGraph::new(cch.forward_first_out(), cch.forward_head(), upward);
+        let backward = FirstOutGraph::new(cch.backward_first_out(), cch.backward_head(), downward);
 
         Server {
-            forward,
-            backward,
+            forward: SteppedEliminationTree::new(forward, cch.elimination_tree()),
+            backward: SteppedEliminationTree::new(backward, cch.elimination_tree()),
             cch,
             tentative_distance: INFINITY,
             meeting_node: 0,
         }
     }
 
-    // Update the metric using a new customization result
-    pub fn update(&mut self, mut customized: Customized<'a, CCH>) {
-        self.forward.graph_mut().swap_weights(&mut customized.upward);
-        self.backward.graph_mut().swap_weights(&mut customized.downward);
+    // Exchange the metric weights with the given ones
+    fn swap_weights(&mut self, upward: &mut Weights, downward: &mut Weights) {
+        self.forward.graph_mut().swap_weights(upward);
+        self.backward.graph_mut().swap_weights(downward);
     }
 
     fn distance(&mut self, from: NodeId, to: NodeId) -> Option<Weight> {
@@ -67,7 +84,7 @@ impl<'a, CCH: CCHT> Server<'a, CCH> {
     fn path(&mut self) -> Vec<NodeId> {
         // unpack shortcuts so that parant pointers already point along the completely unpacked path
         self.forward.unpack_path(self.meeting_node, true, self.cch, self.backward.graph().weight());
-        self.backward.unpack_path(self.meeting_node, true, self.cch, self.forward.graph().weight());
+        self.backward.unpack_path(self.meeting_node, false, self.cch, self.forward.graph().weight());
 
         let mut path = Vec::new();
         path.push(self.meeting_node);
@@ -90,9 +107,9 @@ impl<'a, CCH: CCHT> Server<'a, CCH> {
     }
 }
 
-pub struct PathServerWrapper<'s, 'a, CCH>(&'s mut Server<'a, CCH>);
+pub struct PathServerWrapper<'s, 'a, CCH, Weights: AsSlice<Weight>>(&'s mut Server<'a, CCH, Weights>);
 
-impl<'s, 'a, CCH: CCHT> PathServer for PathServerWrapper<'s, 'a, CCH> {
+impl<'s, 'a, CCH: CCHT, Weights: AsSlice<Weight>> PathServer for PathServerWrapper<'s, 'a, CCH, Weights> {
     type NodeInfo = NodeId;
 
     fn path(&mut self) -> Vec<Self::NodeInfo> {
@@ -100,8 +117,8 @@ impl<'s, 'a, CCH: CCHT> PathServer for PathServerWrapper<'s, 'a, CCH> {
     }
 }
 
-impl<'s, 'a: 's, CCH: CCHT> QueryServer<'s> for Server<'a, CCH> {
-    type P = PathServerWrapper<'s, 'a, CCH>;
+impl<'s, 'a: 's, CCH: CCHT, Weights: AsSlice<Weight> + 's> QueryServer<'s> for Server<'a, CCH, Weights> {
+    type P = PathServerWrapper<'s, 'a, CCH, Weights>;
 
     fn query(&'s mut self, query: Query) -> Option<QueryResult<Self::P, Weight>> {
         self.distance(query.from, query.to)
@@ -0,0 +1,168 @@
+//! Alternative routes with the via-node approach.
+//!
+//! Every node in both elimination tree search spaces is a candidate via node.
+//! The up-down path through a candidate is unpacked and accepted as an alternative if it
+//! is not much longer than the shortest path (bounded stretch),
+//! does not share too much with the shortest path and previously accepted alternatives (limited sharing)
+//! and is locally optimal, which is checked with an additional query around the via node (T-test).
+
+use super::*;
+use std::collections::HashSet;
+
+/// Admissibility criteria for alternatives, all relative to the length of the shortest path.
+#[derive(Debug, Clone, Copy)]
+pub struct AlternativeParams {
+    /// Alternatives may be at most `1 + max_stretch` times longer than the shortest path
+    pub max_stretch: f64,
+    /// Length an alternative may share with the shortest path and all previously accepted alternatives
+    pub max_sharing: f64,
+    /// Length of the subpath around the via node in each direction which has to be a shortest path
+    pub local_optimality: f64,
+}
+
+impl Default for AlternativeParams {
+    fn default() -> Self {
+        AlternativeParams {
+            max_stretch: 0.25,
+            max_sharing: 0.8,
+            local_optimality: 0.25,
+        }
+    }
+}
+
+/// A single route with the nodes of the completely unpacked path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub distance: Weight,
+    pub path: Vec<NodeId>,
+}
+
+impl<'a, CCH: CCHT, Weights: AsSlice<Weight>> Server<'a, CCH, Weights> {
+    /// Compute the shortest path and up to `k` alternatives.
+    /// The shortest path is always the first route, alternatives are ordered by increasing length.
+    /// Returns an empty `Vec` if the target is not reachable.
+    pub fn alternatives(&mut self, query: Query, k: usize, params: AlternativeParams) -> Vec<Route> {
+        let shortest = match self.distance(query.from, query.to) {
+            Some(distance) => distance,
+            None => return Vec::new(),
+        };
+        let max_length = (f64::from(shortest) * (1.0 + params.max_stretch)) as Weight;
+        let max_sharing = (f64::from(shortest) * params.max_sharing) as Weight;
+        let local_optimality = (f64::from(shortest) * params.local_optimality) as Weight;
+
+        // common ancestors of source and target in the elimination tree are the candidates
+        let mut via_nodes = Vec::new();
+        let mut next = Some(self.backward.origin());
+        while let Some(node) = next {
+            let length = self.forward.tentative_distance(node) + self.backward.tentative_distance(node);
+            if length <= max_length {
+                via_nodes.push((length, node));
+            }
+            next = self.backward.parent(node).value();
+        }
+        via_nodes.sort_unstable();
+
+        // unpack all candidates first, the T-tests will overwrite the search spaces
+        let candidates: Vec<_> = via_nodes.iter().map(|&(_, via)| self.unpack_via(via)).collect();
+
+        let cch = self.cch;
+        let order = cch.node_order();
+        let mut routes = Vec::new();
+        let mut accepted_arcs = HashSet::new();
+
+        for (path, distances, via_idx) in candidates {
+            if routes.len() > k {
+                break;
+            }
+
+            // the first candidate is the shortest path
+            if !routes.is_empty() {
+                let mut seen = HashSet::new();
+                if !path.iter().all(|&node| seen.insert(node)) {
+                    continue;
+                }
+
+                let shared: Weight = path
+                    .windows(2)
+                    .zip(distances.windows(2))
+                    .filter(|(arc, _)| accepted_arcs.contains(&(arc[0], arc[1])))
+                    .map(|(_, arc_distances)| arc_distances[1] - arc_distances[0])
+                    .sum();
+                if shared > max_sharing {
+                    continue;
+                }
+
+                let via_distance = distances[via_idx];
+                let from_idx = (0..=via_idx).rev().find(|&idx| via_distance - distances[idx] >= local_optimality).unwrap_or(0);
+                let to_idx = (via_idx..path.len())
+                    .find(|&idx| distances[idx] - via_distance >= local_optimality)
+                    .unwrap_or(path.len() - 1);
+                if self.distance(order.node(path[from_idx]), order.node(path[to_idx])) != Some(distances[to_idx] - distances[from_idx]) {
+                    continue;
+                }
+            }
+
+            accepted_arcs.extend(path.windows(2).map(|arc| (arc[0], arc[1])));
+            routes.push(Route {
+                distance: *distances.last().unwrap(),
+                path: path.iter().map(|&node| order.node(node)).collect(),
+            });
+        }
+
+        routes
+    }
+
+    // Unpack the up-down path through `via` in rank space.
+    // Returns the nodes, the distance from the source for each node and the position of the via node in the path.
+    fn unpack_via(&self, via: NodeId) -> (Vec<NodeId>, Vec<Weight>, usize) {
+        let upward = self.forward.graph().weight();
+        let downward = self.backward.graph().weight();
+
+        let mut up = vec![via];
+        while *up.last().unwrap() != self.forward.origin() {
+            up.push(self.forward.predecessor(*up.last().unwrap()));
+        }
+        up.reverse();
+
+        let mut down = vec![via];
+        while *down.last().unwrap() != self.backward.origin() {
+            down.push(self.backward.predecessor(*down.last().unwrap()));
+        }
+
+        let mut path = vec![up[0]];
+        let mut distances = vec![0];
+        for arc in up.windows(2) {
+            let weight = self.forward.tentative_distance(arc[1]) - self.forward.tentative_distance(arc[0]);
+            unpack_arc_into(self.cch, (arc[0], arc[1], weight), upward, downward, &mut path, &mut distances);
+        }
+        let via_idx = path.len() - 1;
+        for arc in down.windows(2) {
+            let weight = self.backward.tentative_distance(arc[0]) - self.backward.tentative_distance(arc[1]);
+            unpack_arc_into(self.cch, (arc[0], arc[1], weight), upward, downward, &mut path, &mut distances);
+        }
+
+        (path, distances, via_idx)
+    }
+}
+
+// Completely unpack an arc and append its nodes except for the tail to `path`.
+fn unpack_arc_into<CCH: CCHT>(
+    cch: &CCH,
+    arc: (NodeId, NodeId, Weight),
+    upward: &[Weight],
+    downward: &[Weight],
+    path: &mut Vec<NodeId>,
+    distances: &mut Vec<Weight>,
+) {
+    let mut stack = vec![arc];
+    while let Some((from, to, weight)) = stack.pop() {
+        if let Some((middle, first_weight, second_weight)) = cch.unpack_arc(from, to, weight, upward, downward) {
+            stack.push((middle, to, second_weight));
+            stack.push((from, middle, first_weight));
+        } else {
+            let distance = distances.last().unwrap() + weight;
+            path.push(to);
+            distances.push(distance);
+        }
+    }
+}
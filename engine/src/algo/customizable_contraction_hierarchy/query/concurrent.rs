@@ -0,0 +1,79 @@
+//! Concurrent CCH queries on a shared metric.
+//!
+//! The customized weights are stored once in a `SharedMetric` and shared between all threads through `Arc`s.
+//! Each thread only needs its own `Workspace` with the elimination tree search data.
+//! New customizations can be swapped in at any time (RCU style):
+//! Queries which already started finish on the previous weights, queries started afterwards use the new ones.
+//! The previous weights are freed once all workspaces have moved on to the new ones.
+
+use super::*;
+use std::sync::{Arc, RwLock};
+
+type SharedWeights = Arc<Vec<Weight>>;
+
+/// Customized weights which can be queried and updated concurrently.
+#[derive(Debug)]
+pub struct SharedMetric<'a, CCH> {
+    cch: &'a CCH,
+    // upward and downward weights of the most recent customization
+    current: RwLock<(SharedWeights, SharedWeights)>,
+}
+
+impl<'a, CCH: CCHT> SharedMetric<'a, CCH> {
+    pub fn new(customized: Customized<'a, CCH>) -> Self {
+        SharedMetric {
+            cch: customized.cch,
+            current: RwLock::new((Arc::new(customized.upward), Arc::new(customized.downward))),
+        }
+    }
+
+    /// Replace the weights with a new customization result without waiting for running queries.
+    /// Panics if the metric was customized for a different CCH.
+    pub fn update(&self, customized: Customized<'a, CCH>) {
+        assert!(std::ptr::eq(customized.cch, self.cch), "metric was customized for a different CCH");
+        let weights = (Arc::new(customized.upward), Arc::new(customized.downward));
+        *self.current.write().unwrap() = weights;
+    }
+
+    /// Copy of the most recent customization, for example as starting point for a partial customization.
+    pub fn customized(&self) -> Customized<'a, CCH> {
+        let (upward, downward) = self.current();
+        Customized {
+            cch: self.cch,
+            upward: upward.to_vec(),
+            downward: downward.to_vec(),
+        }
+    }
+
+    /// Create a new query workspace, usually one per thread.
+    pub fn workspace(&self) -> Workspace<'a, CCH> {
+        let (upward, downward) = self.current();
+        Workspace {
+            server: Server::with_weights(self.cch, upward, downward),
+        }
+    }
+
+    // The lock is only held while copying the pointers, so neither readers nor writers ever wait for a query or a customization.
+    fn current(&self) -> (SharedWeights, SharedWeights) {
+        let current = self.current.read().unwrap();
+        (current.0.clone(), current.1.clone())
+    }
+}
+
+/// Query data of one thread.
+/// A workspace can be used for all metrics customized for the same CCH.
+#[derive(Debug)]
+pub struct Workspace<'a, CCH> {
+    server: Server<'a, CCH, SharedWeights>,
+}
+
+impl<'a, CCH: CCHT> Workspace<'a, CCH> {
+    /// Get a query server on the most recent weights of `metric`.
+    /// Panics if the metric belongs to a different CCH than the workspace.
+    pub fn server(&mut self, metric: &SharedMetric<'a, CCH>) -> &mut Server<'a, CCH, SharedWeights> {
+        assert!(std::ptr::eq(metric.cch, self.server.cch), "metric belongs to a different CCH");
+        let (mut upward, mut downward) = metric.current();
+        self.server.swap_weights(&mut upward, &mut downward);
+        &mut self.server
+    }
+}
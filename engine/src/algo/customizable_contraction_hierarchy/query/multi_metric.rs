@@ -0,0 +1,66 @@
+//! Query server for several named metrics customized on the same CCH.
+//!
+//! All metrics share the elimination tree query workspaces of a single `Server`.
+//! Selecting a different metric swaps its weights into the server, which takes constant time.
+
+use super::*;
+
+pub struct MultiMetricServer<'a, CCH> {
+    server: Server<'a, CCH>,
+    // name and upward and downward weights of each metric,
+    // the weights of the active metric live in the server and its entry holds empty vectors
+    metrics: Vec<(String, Vec<Weight>, Vec<Weight>)>,
+    active: usize,
+}
+
+impl<'a, CCH: CCHT> MultiMetricServer<'a, CCH> {
+    /// The first metric will be active initially.
+    /// Panics if `metrics` is empty or if the metrics were customized for different CCHs.
+    pub fn new(metrics: Vec<(String, Customized<'a, CCH>)>) -> Self {
+        let mut metrics = metrics.into_iter();
+        let (name, customized) = metrics.next().expect("at least one metric required");
+        let mut server = MultiMetricServer {
+            server: Server::new(customized),
+            metrics: vec![(name, Vec::new(), Vec::new())],
+            active: 0,
+        };
+        for (name, customized) in metrics {
+            server.insert(name, customized);
+        }
+        server
+    }
+
+    /// Add a new metric or replace the weights of an existing one.
+    /// Panics if the metric was customized for a different CCH.
+    pub fn insert(&mut self, name: String, customized: Customized<'a, CCH>) {
+        assert!(std::ptr::eq(customized.cch, self.server.cch), "metric was customized for a different CCH");
+        let Customized { mut upward, mut downward, .. } = customized;
+        match self.metrics.iter().position(|(metric, _, _)| *metric == name) {
+            Some(idx) if idx == self.active => self.server.swap_weights(&mut upward, &mut downward),
+            Some(idx) => {
+                self.metrics[idx].1 = upward;
+                self.metrics[idx].2 = downward;
+            }
+            None => self.metrics.push((name, upward, downward)),
+        }
+    }
+
+    pub fn metric_names(&self) -> impl Iterator<Item = &str> {
+        self.metrics.iter().map(|(name, _, _)| name.as_str())
+    }
+
+    /// Make `name` the active metric and return the server to query it.
+    /// `None` if there is no metric with this name.
+    pub fn select(&mut self, name: &str) -> Option<&mut Server<'a, CCH>> {
+        let idx = self.metrics.iter().position(|(metric, _, _)| metric == name)?;
+        if idx != self.active {
+            let (_, upward, downward) = &mut self.metrics[idx];
+            let (mut upward, mut downward) = (std::mem::take(upward), std::mem::take(downward));
+            self.server.swap_weights(&mut upward, &mut downward);
+            self.metrics[self.active].1 = upward;
+            self.metrics[self.active].2 = downward;
+            self.active = idx;
+        }
+        Some(&mut self.server)
+    }
+}
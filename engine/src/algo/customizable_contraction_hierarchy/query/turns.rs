@@ -0,0 +1,148 @@
+//! Queries between nodes or arcs of the original graph on a CCH of the turn expanded graph.
+//!
+//! Paths are returned as sequences of arcs of the original graph, which can be translated to nodes with `TurnExpansion::node_path`.
+
+use super::*;
+use crate::datastr::graph::turn_expansion::TurnExpansion;
+use crate::util::in_range_option::InRangeOption;
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BinaryHeap, HashMap},
+};
+
+/// Query server for routing with turn restrictions and turn costs.
+#[derive(Debug)]
+pub struct Server<'a, CCH> {
+    server: super::Server<'a, CCH>,
+    expansion: &'a TurnExpansion,
+    weight: Vec<Weight>,
+}
+
+impl<'a, CCH: CCHT> Server<'a, CCH> {
+    /// Create a server from a customized CCH of the turn expanded graph.
+    /// `weight` are the arc weights of the original graph, needed to account for the last arc of a route.
+    pub fn new(customized: Customized<'a, CCH>, expansion: &'a TurnExpansion, weight: Vec<Weight>) -> Self {
+        Server {
+            server: super::Server::new(customized),
+            expansion,
+            weight,
+        }
+    }
+
+    /// Update the metric using a new customization result and the corresponding original arc weights.
+    pub fn update(&mut self, customized: Customized<'a, CCH>, weight: Vec<Weight>) {
+        self.server.update(customized);
+        self.weight = weight;
+    }
+
+    /// Shortest route between two nodes of the original graph.
+    /// Returns the distance and the traversed arcs.
+    pub fn node_query(&mut self, from: NodeId, to: NodeId) -> Option<(Weight, Vec<EdgeId>)> {
+        if from == to {
+            return Some((0, Vec::new()));
+        }
+        let (first, last) = self.best_edge_nodes(from, to)?;
+        let last_weight = self.weight[last as usize];
+        self.arc_query(first, last).map(|(distance, path)| (distance + last_weight, path))
+    }
+
+    /// Shortest route from the start of `from_arc` to the start of `to_arc`.
+    /// Returns the distance and the traversed arcs, including both `from_arc` and `to_arc`.
+    pub fn arc_query(&mut self, from_arc: EdgeId, to_arc: EdgeId) -> Option<(Weight, Vec<EdgeId>)> {
+        self.server
+            .query(Query { from: from_arc, to: to_arc })
+            .as_mut()
+            .map(|result| (result.distance(), result.path()))
+    }
+
+    /// Shortest route and up to `k` alternatives between two nodes of the original graph, see `query::Server::alternatives`.
+    /// The paths of the routes are sequences of arcs.
+    pub fn alternatives(&mut self, from: NodeId, to: NodeId, k: usize, params: AlternativeParams) -> Vec<Route> {
+        if from == to {
+            return vec![Route { distance: 0, path: Vec::new() }];
+        }
+        let (first, last) = match self.best_edge_nodes(from, to) {
+            Some(edge_nodes) => edge_nodes,
+            None => return Vec::new(),
+        };
+        let last_weight = self.weight[last as usize];
+        let mut routes = self.server.alternatives(Query { from: first, to: last }, k, params);
+        for route in &mut routes {
+            route.distance += last_weight;
+        }
+        routes
+    }
+
+    // Routes between nodes may start with any outgoing and end with any incoming arc.
+    // Instead of querying all combinations, the elimination tree searches are started from all of them at once
+    // and the best pair is read off the sources through which the meeting node was reached.
+    fn best_edge_nodes(&mut self, from: NodeId, to: NodeId) -> Option<(EdgeId, EdgeId)> {
+        let order = self.server.cch.node_order();
+        let elimination_tree = self.server.cch.elimination_tree();
+        let forward = elimination_tree_search(
+            self.server.forward.graph(),
+            elimination_tree,
+            self.expansion.outgoing(from).map(|first| (order.rank(first), 0)),
+        );
+        let weight = &self.weight;
+        let backward = elimination_tree_search(
+            self.server.backward.graph(),
+            elimination_tree,
+            self.expansion.incoming(to).iter().map(|&last| (order.rank(last), weight[last as usize])),
+        );
+
+        forward
+            .iter()
+            .filter_map(|(node, &(forward_distance, first))| {
+                backward
+                    .get(node)
+                    .map(|&(backward_distance, last)| (forward_distance + backward_distance, first, last))
+            })
+            .filter(|&(distance, _, _)| distance < INFINITY)
+            .min()
+            .map(|(_, first, last)| (order.node(first), order.node(last)))
+    }
+}
+
+// Elimination tree search started from several nodes with initial distances.
+// Returns for every node on the tree paths from the sources to the root the tentative distance and the source it was reached from.
+// Arcs only lead to ancestors in the elimination tree, so settling the nodes by ascending rank processes each node after all its predecessors.
+fn elimination_tree_search<G: for<'a> LinkIterGraph<'a>>(
+    graph: &G,
+    elimination_tree: &[InRangeOption<NodeId>],
+    sources: impl Iterator<Item = (NodeId, Weight)>,
+) -> HashMap<NodeId, (Weight, NodeId)> {
+    let mut labels: HashMap<NodeId, (Weight, NodeId)> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    for (node, distance) in sources {
+        relax(&mut labels, &mut queue, node, (distance, node));
+    }
+    while let Some(Reverse(node)) = queue.pop() {
+        let (distance, source) = labels[&node];
+        if let Some(parent) = elimination_tree[node as usize].value() {
+            relax(&mut labels, &mut queue, parent, (INFINITY, parent));
+        }
+        if distance < INFINITY {
+            for Link { node: head, weight } in graph.link_iter(node) {
+                relax(&mut labels, &mut queue, head, (distance + weight, source));
+            }
+        }
+    }
+
+    labels
+}
+
+// Improve the label of `node` and queue it when it is reached for the first time.
+fn relax(labels: &mut HashMap<NodeId, (Weight, NodeId)>, queue: &mut BinaryHeap<Reverse<NodeId>>, node: NodeId, label: (Weight, NodeId)) {
+    match labels.entry(node) {
+        Entry::Occupied(mut entry) => {
+            if label.0 < entry.get().0 {
+                entry.insert(label);
+            }
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(label);
+            queue.push(Reverse(node));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Weighted-sum scalarization of two metrics with one CCH customization per linear combination.
+//!
+//! The shortest path for each combination is Pareto-optimal with respect to both metrics,
+//! but only routes on the convex hull of the Pareto set (supported solutions) can be found this way.
+//! Use `dijkstra::query::pareto_dijkstra` for the complete Pareto set.
+
+use super::*;
+use crate::{algo::dijkstra::query::pareto_dijkstra::ParetoRoute, as_slice::AsSlice, datastr::graph::bi_criteria::BiCriteriaGraph};
+
+pub struct Server<'a, F, H, W>
+where
+    F: AsSlice<EdgeId>,
+    H: AsSlice<NodeId>,
+    W: AsSlice<Weight>,
+{
+    graph: &'a BiCriteriaGraph<F, H, W>,
+    // factors of the first and second metric and the server for the customized combination
+    servers: Vec<((Weight, Weight), query::Server<'a, CCH>)>,
+}
+
+impl<'a, F, H, W> Server<'a, F, H, W>
+where
+    F: AsSlice<EdgeId>,
+    H: AsSlice<NodeId>,
+    W: AsSlice<Weight>,
+{
+    /// Customize `cch` once for each `(first_factor, second_factor)` combination.
+    /// Both factors should be positive, with a zero factor, ties in the other metric are broken arbitrarily
+    /// and the resulting route may be dominated.
+    pub fn new(cch: &'a CCH, graph: &'a BiCriteriaGraph<F, H, W>, combinations: &[(Weight, Weight)]) -> Self {
+        let servers = combinations
+            .iter()
+            .map(|&(first_factor, second_factor)| {
+                let customized = customize(cch, &graph.combined(first_factor, second_factor));
+                ((first_factor, second_factor), query::Server::new(customized))
+            })
+            .collect();
+
+        Server { graph, servers }
+    }
+
+    /// Routes optimal for at least one of the combinations.
+    /// Dominated and duplicate routes are removed, the rest is ordered by increasing first and decreasing second criterion.
+    pub fn query(&mut self, query: Query) -> Vec<ParetoRoute> {
+        let graph = self.graph;
+        let mut routes: Vec<ParetoRoute> = Vec::with_capacity(self.servers.len());
+
+        for ((first_factor, second_factor), server) in &mut self.servers {
+            let path = match QueryServer::query(server, query) {
+                Some(mut result) => result.path(),
+                None => return Vec::new(),
+            };
+
+            // sum up both metrics along the cheapest of each set of parallel arcs
+            let (mut first, mut second) = (0, 0);
+            for nodes in path.windows(2) {
+                let arc = graph
+                    .graph()
+                    .neighbor_edge_indices(nodes[0])
+                    .filter(|&arc| graph.graph().head()[arc as usize] == nodes[1])
+                    .min_by_key(|&arc| {
+                        u64::from(*first_factor) * u64::from(graph.first_weight()[arc as usize])
+                            + u64::from(*second_factor) * u64::from(graph.second_weight()[arc as usize])
+                    })
+                    .unwrap();
+                first += graph.first_weight()[arc as usize];
+                second += graph.second_weight()[arc as usize];
+            }
+
+            routes.push(ParetoRoute { first, second, path });
+        }
+
+        routes.sort_by_key(|route| (route.first, route.second));
+        let mut pareto_routes: Vec<ParetoRoute> = Vec::with_capacity(routes.len());
+        for route in routes {
+            if pareto_routes.last().map(|last| last.second > route.second).unwrap_or(true) {
+                pareto_routes.push(route);
+            }
+        }
+        pareto_routes
+    }
+}
@@ -5,4 +5,5 @@ use super::*;
 pub mod bidirectional_dijkstra;
 pub mod dijkstra;
 pub mod floating_td_dijkstra;
+pub mod pareto_dijkstra;
 pub mod td_dijkstra;
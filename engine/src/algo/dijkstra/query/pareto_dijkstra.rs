@@ -0,0 +1,219 @@
+//! Multi-criteria Dijkstra computing all Pareto-optimal routes for two metrics, for example travel time and distance.
+//!
+//! This is a label-setting algorithm: the queue contains individual `(first, second)` labels in lexicographic order.
+//! All labels of a node which were popped earlier are lexicographically smaller, so a popped label
+//! which is not dominated by any of them is Pareto-optimal and becomes permanent.
+//! Each label is settled at most once and only permanent labels are propagated along the outgoing arcs.
+//! Labels dominated by the permanent labels of their node or of the target are discarded,
+//! both when they are created and when they are popped.
+
+use super::*;
+use crate::datastr::{graph::bi_criteria::BiCriteriaGraph, timestamped_vector::TimestampedVector};
+use crate::{as_slice::AsSlice, report::*};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// A single non-dominated pair, with the arc it was reached through (`None` for the source).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParetoLabel {
+    pub first: Weight,
+    pub second: Weight,
+    pub predecessor_arc: Option<EdgeId>,
+}
+
+impl ParetoLabel {
+    fn dominates(&self, other: &ParetoLabel) -> bool {
+        self.first <= other.first && self.second <= other.second
+    }
+}
+
+/// Set of mutually non-dominated labels, ordered by increasing first and strictly decreasing second criterion.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParetoSet(Vec<ParetoLabel>);
+
+impl ParetoSet {
+    pub fn labels(&self) -> &[ParetoLabel] {
+        &self.0
+    }
+
+    /// Is the label dominated by (or equal to) a label in the set?
+    pub fn dominates(&self, label: &ParetoLabel) -> bool {
+        self.0.iter().any(|other| other.dominates(label))
+    }
+
+    /// Insert a label unless it is dominated by (or equal to) a label already in the set.
+    /// Removes all labels dominated by the new one.
+    /// Returns whether the label was inserted.
+    pub fn insert(&mut self, label: ParetoLabel) -> bool {
+        if self.dominates(&label) {
+            return false;
+        }
+        self.0.retain(|other| !label.dominates(other));
+        let position = self.0.partition_point(|other| other.first < label.first);
+        self.0.insert(position, label);
+        true
+    }
+}
+
+/// A Pareto-optimal route.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParetoRoute {
+    pub first: Weight,
+    pub second: Weight,
+    pub path: Vec<NodeId>,
+}
+
+// tentative label as `(first, second, node, predecessor_arc)`, ordered lexicographically
+type QueueEntry = (Weight, Weight, NodeId, Option<EdgeId>);
+
+pub struct Server<F, H, W>
+where
+    F: AsSlice<EdgeId>,
+    H: AsSlice<NodeId>,
+    W: AsSlice<Weight>,
+{
+    graph: BiCriteriaGraph<F, H, W>,
+    // permanent labels of each node
+    settled: TimestampedVector<ParetoSet>,
+    queue: BinaryHeap<Reverse<QueueEntry>>,
+}
+
+impl<F, H, W> Server<F, H, W>
+where
+    F: AsSlice<EdgeId>,
+    H: AsSlice<NodeId>,
+    W: AsSlice<Weight>,
+{
+    pub fn new(graph: BiCriteriaGraph<F, H, W>) -> Self {
+        let n = graph.num_nodes();
+        Server {
+            graph,
+            settled: TimestampedVector::new(n, ParetoSet::default()),
+            queue: BinaryHeap::new(),
+        }
+    }
+
+    /// All Pareto-optimal routes from `query.from` to `query.to`, ordered by increasing first and decreasing second criterion.
+    /// Empty if the target is not reachable.
+    pub fn query(&mut self, query: Query) -> Vec<ParetoRoute> {
+        report!("algo", "Pareto Dijkstra Query");
+        self.settled.reset();
+        self.queue.clear();
+        self.queue.push(Reverse((0, 0, query.from, None)));
+
+        let mut num_queue_pops = 0;
+        let mut num_queue_pushs = 1;
+        let mut num_relaxed_arcs = 0;
+
+        while let Some(Reverse((first, second, node, predecessor_arc))) = self.queue.pop() {
+            num_queue_pops += 1;
+            let label = ParetoLabel {
+                first,
+                second,
+                predecessor_arc,
+            };
+            if self.settled[node as usize].dominates(&label) || self.settled[query.to as usize].dominates(&label) {
+                continue;
+            }
+            self.settled[node as usize].insert(label);
+            // all routes continuing from the target are dominated by the label just settled there
+            if node == query.to {
+                continue;
+            }
+
+            for (head, arc) in LinkIterable::<(NodeId, EdgeId)>::link_iter(&self.graph, node) {
+                num_relaxed_arcs += 1;
+                let linked = ParetoLabel {
+                    first: first + self.graph.first_weight()[arc as usize],
+                    second: second + self.graph.second_weight()[arc as usize],
+                    predecessor_arc: Some(arc),
+                };
+                if linked.first >= INFINITY || linked.second >= INFINITY {
+                    continue;
+                }
+                if self.settled[head as usize].dominates(&linked) || self.settled[query.to as usize].dominates(&linked) {
+                    continue;
+                }
+                self.queue.push(Reverse((linked.first, linked.second, head, linked.predecessor_arc)));
+                num_queue_pushs += 1;
+            }
+        }
+
+        report!("num_queue_pops", num_queue_pops);
+        report!("num_queue_pushs", num_queue_pushs);
+        report!("num_relaxed_arcs", num_relaxed_arcs);
+
+        self.settled[query.to as usize]
+            .labels()
+            .iter()
+            .map(|label| ParetoRoute {
+                first: label.first,
+                second: label.second,
+                path: self.path(query.to, *label),
+            })
+            .collect()
+    }
+
+    // Follow the predecessor arcs back to the source.
+    // Predecessors are identified by their first criterion, which is unique within each set.
+    fn path(&self, node: NodeId, mut label: ParetoLabel) -> Vec<NodeId> {
+        let mut path = vec![node];
+
+        while let Some(arc) = label.predecessor_arc {
+            let tail = self.graph.tail(arc);
+            let first = label.first - self.graph.first_weight()[arc as usize];
+            label = *self.settled[tail as usize].labels().iter().find(|label| label.first == first).unwrap();
+            path.push(tail);
+        }
+
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(first: Weight, second: Weight) -> ParetoLabel {
+        ParetoLabel {
+            first,
+            second,
+            predecessor_arc: None,
+        }
+    }
+
+    fn pairs(set: &ParetoSet) -> Vec<(Weight, Weight)> {
+        set.labels().iter().map(|label| (label.first, label.second)).collect()
+    }
+
+    #[test]
+    fn insert_keeps_labels_sorted() {
+        let mut set = ParetoSet::default();
+        assert!(set.insert(label(5, 5)));
+        assert!(set.insert(label(1, 9)));
+        assert!(set.insert(label(9, 1)));
+        assert_eq!(pairs(&set), vec![(1, 9), (5, 5), (9, 1)]);
+    }
+
+    #[test]
+    fn insert_rejects_dominated_and_equal_labels() {
+        let mut set = ParetoSet::default();
+        assert!(set.insert(label(3, 3)));
+        assert!(!set.insert(label(4, 4)));
+        assert!(!set.insert(label(3, 5)));
+        assert!(!set.insert(label(3, 3)));
+        assert_eq!(pairs(&set), vec![(3, 3)]);
+    }
+
+    #[test]
+    fn insert_removes_dominated_labels() {
+        let mut set = ParetoSet::default();
+        set.insert(label(2, 8));
+        set.insert(label(4, 6));
+        set.insert(label(6, 4));
+        assert!(set.insert(label(3, 5)));
+        assert_eq!(pairs(&set), vec![(2, 8), (3, 5), (6, 4)]);
+        assert!(set.insert(label(1, 1)));
+        assert_eq!(pairs(&set), vec![(1, 1)]);
+    }
+}
@@ -0,0 +1,231 @@
+//! Isochrones, that is the set of everything reachable from a source within a given time budget.
+//!
+//! An isochrone consists of the reached nodes and the boundary points on arcs which can only partially be traversed within the budget.
+//! Boundary points are interpolated linearly between the coordinates of tail and head.
+//! Optionally, a concave hull around all reached positions can be computed as a polygon.
+
+use super::*;
+use crate::algo::dijkstra::{generic_dijkstra::GenericDijkstra, query::td_dijkstra::TDDijkstraOps};
+use crate::datastr::graph::time_dependent::{TDGraph, Timestamp};
+
+/// A point where the time budget runs out while traversing an arc.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundaryPoint {
+    pub edge: EdgeId,
+    /// Fraction of the arc which can be traversed within the budget
+    pub fraction: f32,
+    pub latitude: f32,
+    pub longitude: f32,
+}
+
+/// Result of an isochrone query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Isochrone {
+    pub reached_nodes: Vec<NodeId>,
+    pub boundary: Vec<BoundaryPoint>,
+}
+
+impl Isochrone {
+    /// All reached positions, that is the coordinates of the reached nodes and the boundary points.
+    pub fn positions<'s>(&'s self, lat: &'s [f32], lng: &'s [f32]) -> impl Iterator<Item = (f32, f32)> + 's {
+        self.reached_nodes
+            .iter()
+            .map(move |&node| (lat[node as usize], lng[node as usize]))
+            .chain(self.boundary.iter().map(|point| (point.latitude, point.longitude)))
+    }
+
+    /// Concave hull polygon around all reached positions, see `concave_hull`.
+    pub fn polygon(&self, lat: &[f32], lng: &[f32], max_edge_length: f32) -> Vec<(f32, f32)> {
+        concave_hull(&self.positions(lat, lng).collect::<Vec<_>>(), max_edge_length)
+    }
+}
+
+/// Isochrone for static distances, e.g. obtained from a one-to-all query with `phast::Server` on a CCH.
+/// `distance` has to yield the exact distance for each node of `graph`.
+pub fn static_isochrone<G: RandomLinkAccessGraph>(graph: &G, distance: impl Fn(NodeId) -> Weight, budget: Weight, lat: &[f32], lng: &[f32]) -> Isochrone {
+    let reached_nodes: Vec<NodeId> = (0..graph.num_nodes() as NodeId).filter(|&node| distance(node) <= budget).collect();
+    let boundary = boundary(graph, &reached_nodes, &distance, |edge, _| graph.link(edge).weight, budget, lat, lng);
+    Isochrone { reached_nodes, boundary }
+}
+
+/// Time-dependent isochrone for a departure at `departure` from `from`.
+/// The search is stopped once the budget is exceeded, so only the part of the graph within the isochrone is explored.
+pub fn td_isochrone(
+    dijkstra: &mut GenericDijkstra<TDDijkstraOps, TDGraph>,
+    from: NodeId,
+    departure: Timestamp,
+    budget: Weight,
+    lat: &[f32],
+    lng: &[f32],
+) -> Isochrone {
+    let n = dijkstra.graph().num_nodes() as NodeId;
+    dijkstra.initialize_query(TDQuery { from, to: n, departure });
+
+    let mut reached_nodes = Vec::new();
+    while let Some(node) = dijkstra.next() {
+        if *dijkstra.tentative_distance(node) - departure > budget {
+            break;
+        }
+        reached_nodes.push(node);
+    }
+
+    let dijkstra = &*dijkstra;
+    let graph = dijkstra.graph();
+    let boundary = boundary(
+        graph,
+        &reached_nodes,
+        |node| *dijkstra.tentative_distance(node) - departure,
+        |edge, distance| graph.travel_time_function(edge).eval(departure + distance),
+        budget,
+        lat,
+        lng,
+    );
+    Isochrone { reached_nodes, boundary }
+}
+
+// Cut all arcs leaving the reached nodes which can not completely be traversed within the budget.
+fn boundary<G: RandomLinkAccessGraph>(
+    graph: &G,
+    reached_nodes: &[NodeId],
+    distance: impl Fn(NodeId) -> Weight,
+    travel_time: impl Fn(EdgeId, Weight) -> Weight,
+    budget: Weight,
+    lat: &[f32],
+    lng: &[f32],
+) -> Vec<BoundaryPoint> {
+    let mut boundary = Vec::new();
+    for &node in reached_nodes {
+        let node_distance = distance(node);
+        for edge in graph.neighbor_edge_indices(node) {
+            let arc_travel_time = travel_time(edge, node_distance);
+            if arc_travel_time < INFINITY && node_distance + arc_travel_time > budget {
+                let head = graph.link(edge).node as usize;
+                let fraction = (budget - node_distance) as f32 / arc_travel_time as f32;
+                boundary.push(BoundaryPoint {
+                    edge,
+                    fraction,
+                    latitude: lat[node as usize] + fraction * (lat[head] - lat[node as usize]),
+                    longitude: lng[node as usize] + fraction * (lng[head] - lng[node as usize]),
+                });
+            }
+        }
+    }
+    boundary
+}
+
+/// Concave hull of a point set.
+/// Starts with the convex hull and iteratively digs into hull edges longer than `max_edge_length`
+/// by replacing them with two edges to the closest inner point, as long as this does not cause self intersections.
+/// Quadratic running time in the worst case, intended for the moderately sized point sets of isochrones.
+/// Returns the hull in counter clockwise order without repeating the first point.
+pub fn concave_hull(points: &[(f32, f32)], max_edge_length: f32) -> Vec<(f32, f32)> {
+    let points: Vec<(f64, f64)> = points.iter().map(|&(x, y)| (f64::from(x), f64::from(y))).collect();
+    let mut hull = convex_hull(&points);
+    if hull.len() < 3 {
+        return hull.iter().map(|&idx| to_f32(points[idx])).collect();
+    }
+
+    let mut on_hull = vec![false; points.len()];
+    for &idx in &hull {
+        on_hull[idx] = true;
+    }
+
+    let max_edge_length = f64::from(max_edge_length);
+    let mut i = 0;
+    while i < hull.len() {
+        let a = points[hull[i]];
+        let b = points[hull[(i + 1) % hull.len()]];
+        let edge_length = dist(a, b);
+
+        let mut best: Option<(usize, f64)> = None;
+        if edge_length > max_edge_length {
+            for (idx, &p) in points.iter().enumerate() {
+                if on_hull[idx] || dist(a, p) >= edge_length || dist(p, b) >= edge_length {
+                    continue;
+                }
+                if let Some(segment_dist) = segment_distance(a, b, p) {
+                    if best.map(|(_, best_dist)| segment_dist < best_dist).unwrap_or(true) && !intersects_hull(&points, &hull, i, a, b, p) {
+                        best = Some((idx, segment_dist));
+                    }
+                }
+            }
+        }
+
+        if let Some((idx, _)) = best {
+            // dig in and check the new first edge again
+            on_hull[idx] = true;
+            hull.insert(i + 1, idx);
+        } else {
+            i += 1;
+        }
+    }
+
+    hull.iter().map(|&idx| to_f32(points[idx])).collect()
+}
+
+fn to_f32((x, y): (f64, f64)) -> (f32, f32) {
+    (x as f32, y as f32)
+}
+
+fn dist(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+fn cross(o: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+// Andrews monotone chain, returns indices in counter clockwise order
+fn convex_hull(points: &[(f64, f64)]) -> Vec<usize> {
+    let mut sorted: Vec<usize> = (0..points.len()).collect();
+    sorted.sort_by(|&a, &b| points[a].0.total_cmp(&points[b].0).then(points[a].1.total_cmp(&points[b].1)));
+    sorted.dedup_by(|a, b| points[*a] == points[*b]);
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    let mut hull: Vec<usize> = Vec::with_capacity(2 * sorted.len());
+    for pass in 0..2 {
+        let lower_len = hull.len();
+        for &idx in &sorted {
+            while hull.len() >= lower_len + 2 && cross(points[hull[hull.len() - 2]], points[hull[hull.len() - 1]], points[idx]) <= 0.0 {
+                hull.pop();
+            }
+            hull.push(idx);
+        }
+        // the last point of each chain is the first of the other one
+        hull.pop();
+        if pass == 0 {
+            sorted.reverse();
+        }
+    }
+    hull
+}
+
+// Distance of `p` to the segment from `a` to `b`, if the projection of `p` lies within the segment.
+// Restricting to such points makes sure that no other point is closer to the segment and inside the triangle `a`, `p`, `b`.
+fn segment_distance(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> Option<f64> {
+    let length_squared = (b.0 - a.0).powi(2) + (b.1 - a.1).powi(2);
+    let t = ((p.0 - a.0) * (b.0 - a.0) + (p.1 - a.1) * (b.1 - a.1)) / length_squared;
+    if t <= 0.0 || t >= 1.0 {
+        return None;
+    }
+    Some(cross(a, b, p).abs() / length_squared.sqrt())
+}
+
+fn segments_intersect(p1: (f64, f64), p2: (f64, f64), q1: (f64, f64), q2: (f64, f64)) -> bool {
+    let d1 = cross(q1, q2, p1);
+    let d2 = cross(q1, q2, p2);
+    let d3 = cross(p1, p2, q1);
+    let d4 = cross(p1, p2, q2);
+    ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0)) && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+}
+
+// Would replacing hull edge `edge` (from `a` to `b`) by the edges `a` - `p` and `p` - `b` cause an intersection with any other hull edge?
+fn intersects_hull(points: &[(f64, f64)], hull: &[usize], edge: usize, a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> bool {
+    (0..hull.len()).filter(|&other| other != edge).any(|other| {
+        let q1 = points[hull[other]];
+        let q2 = points[hull[(other + 1) % hull.len()]];
+        segments_intersect(a, p, q1, q2) || segments_intersect(p, b, q1, q2)
+    })
+}
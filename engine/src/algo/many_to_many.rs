@@ -0,0 +1,75 @@
+//! Bucket based many-to-many queries on hierarchies.
+//!
+//! First, an upward search is run from each target in the backward graph.
+//! Each node settled by these searches gets a bucket entry with the target and the distance.
+//! Then, an upward search is run from each source in the forward graph, scanning the buckets of all settled nodes.
+//! Both phases are run in parallel over the targets and sources respectively.
+//! The actual upward searches are specific to the hierarchy (CH or CCH) and plugged in through the `UpwardSearch` trait.
+
+use super::*;
+use crate::datastr::graph::first_out_graph::degrees_to_first_out;
+use rayon::prelude::*;
+
+/// A search which only explores higher ranked nodes, as used by the query phase of (C)CHs.
+pub trait UpwardSearch {
+    /// Search from `from` and call `settled` with every reached node and its distance.
+    /// Nodes and distances are in the rank space of the hierarchy.
+    fn search(&mut self, from: NodeId, settled: impl FnMut(NodeId, Weight));
+}
+
+/// Compute all distances from `sources` to `targets` (both in rank space).
+/// The result is a dense `sources.len() x targets.len()` matrix in row major order, unreachable pairs have distance `INFINITY`.
+/// Since searches are executed in parallel, the searches are created on demand through `forward` and `backward`.
+pub fn bucket_many_to_many<F, B>(
+    num_nodes: usize,
+    sources: &[NodeId],
+    targets: &[NodeId],
+    forward: impl Fn() -> F + Sync + Send,
+    backward: impl Fn() -> B + Sync + Send,
+) -> Vec<Weight>
+where
+    F: UpwardSearch,
+    B: UpwardSearch,
+{
+    if sources.is_empty() || targets.is_empty() {
+        return Vec::new();
+    }
+
+    let target_entries: Vec<Vec<(NodeId, (u32, Weight))>> = targets
+        .par_iter()
+        .enumerate()
+        .map_init(backward, |search, (target_idx, &target)| {
+            let mut entries = Vec::new();
+            search.search(target, |node, distance| entries.push((node, (target_idx as u32, distance))));
+            entries
+        })
+        .collect();
+
+    // put bucket entries into an adjacency array
+    let mut degrees = vec![0; num_nodes];
+    for &(node, _) in target_entries.iter().flatten() {
+        degrees[node as usize] += 1;
+    }
+    let first_entry: Vec<EdgeId> = degrees_to_first_out(degrees.into_iter()).collect();
+    let mut next_entry = first_entry.clone();
+    let mut buckets = vec![(0, 0); first_entry[num_nodes] as usize];
+    for (node, entry) in target_entries.into_iter().flatten() {
+        buckets[next_entry[node as usize] as usize] = entry;
+        next_entry[node as usize] += 1;
+    }
+
+    let mut distances = vec![INFINITY; sources.len() * targets.len()];
+    distances
+        .par_chunks_mut(targets.len())
+        .zip(sources.par_iter())
+        .for_each_init(forward, |search, (row, &source)| {
+            search.search(source, |node, distance| {
+                for &(target_idx, target_distance) in &buckets[first_entry[node as usize] as usize..first_entry[node as usize + 1] as usize] {
+                    let dist = &mut row[target_idx as usize];
+                    *dist = std::cmp::min(*dist, distance + target_distance);
+                }
+            })
+        });
+
+    distances
+}
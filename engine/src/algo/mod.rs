@@ -4,11 +4,16 @@ use crate::datastr::{graph::*, node_order::NodeOrder};
 
 use self::dijkstra::{QueryProgress, State};
 
+pub mod alt;
 pub mod catchup;
 pub mod ch_potentials;
 pub mod contraction_hierarchy;
 pub mod customizable_contraction_hierarchy;
 pub mod dijkstra;
+pub mod isochrone;
+pub mod many_to_many;
+pub mod phast;
+pub mod snapping;
 pub mod time_dependent_sampling;
 pub mod topocore;
 
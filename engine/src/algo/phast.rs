@@ -0,0 +1,100 @@
+//! PHAST and RPHAST one-to-all and one-to-many queries on hierarchies.
+//!
+//! A query consists of an upward search from the source followed by a linear sweep over the downward graph in descending rank order.
+//! For RPHAST, the sweep is restricted to the nodes from which the selected targets can be reached in the downward graph.
+//! This selection has to be computed once for a set of targets and can then be reused for many sources.
+//! The upward search is specific to the hierarchy (CH or CCH), see `contraction_hierarchy::phast` and `customizable_contraction_hierarchy::phast`.
+
+use super::*;
+use crate::algo::many_to_many::UpwardSearch;
+use crate::datastr::timestamped_vector::TimestampedVector;
+use std::borrow::Borrow;
+
+/// Generic PHAST server.
+/// The downward graph has to contain the downward arcs in rank space at their lower ranked node, that is the backward graph of a (C)CH.
+pub struct Server<Search, Graph, Order> {
+    upward: Search,
+    downward: Graph,
+    order: Order,
+    distances: TimestampedVector<Weight>,
+    selection: Vec<NodeId>,
+}
+
+impl<Search, Graph, Order> Server<Search, Graph, Order>
+where
+    Search: UpwardSearch,
+    Graph: for<'a> LinkIterGraph<'a>,
+    Order: Borrow<NodeOrder>,
+{
+    pub(crate) fn from_parts(upward: Search, downward: Graph, order: Order) -> Self {
+        let n = downward.num_nodes();
+        Server {
+            upward,
+            downward,
+            order,
+            distances: TimestampedVector::new(n, INFINITY),
+            selection: Vec::new(),
+        }
+    }
+
+    /// Distances from `from` to all nodes.
+    pub fn one_to_all(&mut self, from: NodeId) -> ServerWrapper<'_, Search, Graph, Order> {
+        self.upward_search(from);
+        for node in (0..self.downward.num_nodes() as NodeId).rev() {
+            self.relax_downward(node);
+        }
+        ServerWrapper(self)
+    }
+
+    /// RPHAST target selection: restrict subsequent `one_to_selected` queries to the nodes necessary to get exact distances to `targets`.
+    pub fn select_targets(&mut self, targets: &[NodeId]) {
+        let mut selected = vec![false; self.downward.num_nodes()];
+        let mut stack: Vec<NodeId> = targets.iter().map(|&node| self.order.borrow().rank(node)).collect();
+        self.selection.clear();
+
+        while let Some(node) = stack.pop() {
+            if !selected[node as usize] {
+                selected[node as usize] = true;
+                self.selection.push(node);
+                stack.extend(self.downward.link_iter(node).map(|link| link.node));
+            }
+        }
+
+        self.selection.sort_unstable_by(|a, b| b.cmp(a));
+    }
+
+    /// Distances from `from` to the targets of the last `select_targets` call.
+    /// Distances to other nodes may be too large.
+    pub fn one_to_selected(&mut self, from: NodeId) -> ServerWrapper<'_, Search, Graph, Order> {
+        self.upward_search(from);
+        for idx in 0..self.selection.len() {
+            self.relax_downward(self.selection[idx]);
+        }
+        ServerWrapper(self)
+    }
+
+    fn upward_search(&mut self, from: NodeId) {
+        let distances = &mut self.distances;
+        distances.reset();
+        self.upward
+            .search(self.order.borrow().rank(from), |node, distance| distances.set(node as usize, distance));
+    }
+
+    fn relax_downward(&mut self, node: NodeId) {
+        let mut distance = self.distances[node as usize];
+        for link in self.downward.link_iter(node) {
+            distance = std::cmp::min(distance, self.distances[link.node as usize] + link.weight);
+        }
+        if distance < INFINITY {
+            self.distances.set(node as usize, distance);
+        }
+    }
+}
+
+pub struct ServerWrapper<'s, Search, Graph, Order>(&'s Server<Search, Graph, Order>);
+
+impl<'s, Search, Graph, Order: Borrow<NodeOrder>> ServerWrapper<'s, Search, Graph, Order> {
+    pub fn distance(&self, node: NodeId) -> Weight {
+        self.0.distances[self.0.order.borrow().rank(node) as usize]
+    }
+}
@@ -0,0 +1,325 @@
+//! Snapping coordinates to positions on arcs.
+//!
+//! Instead of starting a query at the closest node, which might be a junction on the wrong side of a divided road,
+//! coordinates are snapped to the closest point on any arc, using an R-tree over all arc segments.
+//! Since arcs are directed, a snapped position also determines the direction of travel.
+//! One-way roads are only snapped in their direction and a heading can be used to exclude arcs pointing elsewhere.
+//! Distances are computed in an equirectangular projection around the snapped coordinates, which is precise enough at this scale.
+
+use super::*;
+use crate::datastr::{
+    graph::{arc_geometry::ArcGeometry, turn_costs::bearing},
+    rtree::*,
+};
+
+// mean earth radius times pi / 180
+const METERS_PER_DEGREE: f64 = 111_195.0;
+// Arcs whose distance differs less than this (in meters) from the closest one are also returned, e.g. both directions of a two-way road.
+const TIE_TOLERANCE: f32 = 0.1;
+
+/// Parameters for `SnapIndex::snap`.
+#[derive(Debug, Clone, Copy)]
+pub struct SnapParams {
+    /// Direction of travel in degrees clockwise from north
+    pub heading: Option<f32>,
+    /// Arc segments deviating more than this many degrees from the heading are skipped
+    pub heading_tolerance: f32,
+    /// Arcs further away than this many meters are skipped
+    pub max_distance: f32,
+}
+
+impl Default for SnapParams {
+    fn default() -> Self {
+        SnapParams {
+            heading: None,
+            heading_tolerance: 45.0,
+            max_distance: std::f32::INFINITY,
+        }
+    }
+}
+
+/// A position on an arc.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnappedPosition {
+    pub edge: EdgeId,
+    pub tail: NodeId,
+    pub head: NodeId,
+    /// Position along the arc as fraction of its length
+    pub fraction: f32,
+    pub latitude: f32,
+    pub longitude: f32,
+    /// Distance between the snapped position and the original coordinates in meters
+    pub distance: f32,
+}
+
+impl SnappedPosition {
+    /// Query source for this position, that is the head of the arc and the weight of the rest of the arc.
+    pub fn source(&self, weights: &[Weight]) -> (NodeId, Weight) {
+        (self.head, partial_weight(weights[self.edge as usize], 1.0 - self.fraction))
+    }
+
+    /// Query target for this position, that is the tail of the arc and the weight of the arc up to the position.
+    pub fn target(&self, weights: &[Weight]) -> (NodeId, Weight) {
+        (self.tail, partial_weight(weights[self.edge as usize], self.fraction))
+    }
+
+    /// The end of the arc closer to the position.
+    pub fn closest_node(&self) -> NodeId {
+        if self.fraction < 0.5 {
+            self.tail
+        } else {
+            self.head
+        }
+    }
+}
+
+fn partial_weight(weight: Weight, fraction: f32) -> Weight {
+    if weight >= INFINITY {
+        return INFINITY;
+    }
+    (f64::from(weight) * f64::from(fraction)).round() as Weight
+}
+
+// A straight part of the geometry of an arc.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    arc: EdgeId,
+    from: [f32; 2],
+    to: [f32; 2],
+    // length of the arc geometry before this segment in meters
+    offset: f32,
+    length: f32,
+}
+
+/// Spatial index over all arcs of a graph.
+#[derive(Debug)]
+pub struct SnapIndex {
+    tree: RTree<Segment>,
+    tail: Vec<NodeId>,
+    head: Vec<NodeId>,
+    arc_length: Vec<f32>,
+}
+
+impl SnapIndex {
+    /// Index the arcs of `graph` as straight lines between `lat` and `lng` of their nodes or with the shape points of `geometry`.
+    pub fn new<G: RandomLinkAccessGraph>(graph: &G, lat: &[f32], lng: &[f32], geometry: Option<&ArcGeometry>) -> Self {
+        if let Some(geometry) = geometry {
+            assert_eq!(geometry.num_arcs(), graph.num_arcs());
+        }
+
+        let mut tail = Vec::with_capacity(graph.num_arcs());
+        let mut head = Vec::with_capacity(graph.num_arcs());
+        let mut arc_length = Vec::with_capacity(graph.num_arcs());
+        let mut segments = Vec::with_capacity(graph.num_arcs());
+
+        for node in 0..graph.num_nodes() as NodeId {
+            for arc in graph.neighbor_edge_indices(node) {
+                let arc_head = graph.link(arc).node;
+                tail.push(node);
+                head.push(arc_head);
+
+                let mut points = vec![[lat[node as usize], lng[node as usize]]];
+                if let Some(geometry) = geometry {
+                    points.extend(geometry.shape_points(arc).map(|(lat, lng)| [lat, lng]));
+                }
+                points.push([lat[arc_head as usize], lng[arc_head as usize]]);
+
+                let mut offset = 0.0;
+                for pair in points.windows(2) {
+                    let length = length_in_m(pair[0], pair[1]);
+                    let segment = Segment {
+                        arc,
+                        from: pair[0],
+                        to: pair[1],
+                        offset,
+                        length,
+                    };
+                    segments.push((BoundingBox::from_points(pair), segment));
+                    offset += length;
+                }
+                arc_length.push(offset);
+            }
+        }
+
+        SnapIndex {
+            tree: RTree::new(segments),
+            tail,
+            head,
+            arc_length,
+        }
+    }
+
+    /// Positions on the closest arcs matching `params`, usually one or two (for both directions of a two-way road).
+    /// Empty, if no arc matches.
+    /// Note that with a heading, all arcs within the maximum distance may have to be checked when none of them matches.
+    pub fn snap(&self, latitude: f32, longitude: f32, params: &SnapParams) -> Vec<SnappedPosition> {
+        let mut positions: Vec<SnappedPosition> = Vec::new();
+        for position in self.positions(latitude, longitude, params) {
+            if positions
+                .first()
+                .map(|closest| position.distance > closest.distance + TIE_TOLERANCE)
+                .unwrap_or(false)
+            {
+                break;
+            }
+            positions.push(position);
+        }
+        positions
+    }
+
+    /// Positions on all arcs matching `params` by increasing distance, the closest one for each arc.
+    /// Useful to obtain several candidates, e.g. for map matching.
+    pub fn positions(&self, latitude: f32, longitude: f32, params: &SnapParams) -> impl Iterator<Item = SnappedPosition> + '_ {
+        let projection = Projection::new(latitude, longitude);
+        let params = *params;
+        let mut snapped_arcs = Vec::new();
+
+        self.tree
+            .nearest(
+                move |bounding_box| projection.box_distance(bounding_box),
+                move |segment| projection.closest_point(segment).1,
+            )
+            .take_while(move |&(distance, _)| distance <= f64::from(params.max_distance))
+            .filter(move |(_, segment)| match params.heading {
+                Some(heading) => segment.length <= 0.0 || angle_difference(segment_bearing(segment), heading) <= params.heading_tolerance,
+                None => true,
+            })
+            .filter_map(move |(distance, segment)| {
+                if snapped_arcs.contains(&segment.arc) {
+                    return None;
+                }
+                snapped_arcs.push(segment.arc);
+
+                let (t, _) = projection.closest_point(segment);
+                let arc_length = self.arc_length[segment.arc as usize];
+                Some(SnappedPosition {
+                    edge: segment.arc,
+                    tail: self.tail[segment.arc as usize],
+                    head: self.head[segment.arc as usize],
+                    fraction: if arc_length > 0.0 {
+                        (segment.offset + t * segment.length) / arc_length
+                    } else {
+                        0.0
+                    },
+                    latitude: segment.from[0] + t * (segment.to[0] - segment.from[0]),
+                    longitude: segment.from[1] + t * (segment.to[1] - segment.from[1]),
+                    distance: distance as f32,
+                })
+            })
+    }
+}
+
+// Local equirectangular projection around a point, coordinates in meters relative to it.
+#[derive(Debug, Clone, Copy)]
+struct Projection {
+    origin: [f32; 2],
+    lng_scale: f64,
+}
+
+impl Projection {
+    fn new(latitude: f32, longitude: f32) -> Self {
+        Projection {
+            origin: [latitude, longitude],
+            lng_scale: f64::from(latitude).to_radians().cos(),
+        }
+    }
+
+    fn project(&self, point: [f32; 2]) -> (f64, f64) {
+        (
+            f64::from(point[1] - self.origin[1]) * self.lng_scale * METERS_PER_DEGREE,
+            f64::from(point[0] - self.origin[0]) * METERS_PER_DEGREE,
+        )
+    }
+
+    fn box_distance(&self, bounding_box: &BoundingBox) -> f64 {
+        let clamped = [
+            self.origin[0].max(bounding_box.min[0]).min(bounding_box.max[0]),
+            self.origin[1].max(bounding_box.min[1]).min(bounding_box.max[1]),
+        ];
+        let (x, y) = self.project(clamped);
+        (x * x + y * y).sqrt()
+    }
+
+    // Position of the closest point on the segment as fraction of the segment and its distance to the origin.
+    fn closest_point(&self, segment: &Segment) -> (f32, f64) {
+        let (from_x, from_y) = self.project(segment.from);
+        let (to_x, to_y) = self.project(segment.to);
+        let (dx, dy) = (to_x - from_x, to_y - from_y);
+        let squared_length = dx * dx + dy * dy;
+        let t = if squared_length > 0.0 {
+            (-(from_x * dx + from_y * dy) / squared_length).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let (x, y) = (from_x + t * dx, from_y + t * dy);
+        (t as f32, (x * x + y * y).sqrt())
+    }
+}
+
+fn length_in_m(from: [f32; 2], to: [f32; 2]) -> f32 {
+    let lng_scale = f64::from((from[0] + to[0]) / 2.0).to_radians().cos();
+    let x = f64::from(to[1] - from[1]) * lng_scale * METERS_PER_DEGREE;
+    let y = f64::from(to[0] - from[0]) * METERS_PER_DEGREE;
+    (x * x + y * y).sqrt() as f32
+}
+
+fn segment_bearing(segment: &Segment) -> f32 {
+    bearing(
+        (f64::from(segment.from[0]), f64::from(segment.from[1])),
+        (f64::from(segment.to[0]), f64::from(segment.to[1])),
+    )
+}
+
+fn angle_difference(a: f32, b: f32) -> f32 {
+    let difference = (a - b).rem_euclid(360.0);
+    difference.min(360.0 - difference)
+}
+
+/// Shortest route between snapped positions, see `snapped_route`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnappedRoute {
+    pub distance: Weight,
+    pub source: SnappedPosition,
+    pub target: SnappedPosition,
+    /// Nodes passed between source and target, empty if both are on the same arc
+    pub path: Vec<NodeId>,
+}
+
+/// Find the shortest route from any of the `sources` to any of the `targets`, including the partial weights of the snapped arcs.
+/// `query` should return the distance and path between two nodes, e.g. with `QueryServer::query`.
+pub fn snapped_route(
+    sources: &[SnappedPosition],
+    targets: &[SnappedPosition],
+    weights: &[Weight],
+    mut query: impl FnMut(Query) -> Option<(Weight, Vec<NodeId>)>,
+) -> Option<SnappedRoute> {
+    let mut best: Option<SnappedRoute> = None;
+
+    for source in sources {
+        for target in targets {
+            let (from, source_weight) = source.source(weights);
+            let (to, target_weight) = target.target(weights);
+
+            let route = if source.edge == target.edge && source.fraction <= target.fraction {
+                Some((partial_weight(weights[source.edge as usize], target.fraction - source.fraction), Vec::new()))
+            } else if source_weight < INFINITY && target_weight < INFINITY {
+                query(Query { from, to }).map(|(distance, path)| (distance.saturating_add(source_weight).saturating_add(target_weight), path))
+            } else {
+                None
+            };
+
+            if let Some((distance, path)) = route {
+                if distance < INFINITY && best.as_ref().map(|best| distance < best.distance).unwrap_or(true) {
+                    best = Some(SnappedRoute {
+                        distance,
+                        source: *source,
+                        target: *target,
+                        path,
+                    });
+                }
+            }
+        }
+    }
+
+    best
+}
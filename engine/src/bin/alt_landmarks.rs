@@ -0,0 +1,42 @@
+// Select landmarks for ALT and store them with their distances in the `alt` subdirectory of a graph in RoutingKit format.
+// Takes the graph directory, optionally the selection strategy (`avoid` (default), `farthest` or `planar`) and the number of landmarks (default 16).
+
+use std::{env, error::Error, path::Path};
+
+use rand::prelude::*;
+use rust_road_router::{algo::alt::*, cli::CliErr, datastr::graph::*, io::*, report::benchmark::report_time};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args();
+    args.next();
+
+    let arg = &args.next().ok_or(CliErr("No directory arg given"))?;
+    let path = Path::new(arg);
+    let strategy = args.next().unwrap_or_else(|| "avoid".to_string());
+    let num_landmarks = args.next().map(|arg| arg.parse()).transpose()?.unwrap_or(16);
+
+    let first_out = Vec::load_from(path.join("first_out"))?;
+    let head = Vec::load_from(path.join("head"))?;
+    let travel_time = Vec::load_from(path.join("travel_time"))?;
+    let graph = FirstOutGraph::new(&first_out[..], &head[..], &travel_time[..]);
+
+    let landmarks = report_time("landmark selection", || match strategy.as_str() {
+        "avoid" => Ok(avoid_landmarks(&graph, num_landmarks, &mut StdRng::from_seed(Default::default()))),
+        "farthest" => Ok(farthest_landmarks(&graph, num_landmarks, 0)),
+        "planar" => {
+            let lat = Vec::<f32>::load_from(path.join("latitude"))?;
+            let lng = Vec::<f32>::load_from(path.join("longitude"))?;
+            Ok(planar_landmarks(&graph, num_landmarks, &lat, &lng))
+        }
+        _ => Err(Box::new(CliErr("Unknown landmark selection strategy")) as Box<dyn Error>),
+    })?;
+    let landmarks = report_time("landmark distances", || Landmarks::new(&graph, landmarks));
+
+    let alt_folder = path.join("alt");
+    if !alt_folder.exists() {
+        std::fs::create_dir(&alt_folder)?;
+    }
+    landmarks.deconstruct_to(&alt_folder)?;
+
+    Ok(())
+}
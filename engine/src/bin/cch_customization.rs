@@ -0,0 +1,75 @@
+// Customize a metric once and store the result, so it can be loaded instead of customizing again on every start.
+// Takes a directory with a graph in RoutingKit format and a nested dissection order (`cch_perm`) and optionally the metric (default `travel_time`).
+// The metric can be the name of an attribute file or an expression like `travel_time + 0.5*geo_distance + block(2)` (see `customizable_contraction_hierarchy::metric`).
+// The CCH is loaded from the `cch` subdirectory or, if it does not exist yet, built and stored there.
+// The customized metric is written to `cch/customized_<metric>`, where `<metric>` is the canonical form of the expression
+// with special characters escaped (see `MetricBuilder::dir_name`), e.g. `cch/customized_travel_time`.
+// The server only loads `cch/customized_travel_time`, profiles are always customized at startup.
+// A checksum of the metric is stored along with it, so the customization is not loaded anymore once the weights change.
+
+use std::{env, error::Error, path::Path};
+
+use rust_road_router::{
+    algo::customizable_contraction_hierarchy::{metric::MetricBuilder, *},
+    cli::CliErr,
+    datastr::{graph::*, node_order::NodeOrder},
+    io::*,
+    report::benchmark::report_time,
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args();
+    args.next();
+
+    let arg = &args.next().ok_or(CliErr("No directory arg given"))?;
+    let path = Path::new(arg);
+    let metric: MetricBuilder = args.next().unwrap_or_else(|| "travel_time".to_string()).parse()?;
+
+    let first_out = Vec::load_from(path.join("first_out"))?;
+    let head = Vec::load_from(path.join("head"))?;
+    let weight = metric.build(path)?;
+    let graph = FirstOutGraph::new(&first_out[..], &head[..], &weight[..]);
+
+    let cch_folder = path.join("cch");
+    let cch = if cch_folder.join("cch_header").exists() {
+        CCH::reconstruct_from(&cch_folder)?
+    } else if cch_folder.join("cch_first_out").exists() {
+        let node_order = NodeOrder::reconstruct_from(&cch_folder)?;
+        CCHReconstrctor {
+            original_graph: &graph,
+            node_order,
+        }
+        .reconstruct_from(&cch_folder)?
+    } else {
+        let cch_order = NodeOrder::from_node_order(Vec::load_from(path.join("cch_perm"))?);
+        let cch = report_time("CCH contraction", || contract(&graph, cch_order));
+        let cch_order = CCHReordering {
+            cch: &cch,
+            latitude: &[],
+            longitude: &[],
+        }
+        .reorder_for_seperator_based_customization();
+
+        if !cch_folder.exists() {
+            std::fs::create_dir(&cch_folder)?;
+        }
+        cch_order.deconstruct_to(&cch_folder)?;
+        let cch = report_time("CCH contraction", || contract(&graph, cch_order));
+        cch.deconstruct_to(&cch_folder)?;
+        cch
+    };
+
+    let customized = report_time("CCH customization", || customize(&cch, &graph));
+
+    let customized_folder = cch_folder.join(format!("customized_{}", metric.dir_name()));
+    if !customized_folder.exists() {
+        std::fs::create_dir(&customized_folder)?;
+    }
+    CustomizedMetric {
+        customized: &customized,
+        metric: &weight,
+    }
+    .deconstruct_to(&customized_folder)?;
+
+    Ok(())
+}
@@ -0,0 +1,25 @@
+// Calculate a nested dissection order for CCHs without InertialFlowCutter.
+// Takes a directory with a graph in RoutingKit format including coordinates and writes the order to `cch_perm`.
+
+use rust_road_router::{algo::customizable_contraction_hierarchy::nested_dissection, cli::CliErr, datastr::graph::*, io::*};
+
+use std::{env, error::Error, path::Path};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args();
+    args.next();
+    let arg = &args.next().ok_or(CliErr("No graph directory arg given"))?;
+    let path = Path::new(arg);
+
+    let first_out = Vec::load_from(path.join("first_out"))?;
+    let head = Vec::load_from(path.join("head"))?;
+    let lat = Vec::<f32>::load_from(path.join("latitude"))?;
+    let lng = Vec::<f32>::load_from(path.join("longitude"))?;
+
+    let graph = UnweightedFirstOutGraph::new(&first_out[..], &head[..]);
+    let order = nested_dissection(&graph, &lat, &lng);
+
+    order.order().write_to(&path.join("cch_perm"))?;
+
+    Ok(())
+}
@@ -1,11 +1,14 @@
 #[macro_use]
 extern crate rust_road_router;
-use rust_road_router::{cli::CliErr, datastr::graph::*, io::*, report::*};
+use rust_road_router::{
+    algo::customizable_contraction_hierarchy::metric::{FREEWAY_BIT, TUNNEL_BIT},
+    cli::CliErr,
+    datastr::graph::*,
+    io::*,
+    report::*,
+};
 use std::{env, error::Error, path::Path};
 
-const TUNNEL_BIT: u8 = 1;
-const FREEWAY_BIT: u8 = 2;
-
 fn main() -> Result<(), Box<dyn Error>> {
     let _reporter = enable_reporting();
 
@@ -26,6 +26,9 @@ fn main() -> Result<(), Box<dyn Error>> {
     let out_dir = Path::new(out_dir);
 
     data.functional_road_classes.write_to(&out_dir.join("functional_road_classes"))?;
+    data.arc_start_bearings.write_to(&out_dir.join("arc_start_bearing"))?;
+    data.arc_end_bearings.write_to(&out_dir.join("arc_end_bearing"))?;
+    data.arc_geometry.deconstruct_to(&out_dir)?;
     data.lat.write_to(&out_dir.join("latitude"))?;
     data.lng.write_to(&out_dir.join("longitude"))?;
     data.link_id_mapping.write_to(&out_dir.join("link_id_mapping"))?;
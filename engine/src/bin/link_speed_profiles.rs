@@ -0,0 +1,55 @@
+// Build time-dependent travel time profiles from link speed estimates.
+// Takes a graph directory and CSV files with one estimate per line: arc id, link entered timestamp [ms], estimate quality, velocity [km/h].
+// The profiles are written into the graph directory as first_ipp_of_arc, ipp_departure_time and ipp_travel_time.
+
+use rust_road_router::{
+    cli::CliErr,
+    datastr::graph::*,
+    io::*,
+    link_speed_estimates::{profiles::*, LinkSpeedData},
+};
+use std::{env, error::Error, fs::File, path::Path};
+
+use csv::ReaderBuilder;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args();
+    args.next();
+    let arg = &args.next().ok_or(CliErr("No graph directory arg given"))?;
+    let path = Path::new(arg);
+
+    let travel_time = Vec::<Weight>::load_from(path.join("travel_time"))?;
+    let geo_distance = Vec::<Weight>::load_from(path.join("geo_distance"))?;
+    let length = geo_distance.iter().map(|&meters| meters * 1000).collect();
+
+    let mut builder = ProfileBuilder::new(length, travel_time, ProfileParams::default());
+    let mut total = 0;
+
+    for file in args {
+        let file = File::open(file)?;
+        let mut reader = ReaderBuilder::new().has_headers(false).from_reader(file);
+
+        for line in reader.records() {
+            let record = line?;
+            let field = |i| record.get(i).ok_or(CliErr("estimate with missing fields"));
+            builder.add(&LinkSpeedData {
+                link_id: field(0)?.parse()?,
+                link_entered_timestamp: field(1)?.parse()?,
+                estimate_quality: field(2)?.parse()?,
+                velocity: field(3)?.parse()?,
+            })?;
+            total += 1;
+        }
+    }
+
+    let profiles = builder.build();
+    println!(
+        "{} estimates, {} interpolation points for {} arcs",
+        total,
+        profiles.ipp_departure_time.len(),
+        profiles.first_ipp_of_arc.len() - 1
+    );
+    profiles.deconstruct_to(&path)?;
+
+    Ok(())
+}
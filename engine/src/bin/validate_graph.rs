@@ -0,0 +1,110 @@
+// Check a graph directory for violations of the invariants the algorithms in this crate rely on.
+// Takes a directory with a graph in RoutingKit format.
+// Optional files (weights, coordinates, osm ids, time-dependent data, cch order) are checked when present.
+// Optional files which can not be loaded are reported as failed checks.
+// All results are reported as JSON, the exit code is non-zero if any violations were found.
+
+use std::{env, error::Error, path::Path};
+
+#[macro_use]
+extern crate rust_road_router;
+use rust_road_router::{
+    cli::CliErr,
+    datastr::graph::{validate::*, *},
+    io::*,
+    report::*,
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args();
+    args.next();
+    let arg = &args.next().ok_or(CliErr("No graph directory arg given"))?;
+    let path = Path::new(arg);
+
+    let num_violations = {
+        let _reporter = enable_reporting();
+        report!("program", "validate_graph");
+        report!("args", env::args().collect::<Vec<String>>());
+
+        let first_out = Vec::<EdgeId>::load_with_header_from(path.join("first_out"))?;
+        let head = Vec::<NodeId>::load_with_header_from(path.join("head"))?;
+        let n = first_out.len().saturating_sub(1);
+        let m = head.len();
+        report!("graph", { "num_nodes": n, "num_arcs": m });
+
+        let mut checks = vec![index_array("first_out", &first_out, m), head_in_range(&head, n)];
+
+        for metric in &["travel_time", "geo_distance"] {
+            if path.join(metric).exists() {
+                if let Some(weight) = load::<Weight>(path, metric, &mut checks) {
+                    checks.push(length(metric, weight.len(), m));
+                    checks.push(weights(metric, &weight));
+                }
+            }
+        }
+
+        if path.join("latitude").exists() && path.join("longitude").exists() {
+            let lat = load::<f32>(path, "latitude", &mut checks);
+            let lng = load::<f32>(path, "longitude", &mut checks);
+            if let (Some(lat), Some(lng)) = (lat, lng) {
+                checks.push(length("latitude", lat.len(), n));
+                checks.push(length("longitude", lng.len(), n));
+                checks.push(coordinates(&lat, &lng));
+            }
+        }
+
+        if path.join("osm_node_ids").exists() {
+            if let Some(osm_node_ids) = load::<u64>(path, "osm_node_ids", &mut checks) {
+                checks.push(length("osm_node_ids", osm_node_ids.len(), n));
+                checks.push(strictly_increasing("osm_node_ids", &osm_node_ids));
+            }
+        }
+
+        if path.join("first_ipp_of_arc").exists() {
+            let first_ipp_of_arc = load::<u32>(path, "first_ipp_of_arc", &mut checks);
+            let ipp_departure_time = load::<u32>(path, "ipp_departure_time", &mut checks);
+            let ipp_travel_time = load::<u32>(path, "ipp_travel_time", &mut checks);
+
+            if let (Some(first_ipp_of_arc), Some(ipp_departure_time), Some(ipp_travel_time)) = (first_ipp_of_arc, ipp_departure_time, ipp_travel_time) {
+                let structure_checks = vec![
+                    length("first_ipp_of_arc", first_ipp_of_arc.len(), m + 1),
+                    length("ipp_travel_time", ipp_travel_time.len(), ipp_departure_time.len()),
+                    index_array("first_ipp_of_arc", &first_ipp_of_arc, ipp_departure_time.len()),
+                ];
+                // the functions can only be checked when the index structure is valid
+                if structure_checks.iter().all(CheckResult::is_ok) {
+                    checks.extend(travel_time_functions(&first_ipp_of_arc, &ipp_departure_time, &ipp_travel_time));
+                }
+                checks.extend(structure_checks);
+            }
+        }
+
+        if path.join("cch_perm").exists() {
+            if let Some(cch_perm) = load::<NodeId>(path, "cch_perm", &mut checks) {
+                checks.push(length("cch_perm", cch_perm.len(), n));
+                checks.push(permutation("cch_perm", &cch_perm));
+            }
+        }
+
+        report!("checks", checks.iter().map(CheckResult::to_json).collect::<Vec<_>>());
+        let num_violations: usize = checks.iter().map(|check| check.num_violations).sum();
+        report!("num_violations", num_violations);
+        num_violations
+    };
+
+    if num_violations > 0 {
+        return Err(Box::new(CliErr("Graph data violates invariants")));
+    }
+    Ok(())
+}
+
+// Load an optional data file, a failure is recorded as a failed check.
+fn load<T: HeaderElement>(path: &Path, name: &str, checks: &mut Vec<CheckResult>) -> Option<Vec<T>> {
+    match Vec::<T>::load_with_header_from(path.join(name)) {
+        Ok(data) => Some(data),
+        Err(error) => {
+            checks.push(loadable(name, &error));
+            None
+        }
+    }
+}
@@ -5,4 +5,5 @@ pub mod graph;
 pub mod index_heap;
 pub mod node_order;
 pub mod rank_select_map;
+pub mod rtree;
 pub mod timestamped_vector;
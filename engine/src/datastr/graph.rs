@@ -5,10 +5,15 @@
 use crate::datastr::node_order::NodeOrder;
 use std::ops::Range;
 
+pub mod arc_geometry;
+pub mod bi_criteria;
 pub mod first_out_graph;
 pub mod floating_time_dependent;
 pub mod link_id_to_tail_mapper;
 pub mod time_dependent;
+pub mod turn_costs;
+pub mod turn_expansion;
+pub mod validate;
 
 pub use self::first_out_graph::{FirstOutGraph, OwnedGraph, UnweightedFirstOutGraph, UnweightedOwnedGraph};
 
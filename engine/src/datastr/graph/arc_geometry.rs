@@ -0,0 +1,73 @@
+//! Detailed geometry of arcs.
+//!
+//! Arcs may have shape points between the coordinates of their tail and head, for example from the HERE link geometry (see `import::here`).
+//! Arcs without shape points are straight lines.
+
+use super::*;
+use crate::io::*;
+
+/// Intermediate shape points of all arcs in driving direction, without the coordinates of tail and head.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArcGeometry {
+    // shape points of arc `i` are at `first_point[i]..first_point[i + 1]`
+    first_point: Vec<EdgeId>,
+    latitude: Vec<f32>,
+    longitude: Vec<f32>,
+}
+
+impl ArcGeometry {
+    /// Shape points as (lat, lng) for each arc.
+    pub fn new(shapes: &[Vec<(f32, f32)>]) -> Self {
+        let mut first_point = Vec::with_capacity(shapes.len() + 1);
+        first_point.push(0);
+        let mut latitude = Vec::new();
+        let mut longitude = Vec::new();
+        for shape in shapes {
+            for &(lat, lng) in shape {
+                latitude.push(lat);
+                longitude.push(lng);
+            }
+            first_point.push(latitude.len() as EdgeId);
+        }
+        ArcGeometry {
+            first_point,
+            latitude,
+            longitude,
+        }
+    }
+
+    pub fn num_arcs(&self) -> usize {
+        self.first_point.len() - 1
+    }
+
+    /// Intermediate shape points of `arc` as (lat, lng).
+    pub fn shape_points(&self, arc: EdgeId) -> impl Iterator<Item = (f32, f32)> + '_ {
+        let range = self.first_point[arc as usize] as usize..self.first_point[arc as usize + 1] as usize;
+        self.latitude[range.clone()].iter().cloned().zip(self.longitude[range].iter().cloned())
+    }
+}
+
+impl Deconstruct for ArcGeometry {
+    fn store_each(&self, store: &dyn Fn(&str, &dyn Store) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("arc_geometry_first_point", &self.first_point)?;
+        store("arc_geometry_latitude", &self.latitude)?;
+        store("arc_geometry_longitude", &self.longitude)?;
+        Ok(())
+    }
+}
+
+impl Reconstruct for ArcGeometry {
+    fn reconstruct_with(loader: Loader) -> std::io::Result<Self> {
+        let first_point: Vec<EdgeId> = loader.load("arc_geometry_first_point")?;
+        let latitude: Vec<f32> = loader.load("arc_geometry_latitude")?;
+        let longitude: Vec<f32> = loader.load("arc_geometry_longitude")?;
+        if first_point.last().map(|&last| last as usize) != Some(latitude.len()) || latitude.len() != longitude.len() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "arc geometry arrays do not match"));
+        }
+        Ok(ArcGeometry {
+            first_point,
+            latitude,
+            longitude,
+        })
+    }
+}
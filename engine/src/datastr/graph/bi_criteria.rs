@@ -0,0 +1,99 @@
+//! Graph with two metrics per arc, for example `travel_time` and `geo_distance`.
+
+use super::*;
+use crate::as_slice::AsSlice;
+
+/// A `FirstOutGraph` with the first metric and an additional second metric for each arc.
+#[derive(Debug, Clone)]
+pub struct BiCriteriaGraph<FirstOutContainer, HeadContainer, WeightContainer>
+where
+    FirstOutContainer: AsSlice<EdgeId>,
+    HeadContainer: AsSlice<NodeId>,
+    WeightContainer: AsSlice<Weight>,
+{
+    graph: FirstOutGraph<FirstOutContainer, HeadContainer, WeightContainer>,
+    second_weight: WeightContainer,
+}
+
+impl<FirstOutContainer, HeadContainer, WeightContainer> BiCriteriaGraph<FirstOutContainer, HeadContainer, WeightContainer>
+where
+    FirstOutContainer: AsSlice<EdgeId>,
+    HeadContainer: AsSlice<NodeId>,
+    WeightContainer: AsSlice<Weight>,
+{
+    pub fn new(first_out: FirstOutContainer, head: HeadContainer, first_weight: WeightContainer, second_weight: WeightContainer) -> Self {
+        let graph = FirstOutGraph::new(first_out, head, first_weight);
+        assert_eq!(graph.num_arcs(), second_weight.as_slice().len());
+        BiCriteriaGraph { graph, second_weight }
+    }
+
+    /// The underlying graph with the first metric.
+    pub fn graph(&self) -> &FirstOutGraph<FirstOutContainer, HeadContainer, WeightContainer> {
+        &self.graph
+    }
+
+    pub fn first_weight(&self) -> &[Weight] {
+        self.graph.weight()
+    }
+
+    pub fn second_weight(&self) -> &[Weight] {
+        self.second_weight.as_slice()
+    }
+
+    /// Tail node of an arc, determined by binary search.
+    pub fn tail(&self, edge_id: EdgeId) -> NodeId {
+        (self.graph.first_out().partition_point(|&first_out| first_out <= edge_id) - 1) as NodeId
+    }
+
+    /// Graph with a linear combination `first_factor * first_weight + second_factor * second_weight` of both metrics as weights.
+    /// Weights are capped at `INFINITY`.
+    pub fn combined(&self, first_factor: Weight, second_factor: Weight) -> FirstOutGraph<&[EdgeId], &[NodeId], Vec<Weight>> {
+        let weight = self
+            .first_weight()
+            .iter()
+            .zip(self.second_weight())
+            .map(|(&first, &second)| {
+                let combined = u64::from(first_factor) * u64::from(first) + u64::from(second_factor) * u64::from(second);
+                std::cmp::min(combined, u64::from(INFINITY)) as Weight
+            })
+            .collect();
+        FirstOutGraph::new(self.graph.first_out(), self.graph.head(), weight)
+    }
+}
+
+impl<FirstOutContainer, HeadContainer, WeightContainer> Graph for BiCriteriaGraph<FirstOutContainer, HeadContainer, WeightContainer>
+where
+    FirstOutContainer: AsSlice<EdgeId>,
+    HeadContainer: AsSlice<NodeId>,
+    WeightContainer: AsSlice<Weight>,
+{
+    fn num_nodes(&self) -> usize {
+        self.graph.num_nodes()
+    }
+
+    fn num_arcs(&self) -> usize {
+        self.graph.num_arcs()
+    }
+
+    fn degree(&self, node: NodeId) -> usize {
+        self.graph.degree(node)
+    }
+}
+
+impl<'a, FirstOutContainer, HeadContainer, WeightContainer> LinkIterable<'a, (NodeId, EdgeId)>
+    for BiCriteriaGraph<FirstOutContainer, HeadContainer, WeightContainer>
+where
+    FirstOutContainer: AsSlice<EdgeId>,
+    HeadContainer: AsSlice<NodeId>,
+    WeightContainer: AsSlice<Weight>,
+{
+    type Iter = std::iter::Zip<std::iter::Cloned<std::slice::Iter<'a, NodeId>>, Range<EdgeId>>;
+
+    #[inline(always)]
+    fn link_iter(&'a self, node: NodeId) -> Self::Iter {
+        self.graph.head()[self.graph.neighbor_edge_indices_usize(node)]
+            .iter()
+            .cloned()
+            .zip(self.graph.neighbor_edge_indices(node))
+    }
+}
@@ -144,14 +144,15 @@ impl<G: for<'a> LinkIterable<'a, Link>> BuildPermutated<G> for OwnedGraph {
     }
 }
 
-impl<FirstOutContainer, HeadContainer> FirstOutGraph<FirstOutContainer, HeadContainer, Vec<Weight>>
+impl<FirstOutContainer, HeadContainer, WeightContainer> FirstOutGraph<FirstOutContainer, HeadContainer, WeightContainer>
 where
     FirstOutContainer: AsSlice<EdgeId>,
     HeadContainer: AsSlice<NodeId>,
+    WeightContainer: AsSlice<Weight>,
 {
-    pub fn swap_weights(&mut self, mut new_weights: &mut Vec<Weight>) {
-        assert!(new_weights.len() == self.weight.len());
-        swap(&mut self.weight, &mut new_weights);
+    pub fn swap_weights(&mut self, new_weights: &mut WeightContainer) {
+        assert!(new_weights.as_slice().len() == self.weight.as_slice().len());
+        swap(&mut self.weight, new_weights);
     }
 }
 
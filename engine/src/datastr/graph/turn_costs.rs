@@ -0,0 +1,166 @@
+//! Turn costs derived from the road geometry.
+//!
+//! The cost of a turn depends on the angle between the end of the incoming and the start of the outgoing arc,
+//! whether the turn crosses oncoming traffic and how much the road class changes.
+//! Bearings can either be derived from node coordinates or from a detailed link geometry (see `import::here`).
+//! `TurnCostModel::cost` can directly be used as (part of) the callback for `RandomLinkAccessGraph::line_graph`.
+
+use super::turn_expansion::TurnExpansion;
+use super::*;
+
+/// Initial bearing from one (lat, lng) position to another in degrees clockwise from north, in the range `[0, 360)`.
+pub fn bearing((from_lat, from_lng): (f64, f64), (to_lat, to_lng): (f64, f64)) -> f32 {
+    let (from_lat, to_lat) = (from_lat.to_radians(), to_lat.to_radians());
+    let delta_lng = (to_lng - from_lng).to_radians();
+    let y = delta_lng.sin() * to_lat.cos();
+    let x = from_lat.cos() * to_lat.sin() - from_lat.sin() * to_lat.cos() * delta_lng.cos();
+    let bearing = y.atan2(x).to_degrees().rem_euclid(360.0) as f32;
+    // bearings just west of north may be rounded up to 360 by the conversion
+    if bearing < 360.0 {
+        bearing
+    } else {
+        0.0
+    }
+}
+
+/// Direction of each arc when leaving its tail (`start`) and when arriving at its head (`end`).
+/// For straight arcs, both are the same.
+#[derive(Debug, Clone)]
+pub struct ArcBearings {
+    pub start: Vec<f32>,
+    pub end: Vec<f32>,
+}
+
+impl ArcBearings {
+    /// Bearings of straight lines between the coordinates of tail and head.
+    pub fn from_coordinates(expansion: &TurnExpansion, lat: &[f32], lng: &[f32]) -> Self {
+        let position = |node: NodeId| (f64::from(lat[node as usize]), f64::from(lng[node as usize]));
+        let start: Vec<f32> = (0..expansion.num_arcs() as EdgeId)
+            .map(|arc| bearing(position(expansion.tail(arc)), position(expansion.head(arc))))
+            .collect();
+        ArcBearings { end: start.clone(), start }
+    }
+}
+
+/// Which side of the road vehicles drive on.
+/// Turns to the other side cross oncoming traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrafficSide {
+    Right,
+    Left,
+}
+
+/// Parameters of the turn cost model, all costs in ms.
+#[derive(Debug, Clone, Copy)]
+pub struct TurnCostParams {
+    pub traffic_side: TrafficSide,
+    /// Turns deviating at most this many degrees from going straight are free
+    pub straight_angle: f32,
+    /// Cost of a right angle turn which does not cross oncoming traffic, scaled linearly with the angle
+    pub turn_cost: Weight,
+    /// Cost of a right angle turn across oncoming traffic, scaled linearly with the angle
+    pub crossing_turn_cost: Weight,
+    pub u_turn_cost: Weight,
+    /// Additional cost for each level the road class changes by
+    pub road_class_change_cost: Weight,
+}
+
+impl Default for TurnCostParams {
+    fn default() -> Self {
+        TurnCostParams {
+            traffic_side: TrafficSide::Right,
+            straight_angle: 20.0,
+            turn_cost: 3000,
+            crossing_turn_cost: 6000,
+            u_turn_cost: 20000,
+            road_class_change_cost: 1000,
+        }
+    }
+}
+
+/// Geometric turn cost model.
+#[derive(Debug, Clone)]
+pub struct TurnCostModel<'a> {
+    params: TurnCostParams,
+    expansion: &'a TurnExpansion,
+    bearings: &'a ArcBearings,
+    road_classes: Option<&'a [u8]>,
+}
+
+impl<'a> TurnCostModel<'a> {
+    pub fn new(params: TurnCostParams, expansion: &'a TurnExpansion, bearings: &'a ArcBearings) -> Self {
+        TurnCostModel {
+            params,
+            expansion,
+            bearings,
+            road_classes: None,
+        }
+    }
+
+    /// Also charge for changing the road class, e.g. HERE `functional_road_classes` or RoutingKit `arc_category`.
+    pub fn with_road_classes(self, road_classes: &'a [u8]) -> Self {
+        TurnCostModel {
+            road_classes: Some(road_classes),
+            ..self
+        }
+    }
+
+    /// Signed deviation from going straight in degrees in the range `(-180, 180]`.
+    /// Positive values are right turns, negative values left turns.
+    pub fn turn_angle(&self, from_arc: EdgeId, to_arc: EdgeId) -> f32 {
+        let angle = (self.bearings.start[to_arc as usize] - self.bearings.end[from_arc as usize]).rem_euclid(360.0);
+        if angle > 180.0 {
+            angle - 360.0
+        } else {
+            angle
+        }
+    }
+
+    pub fn cost(&self, from_arc: EdgeId, to_arc: EdgeId) -> Weight {
+        let params = &self.params;
+        let angle_cost = if self.expansion.is_u_turn(from_arc, to_arc) {
+            params.u_turn_cost
+        } else {
+            let angle = self.turn_angle(from_arc, to_arc);
+            if angle.abs() <= params.straight_angle {
+                0
+            } else {
+                let crosses_traffic = match params.traffic_side {
+                    TrafficSide::Right => angle < 0.0,
+                    TrafficSide::Left => angle > 0.0,
+                };
+                let right_angle_cost = if crosses_traffic { params.crossing_turn_cost } else { params.turn_cost };
+                (right_angle_cost as f32 * angle.abs() / 90.0).round() as Weight
+            }
+        };
+
+        let class_change_cost = self
+            .road_classes
+            .map(|classes| {
+                let (from_class, to_class) = (classes[from_arc as usize], classes[to_arc as usize]);
+                Weight::from(std::cmp::max(from_class, to_class) - std::cmp::min(from_class, to_class)) * params.road_class_change_cost
+            })
+            .unwrap_or(0);
+
+        angle_cost + class_change_cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearings_are_clockwise_from_north() {
+        let approx = |bearing: f32, expected: f32| (bearing - expected).abs() < 0.01;
+        assert!(approx(bearing((0.0, 0.0), (1.0, 0.0)), 0.0));
+        assert!(approx(bearing((0.0, 0.0), (0.0, 1.0)), 90.0));
+        assert!(approx(bearing((0.0, 0.0), (-1.0, 0.0)), 180.0));
+        assert!(approx(bearing((0.0, 0.0), (0.0, -1.0)), 270.0));
+        assert!(approx(bearing((0.0, 0.0), (1.0, 1.0)), 45.0));
+        // meridians converge, so the initial bearing along a parallel deviates from east
+        assert!(bearing((60.0, 0.0), (60.0, 10.0)) < 90.0);
+        // rounds to 360 in single precision
+        assert!((0.0..360.0).contains(&bearing((0.0, 0.0), (1.0, -1e-9))));
+    }
+}
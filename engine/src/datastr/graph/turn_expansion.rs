@@ -0,0 +1,323 @@
+//! Turn expanded graphs (line graphs) to model turn restrictions and turn costs.
+//!
+//! In the turn expanded graph, each arc of the original graph becomes a node (an edge-node) and each allowed turn becomes an arc.
+//! Edge-node ids are the arc ids of the original graph.
+//! The expanded graph itself can be built with `RandomLinkAccessGraph::line_graph`.
+//! This module contains the mapping between both graphs, so queries and paths can be translated back and forth.
+//! Alternatively, `TurnExpandedGraph` provides the turn expanded graph without materializing it.
+
+use super::*;
+
+/// Mapping between a graph and its turn expanded graph.
+#[derive(Debug, Clone)]
+pub struct TurnExpansion {
+    first_out: Vec<EdgeId>,
+    head: Vec<NodeId>,
+    tail: Vec<NodeId>,
+    first_in: Vec<EdgeId>,
+    incoming: Vec<EdgeId>,
+}
+
+impl TurnExpansion {
+    pub fn new<G: RandomLinkAccessGraph>(graph: &G) -> Self {
+        let n = graph.num_nodes();
+        let m = graph.num_arcs();
+
+        let mut first_out = Vec::with_capacity(n + 1);
+        first_out.push(0);
+        let mut tail = Vec::with_capacity(m);
+        let mut head = Vec::with_capacity(m);
+        let mut in_degrees = vec![0; n];
+        for node in 0..n as NodeId {
+            for edge in graph.neighbor_edge_indices(node) {
+                let link_head = graph.link(edge).node;
+                tail.push(node);
+                head.push(link_head);
+                in_degrees[link_head as usize] += 1;
+            }
+            first_out.push(tail.len() as EdgeId);
+        }
+
+        let first_in: Vec<EdgeId> = first_out_graph::degrees_to_first_out(in_degrees.into_iter()).collect();
+        let mut next_in = first_in.clone();
+        let mut incoming = vec![0; m];
+        for (edge, &link_head) in head.iter().enumerate() {
+            incoming[next_in[link_head as usize] as usize] = edge as EdgeId;
+            next_in[link_head as usize] += 1;
+        }
+
+        TurnExpansion {
+            first_out,
+            head,
+            tail,
+            first_in,
+            incoming,
+        }
+    }
+
+    /// Does the turn from `from_arc` to `to_arc` lead back to where we came from?
+    pub fn is_u_turn(&self, from_arc: EdgeId, to_arc: EdgeId) -> bool {
+        self.tail[from_arc as usize] == self.head[to_arc as usize]
+    }
+
+    pub fn num_arcs(&self) -> usize {
+        self.head.len()
+    }
+
+    pub fn tail(&self, edge: EdgeId) -> NodeId {
+        self.tail[edge as usize]
+    }
+
+    pub fn head(&self, edge: EdgeId) -> NodeId {
+        self.head[edge as usize]
+    }
+
+    /// Edge-nodes for the outgoing arcs of `node`
+    pub fn outgoing(&self, node: NodeId) -> Range<EdgeId> {
+        self.first_out[node as usize]..self.first_out[node as usize + 1]
+    }
+
+    /// Edge-nodes for the incoming arcs of `node`
+    pub fn incoming(&self, node: NodeId) -> &[EdgeId] {
+        &self.incoming[self.first_in[node as usize] as usize..self.first_in[node as usize + 1] as usize]
+    }
+
+    /// Translate a path of edge-nodes back to the nodes of the original graph.
+    pub fn node_path(&self, edge_path: &[EdgeId]) -> Vec<NodeId> {
+        edge_path
+            .first()
+            .map(|&edge| self.tail(edge))
+            .into_iter()
+            .chain(edge_path.iter().map(|&edge| self.head(edge)))
+            .collect()
+    }
+
+    /// Coordinates for edge-nodes, the middle between tail and head.
+    /// Useful for coordinate based ordering algorithms.
+    pub fn edge_node_coordinates(&self, lat: &[f32], lng: &[f32]) -> (Vec<f32>, Vec<f32>) {
+        let middle = |coords: &[f32]| -> Vec<f32> {
+            self.tail
+                .iter()
+                .zip(self.head.iter())
+                .map(|(&tail, &head)| (coords[tail as usize] + coords[head as usize]) / 2.0)
+                .collect()
+        };
+        (middle(lat), middle(lng))
+    }
+}
+
+/// Sparse per turn data as in the RoutingKit `forbidden_turn_from_arc` and `forbidden_turn_to_arc` files.
+/// Turns have to be sorted lexicographically by from and to arc.
+#[derive(Debug, Clone, Copy)]
+pub struct TurnTable<'a> {
+    from_arc: &'a [EdgeId],
+    to_arc: &'a [EdgeId],
+}
+
+impl<'a> TurnTable<'a> {
+    pub fn new(from_arc: &'a [EdgeId], to_arc: &'a [EdgeId]) -> Self {
+        assert_eq!(from_arc.len(), to_arc.len());
+        TurnTable { from_arc, to_arc }
+    }
+
+    /// Index of the turn from `from_arc` to `to_arc`, if it is contained.
+    pub fn find(&self, from_arc: EdgeId, to_arc: EdgeId) -> Option<usize> {
+        let (start, to_arcs) = self.turns_from(from_arc);
+        to_arcs.binary_search(&to_arc).ok().map(|idx| start + idx)
+    }
+
+    // Index of the first turn from `from_arc` and the sorted target arcs of all turns from `from_arc`.
+    fn turns_from(&self, from_arc: EdgeId) -> (usize, &'a [EdgeId]) {
+        let start = self.from_arc.partition_point(|&arc| arc < from_arc);
+        let end = start + self.from_arc[start..].partition_point(|&arc| arc == from_arc);
+        (start, &self.to_arc[start..end])
+    }
+}
+
+/// Which U-turns are allowed in a `TurnExpandedGraph`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UTurns {
+    Allowed,
+    Forbidden,
+    /// Only where they are the only way to continue
+    AtDeadEnds,
+}
+
+/// Implicit turn expanded graph.
+/// Behaves like the result of `RandomLinkAccessGraph::line_graph` with the same turn rules, including the edge ids,
+/// but enumerates turns on the fly from the original graph and sparse turn tables.
+/// Only an offset array with one entry per edge-node is stored.
+/// Random access to turns by edge id takes logarithmic time plus the degree of the intersection.
+#[derive(Debug)]
+pub struct TurnExpandedGraph<'a, G> {
+    graph: &'a G,
+    expansion: &'a TurnExpansion,
+    forbidden_turns: TurnTable<'a>,
+    turn_costs: TurnTable<'a>,
+    turn_cost: &'a [Weight],
+    u_turns: UTurns,
+    first_turn: Vec<EdgeId>,
+}
+
+impl<'a, G: RandomLinkAccessGraph> TurnExpandedGraph<'a, G> {
+    /// Create the implicit turn expanded graph of `graph`.
+    /// Turns in `turn_costs` get the respective cost from `turn_cost`, all other turns are free.
+    pub fn new(
+        graph: &'a G,
+        expansion: &'a TurnExpansion,
+        forbidden_turns: TurnTable<'a>,
+        turn_costs: TurnTable<'a>,
+        turn_cost: &'a [Weight],
+        u_turns: UTurns,
+    ) -> Self {
+        assert_eq!(turn_costs.to_arc.len(), turn_cost.len());
+        let mut turn_graph = TurnExpandedGraph {
+            graph,
+            expansion,
+            forbidden_turns,
+            turn_costs,
+            turn_cost,
+            u_turns,
+            first_turn: Vec::new(),
+        };
+
+        let mut first_turn = Vec::with_capacity(graph.num_arcs() + 1);
+        first_turn.push(0);
+        for arc in 0..graph.num_arcs() as EdgeId {
+            let num_turns = first_turn.last().unwrap() + turn_graph.turns(arc).count() as EdgeId;
+            first_turn.push(num_turns);
+        }
+        turn_graph.first_turn = first_turn;
+        turn_graph
+    }
+
+    fn turns(&self, from_arc: EdgeId) -> Turns<'_> {
+        let next_arcs = self.expansion.outgoing(self.expansion.head(from_arc));
+        let forbid_u_turn = match self.u_turns {
+            UTurns::Allowed => false,
+            UTurns::Forbidden => true,
+            UTurns::AtDeadEnds => next_arcs.end - next_arcs.start > 1,
+        };
+        let (_, forbidden) = self.forbidden_turns.turns_from(from_arc);
+        let (cost_offset, cost_to_arcs) = self.turn_costs.turns_from(from_arc);
+
+        Turns {
+            next_arcs,
+            weight: self.graph.link(from_arc).weight,
+            u_turn_head: if forbid_u_turn { Some(self.expansion.tail(from_arc)) } else { None },
+            forbidden,
+            cost_to_arcs,
+            costs: &self.turn_cost[cost_offset..cost_offset + cost_to_arcs.len()],
+            expansion: self.expansion,
+        }
+    }
+}
+
+/// Iterator over the allowed turns of an edge-node.
+/// Merges the (sorted) outgoing arcs at the intersection with the sorted turn table entries.
+#[derive(Debug, Clone)]
+pub struct Turns<'a> {
+    next_arcs: Range<EdgeId>,
+    weight: Weight,
+    u_turn_head: Option<NodeId>,
+    forbidden: &'a [EdgeId],
+    cost_to_arcs: &'a [EdgeId],
+    costs: &'a [Weight],
+    expansion: &'a TurnExpansion,
+}
+
+impl<'a> Iterator for Turns<'a> {
+    type Item = Link;
+
+    fn next(&mut self) -> Option<Link> {
+        for next_arc in &mut self.next_arcs {
+            while self.forbidden.first().map(|&arc| arc < next_arc).unwrap_or(false) {
+                self.forbidden = &self.forbidden[1..];
+            }
+            while self.cost_to_arcs.first().map(|&arc| arc < next_arc).unwrap_or(false) {
+                self.cost_to_arcs = &self.cost_to_arcs[1..];
+                self.costs = &self.costs[1..];
+            }
+
+            if self.forbidden.first() == Some(&next_arc) || self.u_turn_head == Some(self.expansion.head(next_arc)) {
+                continue;
+            }
+            let turn_cost = if self.cost_to_arcs.first() == Some(&next_arc) { self.costs[0] } else { 0 };
+            return Some(Link {
+                node: next_arc,
+                weight: self.weight + turn_cost,
+            });
+        }
+        None
+    }
+}
+
+impl<'a, G> Graph for TurnExpandedGraph<'a, G> {
+    fn num_nodes(&self) -> usize {
+        self.first_turn.len() - 1
+    }
+
+    fn num_arcs(&self) -> usize {
+        *self.first_turn.last().unwrap() as usize
+    }
+
+    fn degree(&self, node: NodeId) -> usize {
+        (self.first_turn[node as usize + 1] - self.first_turn[node as usize]) as usize
+    }
+}
+
+impl<'a, 'g, G: RandomLinkAccessGraph> LinkIterable<'a, Link> for TurnExpandedGraph<'g, G> {
+    type Iter = Turns<'a>;
+
+    #[inline]
+    fn link_iter(&'a self, node: NodeId) -> Self::Iter {
+        self.turns(node)
+    }
+}
+
+impl<'a, 'g, G: RandomLinkAccessGraph> LinkIterable<'a, NodeId> for TurnExpandedGraph<'g, G> {
+    type Iter = std::iter::Map<Turns<'a>, fn(Link) -> NodeId>;
+
+    #[inline]
+    fn link_iter(&'a self, node: NodeId) -> Self::Iter {
+        self.turns(node).map(|link| link.node)
+    }
+}
+
+impl<'a, G: RandomLinkAccessGraph> RandomLinkAccessGraph for TurnExpandedGraph<'a, G> {
+    fn link(&self, edge_id: EdgeId) -> Link {
+        let from_arc = self.first_turn.partition_point(|&first| first <= edge_id) - 1;
+        self.turns(from_arc as EdgeId).nth((edge_id - self.first_turn[from_arc]) as usize).unwrap()
+    }
+
+    fn edge_index(&self, from: NodeId, to: NodeId) -> Option<EdgeId> {
+        self.turns(from)
+            .position(|link| link.node == to)
+            .map(|idx| self.first_turn[from as usize] + idx as EdgeId)
+    }
+
+    fn neighbor_edge_indices(&self, node: NodeId) -> Range<EdgeId> {
+        self.first_turn[node as usize]..self.first_turn[node as usize + 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_are_found_by_from_and_to_arc() {
+        let from_arc = [0, 0, 2, 2, 2, 5];
+        let to_arc = [1, 3, 0, 4, 7, 2];
+        let turns = TurnTable::new(&from_arc, &to_arc);
+
+        for (idx, (&from, &to)) in from_arc.iter().zip(to_arc.iter()).enumerate() {
+            assert_eq!(turns.find(from, to), Some(idx));
+        }
+        assert_eq!(turns.find(0, 2), None);
+        assert_eq!(turns.find(1, 3), None);
+        assert_eq!(turns.find(2, 5), None);
+        assert_eq!(turns.find(6, 2), None);
+        assert_eq!(TurnTable::new(&[], &[]).find(0, 0), None);
+    }
+}
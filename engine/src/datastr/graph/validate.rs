@@ -0,0 +1,217 @@
+//! Consistency checks for graph data.
+//!
+//! Many algorithms silently assume certain invariants of their input data,
+//! for example that `first_out` is sorted or that all head nodes are valid node ids.
+//! The checks in this module verify these invariants and collect all violations instead of stopping at the first one.
+//! Each check produces a `CheckResult` which can be turned into JSON for reporting.
+
+use super::time_dependent::{period, Timestamp};
+use super::*;
+use serde_json::{json, Value};
+use std::fmt::Debug;
+
+/// Only this many violations are recorded with details for each check, the rest is only counted.
+pub const MAX_RECORDED_VIOLATIONS: usize = 1000;
+
+/// Result of checking a single invariant.
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    /// Name of the checked invariant
+    pub check: String,
+    /// Total number of violations
+    pub num_violations: usize,
+    /// The first `MAX_RECORDED_VIOLATIONS` violations - the index of the offending element and a description
+    pub violations: Vec<(usize, String)>,
+}
+
+impl CheckResult {
+    fn new(check: impl Into<String>) -> Self {
+        CheckResult {
+            check: check.into(),
+            num_violations: 0,
+            violations: Vec::new(),
+        }
+    }
+
+    // messages are created lazily, so checks on large valid graphs don't allocate
+    fn violation(&mut self, index: usize, message: impl FnOnce() -> String) {
+        if self.num_violations < MAX_RECORDED_VIOLATIONS {
+            self.violations.push((index, message()));
+        }
+        self.num_violations += 1;
+    }
+
+    /// Was the invariant fulfilled?
+    pub fn is_ok(&self) -> bool {
+        self.num_violations == 0
+    }
+
+    /// Structured representation for reporting.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "check": self.check,
+            "ok": self.is_ok(),
+            "num_violations": self.num_violations,
+            "violations": self.violations.iter().map(|(index, message)| json!({ "index": index, "message": message })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Check that a data vector has the expected number of elements.
+pub fn length(name: &str, len: usize, expected: usize) -> CheckResult {
+    let mut result = CheckResult::new(format!("{} length", name));
+    if len != expected {
+        result.violation(len, || format!("{} has {} elements but {} are expected", name, len, expected));
+    }
+    result
+}
+
+/// A data file which could not be loaded at all.
+/// Recorded as a failed check, so the remaining files can still be validated.
+pub fn loadable(name: &str, error: &dyn std::error::Error) -> CheckResult {
+    let mut result = CheckResult::new(format!("{} loadable", name));
+    result.violation(0, || format!("{} could not be loaded: {}", name, error));
+    result
+}
+
+/// Check an adjacency array index (`first_out` or `first_ipp_of_arc`):
+/// it has to start with 0, be sorted and end with the number of indexed elements.
+pub fn index_array(name: &str, first_out: &[u32], num_elements: usize) -> CheckResult {
+    let mut result = CheckResult::new(format!("{} valid", name));
+    match first_out.first() {
+        None => result.violation(0, || format!("{} is empty", name)),
+        Some(&first) if first != 0 => result.violation(0, || format!("{} starts with {} instead of 0", name, first)),
+        _ => (),
+    }
+    for (index, window) in first_out.windows(2).enumerate() {
+        if window[0] > window[1] {
+            result.violation(index, || format!("{} decreases from {} to {}", name, window[0], window[1]));
+        }
+    }
+    if let Some(&last) = first_out.last() {
+        if last as usize != num_elements {
+            result.violation(first_out.len() - 1, || {
+                format!("{} ends with {} but there are {} elements", name, last, num_elements)
+            });
+        }
+    }
+    result
+}
+
+/// Check that all head nodes are valid node ids.
+pub fn head_in_range(head: &[NodeId], num_nodes: usize) -> CheckResult {
+    let mut result = CheckResult::new("head in range");
+    for (edge, &node) in head.iter().enumerate() {
+        if node as usize >= num_nodes {
+            result.violation(edge, || format!("head {} is not a valid node id", node));
+        }
+    }
+    result
+}
+
+/// Check that no arc has a weight of `INFINITY` or more.
+pub fn weights(name: &str, weights: &[Weight]) -> CheckResult {
+    let mut result = CheckResult::new(format!("{} below infinity", name));
+    for (edge, &weight) in weights.iter().enumerate() {
+        if weight >= INFINITY {
+            result.violation(edge, || format!("weight {} is not below infinity", weight));
+        }
+    }
+    result
+}
+
+/// Check that the values are strictly increasing, e.g. for `osm_node_ids`.
+pub fn strictly_increasing<T: Ord + Debug>(name: &str, values: &[T]) -> CheckResult {
+    let mut result = CheckResult::new(format!("{} strictly increasing", name));
+    for (index, window) in values.windows(2).enumerate() {
+        if window[0] >= window[1] {
+            result.violation(index + 1, || format!("{:?} follows {:?}", window[1], window[0]));
+        }
+    }
+    result
+}
+
+/// Check that `order` is a permutation of `0..n`, e.g. for `cch_perm`.
+pub fn permutation(name: &str, order: &[NodeId]) -> CheckResult {
+    let mut result = CheckResult::new(format!("{} is permutation", name));
+    let mut seen = vec![false; order.len()];
+    for (index, &node) in order.iter().enumerate() {
+        if node as usize >= order.len() {
+            result.violation(index, || format!("{} is out of range", node));
+        } else if seen[node as usize] {
+            result.violation(index, || format!("{} occurs more than once", node));
+        } else {
+            seen[node as usize] = true;
+        }
+    }
+    result
+}
+
+/// Check that coordinates are finite and within the valid ranges for latitude and longitude.
+pub fn coordinates(latitude: &[f32], longitude: &[f32]) -> CheckResult {
+    let mut result = CheckResult::new("coordinates valid");
+    for (node, (&lat, &lng)) in latitude.iter().zip(longitude.iter()).enumerate() {
+        if !(lat.is_finite() && (-90.0..=90.0).contains(&lat) && lng.is_finite() && (-180.0..=180.0).contains(&lng)) {
+            result.violation(node, || format!("invalid coordinate ({}, {})", lat, lng));
+        }
+    }
+    result
+}
+
+/// Check the travel time functions of a time-dependent graph.
+/// Assumes that `first_ipp_of_arc` was already checked with `index_array`.
+/// The departure times of each arc have to be strictly increasing and within one period,
+/// travel times have to be below `INFINITY` and the functions have to fulfill the FIFO property,
+/// that is departing later never results in arriving earlier (including the wraparound at the end of the period).
+pub fn travel_time_functions(first_ipp_of_arc: &[u32], ipp_departure_time: &[Timestamp], ipp_travel_time: &[Weight]) -> Vec<CheckResult> {
+    let mut non_empty = CheckResult::new("ttfs not empty");
+    let mut departure_times = CheckResult::new("ipp_departure_time strictly increasing within period");
+    let mut travel_times = CheckResult::new("ipp_travel_time below infinity");
+    let mut fifo = CheckResult::new("ttfs fifo");
+
+    for (edge, range) in first_ipp_of_arc.windows(2).enumerate() {
+        let range = range[0] as usize..range[1] as usize;
+        if range.start >= range.end {
+            non_empty.violation(edge, || "arc has no interpolation points".to_string());
+            continue;
+        }
+        let departures = &ipp_departure_time[range.clone()];
+        let tts = &ipp_travel_time[range.clone()];
+
+        for (ipp, (&departure, &tt)) in departures.iter().zip(tts.iter()).enumerate() {
+            if departure > period() {
+                departure_times.violation(range.start + ipp, || format!("departure time {} exceeds period", departure));
+            }
+            if tt >= INFINITY {
+                travel_times.violation(range.start + ipp, || format!("travel time {} is not below infinity", tt));
+            }
+        }
+
+        for (ipp, (departure_window, tt_window)) in departures.windows(2).zip(tts.windows(2)).enumerate() {
+            if departure_window[0] >= departure_window[1] {
+                departure_times.violation(range.start + ipp + 1, || {
+                    format!("departure time {} follows {}", departure_window[1], departure_window[0])
+                });
+            } else if u64::from(departure_window[0]) + u64::from(tt_window[0]) > u64::from(departure_window[1]) + u64::from(tt_window[1]) {
+                fifo.violation(range.start + ipp + 1, || {
+                    format!(
+                        "departing at {} arrives at {} but departing at {} arrives at {}",
+                        departure_window[1],
+                        u64::from(departure_window[1]) + u64::from(tt_window[1]),
+                        departure_window[0],
+                        u64::from(departure_window[0]) + u64::from(tt_window[0])
+                    )
+                });
+            }
+        }
+
+        // the function continues periodically, so the last point has to be compatible with the first point of the next period
+        let last_arrival = u64::from(*departures.last().unwrap()) + u64::from(*tts.last().unwrap());
+        let next_departure = u64::from(departures[0]) + u64::from(period());
+        if departures.len() > 1 && last_arrival > next_departure + u64::from(tts[0]) {
+            fifo.violation(range.end - 1, || "ttf violates fifo at the wraparound of the period".to_string());
+        }
+    }
+
+    vec![non_empty, departure_times, travel_times, fifo]
+}
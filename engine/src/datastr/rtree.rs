@@ -0,0 +1,257 @@
+//! Static R-tree for nearest neighbor queries.
+//!
+//! The tree is bulk loaded once with Sort-Tile-Recursive packing and can not be modified afterwards.
+//! Nodes are stored level by level in flat arrays.
+//! The children of node `i` are the entries `i * NODE_CAPACITY..(i + 1) * NODE_CAPACITY` of the level below (or of the items for the lowest level).
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+const NODE_CAPACITY: usize = 16;
+
+/// Axis aligned bounding box over two dimensional coordinates, e.g. `[latitude, longitude]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl BoundingBox {
+    /// Smallest box containing all the points.
+    /// Panics if there are no points.
+    pub fn from_points(points: &[[f32; 2]]) -> Self {
+        let mut bounding_box = BoundingBox {
+            min: points[0],
+            max: points[0],
+        };
+        for point in &points[1..] {
+            bounding_box = bounding_box.merge(&BoundingBox { min: *point, max: *point });
+        }
+        bounding_box
+    }
+
+    /// Smallest box containing both boxes.
+    pub fn merge(&self, other: &Self) -> Self {
+        BoundingBox {
+            min: [self.min[0].min(other.min[0]), self.min[1].min(other.min[1])],
+            max: [self.max[0].max(other.max[0]), self.max[1].max(other.max[1])],
+        }
+    }
+
+    pub fn center(&self) -> [f32; 2] {
+        [(self.min[0] + self.max[0]) / 2.0, (self.min[1] + self.max[1]) / 2.0]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RTree<T> {
+    // bounding boxes of the nodes from the lowest level to the root
+    levels: Vec<Vec<BoundingBox>>,
+    items: Vec<(BoundingBox, T)>,
+}
+
+impl<T> RTree<T> {
+    pub fn new(mut items: Vec<(BoundingBox, T)>) -> Self {
+        // sort into vertical slices by the first coordinate, then each slice by the second coordinate
+        let num_slices = (items.len() as f64 / NODE_CAPACITY as f64).sqrt().ceil() as usize;
+        let slice_len = std::cmp::max(num_slices * NODE_CAPACITY, 1);
+        items.sort_by(|(a, _), (b, _)| a.center()[0].total_cmp(&b.center()[0]));
+        for slice in items.chunks_mut(slice_len) {
+            slice.sort_by(|(a, _), (b, _)| a.center()[1].total_cmp(&b.center()[1]));
+        }
+
+        let item_boxes: Vec<BoundingBox> = items.iter().map(|(bounding_box, _)| *bounding_box).collect();
+        let mut levels = vec![parent_boxes(&item_boxes)];
+        while levels.last().unwrap().len() > 1 {
+            let parents = parent_boxes(levels.last().unwrap());
+            levels.push(parents);
+        }
+
+        RTree { levels, items }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Iterate over all items by increasing distance together with the distance.
+    /// `box_distance` has to be a lower bound for `item_distance` of all items within the box.
+    pub fn nearest<B, I>(&self, box_distance: B, item_distance: I) -> NearestNeighbors<'_, T, B, I>
+    where
+        B: Fn(&BoundingBox) -> f64,
+        I: Fn(&T) -> f64,
+    {
+        let root_level = self.levels.len() - 1;
+        let queue = self.levels[root_level]
+            .iter()
+            .enumerate()
+            .map(|(index, bounding_box)| Candidate {
+                distance: box_distance(bounding_box),
+                entry: Entry::Node { level: root_level, index },
+            })
+            .collect();
+
+        NearestNeighbors {
+            tree: self,
+            box_distance,
+            item_distance,
+            queue,
+        }
+    }
+}
+
+// bounding boxes of the nodes containing the given children
+fn parent_boxes(children: &[BoundingBox]) -> Vec<BoundingBox> {
+    children
+        .chunks(NODE_CAPACITY)
+        .map(|chunk| chunk[1..].iter().fold(chunk[0], |merged, bounding_box| merged.merge(bounding_box)))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    Node { level: usize, index: usize },
+    Item(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+    distance: f64,
+    entry: Entry,
+}
+
+impl Eq for Candidate {}
+
+// reversed, so the `BinaryHeap` pops the closest candidate first
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Best first traversal of an `RTree`, see `RTree::nearest`.
+#[derive(Debug)]
+pub struct NearestNeighbors<'t, T, B, I> {
+    tree: &'t RTree<T>,
+    box_distance: B,
+    item_distance: I,
+    queue: BinaryHeap<Candidate>,
+}
+
+impl<'t, T, B, I> Iterator for NearestNeighbors<'t, T, B, I>
+where
+    B: Fn(&BoundingBox) -> f64,
+    I: Fn(&T) -> f64,
+{
+    type Item = (f64, &'t T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(Candidate { distance, entry }) = self.queue.pop() {
+            match entry {
+                Entry::Item(index) => return Some((distance, &self.tree.items[index].1)),
+                Entry::Node { level, index } => {
+                    let num_children = if level == 0 {
+                        self.tree.items.len()
+                    } else {
+                        self.tree.levels[level - 1].len()
+                    };
+                    for child in index * NODE_CAPACITY..std::cmp::min((index + 1) * NODE_CAPACITY, num_children) {
+                        let candidate = if level == 0 {
+                            Candidate {
+                                distance: (self.item_distance)(&self.tree.items[child].1),
+                                entry: Entry::Item(child),
+                            }
+                        } else {
+                            Candidate {
+                                distance: (self.box_distance)(&self.tree.levels[level - 1][child]),
+                                entry: Entry::Node {
+                                    level: level - 1,
+                                    index: child,
+                                },
+                            }
+                        };
+                        self.queue.push(candidate);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    fn distance(point: [f32; 2], query: [f32; 2]) -> f64 {
+        f64::from(((point[0] - query[0]).powi(2) + (point[1] - query[1]).powi(2)).sqrt())
+    }
+
+    fn box_distance(bounding_box: &BoundingBox, query: [f32; 2]) -> f64 {
+        distance(
+            [
+                query[0].max(bounding_box.min[0]).min(bounding_box.max[0]),
+                query[1].max(bounding_box.min[1]).min(bounding_box.max[1]),
+            ],
+            query,
+        )
+    }
+
+    #[test]
+    fn items_are_yielded_by_distance() {
+        let mut rng = StdRng::from_seed([5; 32]);
+        let points: Vec<[f32; 2]> = (0..1000).map(|_| [rng.gen_range(-10.0, 10.0), rng.gen_range(-10.0, 10.0)]).collect();
+        let tree = RTree::new(points.iter().map(|&point| (BoundingBox::from_points(&[point]), point)).collect());
+        assert_eq!(tree.len(), points.len());
+
+        let query = [1.5, -2.5];
+        let nearest: Vec<f64> = tree
+            .nearest(|bounding_box| box_distance(bounding_box, query), |&point| distance(point, query))
+            .map(|(dist, _)| dist)
+            .collect();
+        assert_eq!(nearest.len(), points.len());
+        assert!(nearest.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(nearest[0], points.iter().map(|&point| distance(point, query)).fold(f64::INFINITY, f64::min));
+    }
+
+    #[test]
+    fn empty_tree_yields_nothing() {
+        let tree = RTree::<()>::new(Vec::new());
+        assert!(tree.is_empty());
+        assert_eq!(tree.nearest(|bounding_box| box_distance(bounding_box, [0.0, 0.0]), |_| 0.0).count(), 0);
+    }
+
+    #[test]
+    fn nan_coordinates_do_not_panic() {
+        let points = vec![[0.0, 0.0], [f32::NAN, 1.0], [2.0, f32::NAN], [3.0, 3.0]];
+        let tree = RTree::new(points.iter().map(|&point| (BoundingBox::from_points(&[point]), point)).collect());
+        assert_eq!(
+            tree.nearest(|bounding_box| box_distance(bounding_box, [0.0, 0.0]), |&point| distance(point, [0.0, 0.0]))
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn bounding_boxes_are_merged() {
+        let bounding_box = BoundingBox::from_points(&[[1.0, 5.0], [-1.0, 2.0], [0.0, 7.0]]);
+        assert_eq!(
+            bounding_box,
+            BoundingBox {
+                min: [-1.0, 2.0],
+                max: [1.0, 7.0]
+            }
+        );
+        assert_eq!(bounding_box.center(), [0.0, 4.5]);
+    }
+}
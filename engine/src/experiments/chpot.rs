@@ -1,12 +1,18 @@
+#[cfg(feature = "chpot-alt")]
+use crate::algo::alt::{self, *};
+#[cfg(any(feature = "chpot-only-topo", feature = "chpot-oracle", feature = "chpot-cch", not(feature = "chpot-alt")))]
+use crate::algo::ch_potentials::*;
 #[cfg(feature = "chpot-cch")]
 use crate::algo::customizable_contraction_hierarchy::*;
+#[cfg(any(feature = "chpot-cch", not(any(feature = "chpot-only-topo", feature = "chpot-alt"))))]
+use crate::datastr::node_order::NodeOrder;
 use crate::{
     algo::{
-        ch_potentials::{query::Server as TopoServer, *},
+        ch_potentials::query::Server as TopoServer,
         dijkstra::{generic_dijkstra::DefaultOps, query::dijkstra::Server as DijkServer},
         *,
     },
-    datastr::{graph::*, node_order::NodeOrder},
+    datastr::graph::*,
     io::*,
     report::*,
 };
@@ -22,6 +28,10 @@ pub const NUM_QUERIES: usize = 10000;
 #[cfg(override_chpot_num_queries)]
 pub const NUM_QUERIES: usize = include!(concat!(env!("OUT_DIR"), "/CHPOT_NUM_QUERIES"));
 
+/// Number of landmarks selected when the graph directory contains no precomputed ALT data (`alt` subdirectory).
+#[cfg(feature = "chpot-alt")]
+pub const NUM_LANDMARKS: usize = 16;
+
 pub fn run(
     path: &Path,
     modify_travel_time: impl FnOnce(&FirstOutGraph<&[EdgeId], &[NodeId], &[Weight]>, &mut StdRng, &mut [Weight]) -> Result<(), Box<dyn Error>>,
@@ -75,6 +85,19 @@ pub fn run(
         contract(&graph, cch_order)
     };
 
+    #[cfg(feature = "chpot-alt")]
+    let landmarks = {
+        let _alt_ctxt = algo_runs_ctxt.push_collection_item();
+        let alt_folder = path.join("alt");
+        if alt_folder.join("landmarks").exists() {
+            Landmarks::reconstruct_from(&alt_folder)?
+        } else {
+            // separate rng, so the queries are the same as without ALT
+            let landmarks = avoid_landmarks(&graph, NUM_LANDMARKS, &mut StdRng::from_seed(seed));
+            Landmarks::new(&graph, landmarks)
+        }
+    };
+
     let potential = {
         #[cfg(feature = "chpot-only-topo")]
         {
@@ -87,7 +110,11 @@ pub fn run(
                 let _potential_ctxt = algo_runs_ctxt.push_collection_item();
                 CCHPotential::new(&cch, &graph)
             }
-            #[cfg(not(feature = "chpot-cch"))]
+            #[cfg(feature = "chpot-alt")]
+            {
+                ALTPotential::new(&landmarks)
+            }
+            #[cfg(not(any(feature = "chpot-cch", feature = "chpot-alt")))]
             {
                 let forward_first_out = Vec::<EdgeId>::load_from(path.join("lower_bound_ch/forward_first_out"))?;
                 let forward_head = Vec::<NodeId>::load_from(path.join("lower_bound_ch/forward_head"))?;
@@ -162,6 +189,30 @@ pub fn run(
         eprintln!("Avg. query time {}", total_query_time / (query_count as i32))
     };
 
+    #[cfg(feature = "chpot-alt")]
+    {
+        let mut bidir_alt = alt::Server::new(
+            FirstOutGraph::new(modified_graph.first_out(), modified_graph.head(), modified_graph.weight()),
+            &landmarks,
+        );
+
+        for _i in 0..NUM_QUERIES {
+            let _query_ctxt = algo_runs_ctxt.push_collection_item();
+            let from: NodeId = rng.gen_range(0, graph.num_nodes() as NodeId);
+            let to: NodeId = rng.gen_range(0, graph.num_nodes() as NodeId);
+
+            report!("algo", "bidirectional_alt");
+            report!("from", from);
+            report!("to", to);
+
+            let (mut res, time) = measure(|| QueryServer::query(&mut bidir_alt, Query { from, to }));
+            report!("running_time_ms", time.to_std().unwrap().as_nanos() as f64 / 1_000_000.0);
+            let dist = res.as_ref().map(|res| res.distance());
+            report!("result", dist);
+            res.as_mut().map(|res| res.path());
+        }
+    }
+
     let mut server = DijkServer::<DefaultOps, _, _>::new(modified_graph);
 
     for _i in 0..super::NUM_DIJKSTRA_QUERIES {
@@ -1,4 +1,4 @@
-use crate::datastr::graph::*;
+use crate::datastr::graph::{arc_geometry::ArcGeometry, turn_costs::bearing, *};
 use crate::datastr::rank_select_map::{BitVec, RankSelectMap};
 use crate::util::in_range_option::*;
 use std::error::Error;
@@ -140,6 +140,12 @@ impl RdfLinkGeometry {
 pub struct HereData {
     pub graph: OwnedGraph,
     pub link_lengths: Vec<f64>,
+    /// Direction of each arc at its tail in degrees, derived from the link geometry, see `turn_costs::ArcBearings`
+    pub arc_start_bearings: Vec<f32>,
+    /// Direction of each arc at its head in degrees, derived from the link geometry
+    pub arc_end_bearings: Vec<f32>,
+    /// Shape points of each arc from the link geometry
+    pub arc_geometry: ArcGeometry,
     pub functional_road_classes: Vec<u8>,
     pub lat: Vec<f32>,
     pub lng: Vec<f32>,
@@ -273,6 +279,9 @@ pub fn read_graph(source: &dyn RdfDataSource, (min_lat, min_lon): (i64, i64), (m
     let mut head: Vec<NodeId> = vec![0; m as usize];
     let mut travel_times: Vec<Weight> = vec![0; m as usize];
     let mut link_lengths: Vec<f64> = vec![0.0; m as usize];
+    let mut arc_start_bearings: Vec<f32> = vec![0.0; m as usize];
+    let mut arc_end_bearings: Vec<f32> = vec![0.0; m as usize];
+    let mut arc_shapes: Vec<Vec<(f32, f32)>> = vec![Vec::new(); m as usize];
     let mut functional_road_classes: Vec<u8> = vec![0; m as usize];
     let mut here_rank_to_link_id: Vec<(InRangeOption<EdgeId>, InRangeOption<EdgeId>)> = vec![(InRangeOption::new(None), InRangeOption::new(None)); links.len()];
 
@@ -292,6 +301,28 @@ pub fn read_graph(source: &dyn RdfDataSource, (min_lat, min_lon): (i64, i64), (m
             let from_node = node_id_mapping.at(link.ref_node_id as usize);
             let to_node = node_id_mapping.at(link.nonref_node_id as usize);
 
+            // bearings at both ends of the link in `FromRef` direction, the `ToRef` ones are the opposite directions
+            let shape: Vec<(f64, f64)> = if link_geometries[link_index].len() >= 2 {
+                link_geometries[link_index]
+                    .iter()
+                    .map(|point| ((point.lat as f64) / 10_000_000., (point.lon as f64) / 10_000_000.))
+                    .collect()
+            } else {
+                [&nodes[from_node], &nodes[to_node]]
+                    .iter()
+                    .map(|node| ((node.lat as f64) / 100_000., (node.lon as f64) / 100_000.))
+                    .collect()
+            };
+            let from_ref_bearings = (bearing(shape[0], shape[1]), bearing(shape[shape.len() - 2], shape[shape.len() - 1]));
+            let to_ref_bearings = ((from_ref_bearings.1 + 180.0) % 360.0, (from_ref_bearings.0 + 180.0) % 360.0);
+            // the link geometry includes the positions of both nodes, only the points in between are kept
+            let from_ref_shape: Vec<(f32, f32)> = if shape.len() > 2 {
+                shape[1..shape.len() - 1].iter().map(|&(lat, lng)| (lat as f32, lng as f32)).collect()
+            } else {
+                Vec::new()
+            };
+            let to_ref_shape: Vec<(f32, f32)> = from_ref_shape.iter().rev().cloned().collect();
+
             let from_weight = (1000. * length / nav_link.speed_in_m_per_s(RdfLinkDirection::FromRef)).round() as Weight;
             let to_weight = (1000. * length / nav_link.speed_in_m_per_s(RdfLinkDirection::ToRef)).round() as Weight;
 
@@ -300,6 +331,9 @@ pub fn read_graph(source: &dyn RdfDataSource, (min_lat, min_lon): (i64, i64), (m
                     head[first_out[from_node] as usize] = to_node as NodeId;
                     travel_times[first_out[from_node] as usize] = from_weight;
                     link_lengths[first_out[from_node] as usize] = length;
+                    arc_start_bearings[first_out[from_node] as usize] = from_ref_bearings.0;
+                    arc_end_bearings[first_out[from_node] as usize] = from_ref_bearings.1;
+                    arc_shapes[first_out[from_node] as usize] = from_ref_shape.clone();
                     functional_road_classes[first_out[from_node] as usize] = nav_link.functional_class;
                     here_rank_to_link_id[link_index].0 = InRangeOption::new(Some(first_out[from_node]));
                     first_out[from_node] += 1;
@@ -308,6 +342,9 @@ pub fn read_graph(source: &dyn RdfDataSource, (min_lat, min_lon): (i64, i64), (m
                     head[first_out[to_node] as usize] = from_node as NodeId;
                     travel_times[first_out[to_node] as usize] = to_weight;
                     link_lengths[first_out[to_node] as usize] = length;
+                    arc_start_bearings[first_out[to_node] as usize] = to_ref_bearings.0;
+                    arc_end_bearings[first_out[to_node] as usize] = to_ref_bearings.1;
+                    arc_shapes[first_out[to_node] as usize] = to_ref_shape.clone();
                     functional_road_classes[first_out[to_node] as usize] = nav_link.functional_class;
                     here_rank_to_link_id[link_index].1 = InRangeOption::new(Some(first_out[to_node]));
                     first_out[to_node] += 1;
@@ -316,6 +353,9 @@ pub fn read_graph(source: &dyn RdfDataSource, (min_lat, min_lon): (i64, i64), (m
                     head[first_out[from_node] as usize] = to_node as NodeId;
                     travel_times[first_out[from_node] as usize] = from_weight;
                     link_lengths[first_out[from_node] as usize] = length;
+                    arc_start_bearings[first_out[from_node] as usize] = from_ref_bearings.0;
+                    arc_end_bearings[first_out[from_node] as usize] = from_ref_bearings.1;
+                    arc_shapes[first_out[from_node] as usize] = from_ref_shape.clone();
                     functional_road_classes[first_out[from_node] as usize] = nav_link.functional_class;
                     here_rank_to_link_id[link_index].0 = InRangeOption::new(Some(first_out[from_node]));
                     first_out[from_node] += 1;
@@ -323,6 +363,9 @@ pub fn read_graph(source: &dyn RdfDataSource, (min_lat, min_lon): (i64, i64), (m
                     head[first_out[to_node] as usize] = from_node as NodeId;
                     travel_times[first_out[to_node] as usize] = to_weight;
                     link_lengths[first_out[to_node] as usize] = length;
+                    arc_start_bearings[first_out[to_node] as usize] = to_ref_bearings.0;
+                    arc_end_bearings[first_out[to_node] as usize] = to_ref_bearings.1;
+                    arc_shapes[first_out[to_node] as usize] = to_ref_shape.clone();
                     functional_road_classes[first_out[to_node] as usize] = nav_link.functional_class;
                     here_rank_to_link_id[link_index].1 = InRangeOption::new(Some(first_out[to_node]));
                     first_out[to_node] += 1;
@@ -343,6 +386,9 @@ pub fn read_graph(source: &dyn RdfDataSource, (min_lat, min_lon): (i64, i64), (m
     HereData {
         graph,
         link_lengths,
+        arc_start_bearings,
+        arc_end_bearings,
+        arc_geometry: ArcGeometry::new(&arc_shapes),
         functional_road_classes,
         lat,
         lng,
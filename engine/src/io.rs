@@ -15,12 +15,21 @@
 //! head.write_to(&"output_file")?;
 //! # Ok::<(), Box<dyn std::error::Error>>(())
 //! ```
+//!
+//! Large files can also be memory mapped with `MmapSlice::map_from` instead of being loaded into a `Vec`.
+//!
+//! The files are headerless by default, compatible with RoutingKit.
+//! `StoreWithHeader` and `LoadWithHeader` (or `MmapSlice::map_with_header_from`) additionally write and validate
+//! a header describing the element type, the number of elements and the endianness.
 
+use crate::as_slice::AsSlice;
 use std::{
     ffi::OsStr,
     fs::{metadata, File},
-    io::{prelude::*, Result},
+    io::{prelude::*, Error, ErrorKind, Result, SeekFrom},
+    marker::PhantomData,
     mem,
+    ops::Deref,
     path::Path,
     slice,
 };
@@ -118,6 +127,271 @@ impl<T: Default + Copy> Load for Vec<T> {
     }
 }
 
+/// Magic bytes at the start of files with a self-describing header.
+const HEADER_MAGIC: [u8; 8] = *b"RRRDATA\0";
+const HEADER_VERSION: u32 = 1;
+// Written in native byte order, so files written on a machine with a different endianness can be detected.
+const ENDIANNESS_MARKER: u32 = 0x0102_0304;
+const HEADER_LEN: usize = 32;
+
+/// The kind of elements stored in a file with a header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ElementKind {
+    Unsigned = 1,
+    Signed = 2,
+    Float = 3,
+}
+
+/// Element types which can be described by a file header.
+/// Together with the size of the type, the kind identifies the element type.
+pub trait HeaderElement: Copy + Default {
+    const KIND: ElementKind;
+}
+
+macro_rules! impl_header_element {
+    ($kind:ident: $($t:ty),+) => {
+        $(
+            impl HeaderElement for $t {
+                const KIND: ElementKind = ElementKind::$kind;
+            }
+        )+
+    };
+}
+
+impl_header_element!(Unsigned: u8, u16, u32, u64);
+impl_header_element!(Signed: i8, i16, i32, i64);
+impl_header_element!(Float: f32, f64);
+
+// The header consists of the magic bytes, the endianness marker, the format version,
+// the element kind, the element size and the number of elements.
+fn header<T: HeaderElement>(len: usize) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[0..8].copy_from_slice(&HEADER_MAGIC);
+    header[8..12].copy_from_slice(&ENDIANNESS_MARKER.to_ne_bytes());
+    header[12..16].copy_from_slice(&HEADER_VERSION.to_ne_bytes());
+    header[16..20].copy_from_slice(&(T::KIND as u32).to_ne_bytes());
+    header[20..24].copy_from_slice(&(mem::size_of::<T>() as u32).to_ne_bytes());
+    header[24..32].copy_from_slice(&(len as u64).to_ne_bytes());
+    header
+}
+
+fn invalid_data(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+// Determine the number of elements in the file and where they start.
+// Files starting with the magic bytes get their header validated, all other files are treated as legacy files with raw data.
+// Leaves the file positioned at the start of the data.
+fn read_header<T: HeaderElement>(file: &mut File) -> Result<(usize, usize)> {
+    let num_bytes = file.metadata()?.len() as usize;
+    let size = mem::size_of::<T>();
+
+    if num_bytes >= HEADER_LEN {
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header)?;
+
+        if header[0..8] == HEADER_MAGIC {
+            let u32_at = |pos: usize| u32::from_ne_bytes([header[pos], header[pos + 1], header[pos + 2], header[pos + 3]]);
+            let mut len_bytes = [0; 8];
+            len_bytes.copy_from_slice(&header[24..32]);
+            let len = u64::from_ne_bytes(len_bytes) as usize;
+
+            if u32_at(8) != ENDIANNESS_MARKER {
+                return Err(invalid_data("file was written on a machine with a different endianness"));
+            }
+            if u32_at(12) != HEADER_VERSION {
+                return Err(invalid_data(format!("unsupported header version {}", u32_at(12))));
+            }
+            if u32_at(16) != T::KIND as u32 || u32_at(20) as usize != size {
+                return Err(invalid_data(format!(
+                    "file contains elements of kind {} and size {} but {} was expected",
+                    u32_at(16),
+                    u32_at(20),
+                    std::any::type_name::<T>()
+                )));
+            }
+            if len.checked_mul(size) != Some(num_bytes - HEADER_LEN) {
+                return Err(invalid_data(format!("header announces {} elements but file size does not match", len)));
+            }
+
+            return Ok((len, HEADER_LEN));
+        }
+
+        file.seek(SeekFrom::Start(0))?;
+    }
+
+    let len = num_bytes / size;
+    if len * size != num_bytes {
+        return Err(invalid_data(format!(
+            "file size {} is not a multiple of the size of {}",
+            num_bytes,
+            std::any::type_name::<T>()
+        )));
+    }
+    Ok((len, 0))
+}
+
+/// A variant of `Store` which prepends a self-describing header with magic bytes, element type, element size, element count and endianness.
+pub trait StoreWithHeader {
+    /// Writes header and data to the file with the given path
+    fn write_with_header_to(&self, path: &dyn AsRef<Path>) -> Result<()>;
+}
+
+impl<T: HeaderElement> StoreWithHeader for [T] {
+    fn write_with_header_to(&self, path: &dyn AsRef<Path>) -> Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(&header::<T>(self.len()))?;
+        file.write_all(self.data_bytes())
+    }
+}
+
+/// A variant of `Load` which validates the header written by `StoreWithHeader`.
+/// Legacy files without header (as written by `Store` or RoutingKit) can still be read.
+/// Instead of panicking, mismatches between the file and the expected type are reported as `io::Error`s.
+pub trait LoadWithHeader: Sized {
+    /// Loads the data from the file with the given path
+    fn load_with_header_from<P: AsRef<Path>>(path: P) -> Result<Self>;
+}
+
+impl<T: HeaderElement> LoadWithHeader for Vec<T> {
+    fn load_with_header_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let (len, _) = read_header::<T>(&mut file)?;
+        let mut data = vec![T::default(); len];
+        file.read_exact(data.data_bytes_mut())?;
+        Ok(data)
+    }
+}
+
+/// A read-only container for data on disk which is memory mapped instead of being read into an allocated buffer.
+/// Loading is zero-copy and several processes mapping the same file share the same pages of the page cache.
+/// The data can be accessed as a slice, so it can be used as a container for `FirstOutGraph`.
+///
+/// Like `Load`, this assumes that the file contains the raw bytes of valid elements of type `T`.
+pub struct MmapSlice<T> {
+    // mapping empty files is not possible, so we don't have a mapping in this case
+    mmap: Option<memmap::Mmap>,
+    // number of bytes before the actual data
+    offset: usize,
+    len: usize,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Copy> MmapSlice<T> {
+    /// Memory map the file at the given path.
+    /// The file should not be modified while it is mapped.
+    pub fn map_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        let num_bytes = file.metadata()?.len() as usize;
+        let len = num_bytes / mem::size_of::<T>();
+        if len * mem::size_of::<T>() != num_bytes {
+            return Err(invalid_data("file size is not a multiple of the element size"));
+        }
+        Self::map(&file, 0, len)
+    }
+
+    fn map(file: &File, offset: usize, len: usize) -> Result<Self> {
+        let mmap = if offset + len > 0 { Some(unsafe { memmap::Mmap::map(file)? }) } else { None };
+        // mappings are page aligned and the header length is a multiple of 8, so this can only fail for absurdly aligned types
+        if let Some(mmap) = &mmap {
+            assert_eq!(mmap[offset..].as_ptr() as usize % mem::align_of::<T>(), 0);
+        }
+
+        Ok(MmapSlice {
+            mmap,
+            offset,
+            len,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<T: HeaderElement> MmapSlice<T> {
+    /// Memory map a file with or without header.
+    /// Like `LoadWithHeader`, this validates the header if present and reports mismatches as `io::Error`s.
+    pub fn map_with_header_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let (len, offset) = read_header::<T>(&mut file)?;
+        Self::map(&file, offset, len)
+    }
+}
+
+impl<T: Copy> Deref for MmapSlice<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match &self.mmap {
+            Some(mmap) => unsafe { slice::from_raw_parts(mmap[self.offset..].as_ptr() as *const T, self.len) },
+            None => &[],
+        }
+    }
+}
+
+impl<T: Copy> AsSlice<T> for MmapSlice<T> {
+    fn as_slice(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T: Copy> DataBytes for MmapSlice<T> {
+    fn data_bytes(&self) -> &[u8] {
+        match &self.mmap {
+            Some(mmap) => &mmap[self.offset..],
+            None => &[],
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for MmapSlice<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("MmapSlice").field("len", &self.len).finish()
+    }
+}
+
+/// Read-only data which is either owned or memory mapped.
+/// Allows structures which are usually built in memory to reference their data directly from disk after loading.
+#[derive(Debug)]
+pub enum VecOrMmap<T> {
+    Owned(Vec<T>),
+    Mapped(MmapSlice<T>),
+}
+
+impl<T: Copy> Deref for VecOrMmap<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            VecOrMmap::Owned(data) => data,
+            VecOrMmap::Mapped(data) => data,
+        }
+    }
+}
+
+impl<T: Copy> AsSlice<T> for VecOrMmap<T> {
+    fn as_slice(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T: Copy> DataBytes for VecOrMmap<T> {
+    fn data_bytes(&self) -> &[u8] {
+        self[..].data_bytes()
+    }
+}
+
+impl<T> From<Vec<T>> for VecOrMmap<T> {
+    fn from(data: Vec<T>) -> Self {
+        VecOrMmap::Owned(data)
+    }
+}
+
+impl<T> From<MmapSlice<T>> for VecOrMmap<T> {
+    fn from(data: MmapSlice<T>) -> Self {
+        VecOrMmap::Mapped(data)
+    }
+}
+
 /// A trait to allow serializing more complex objects
 /// which need more than a single file.
 pub trait Deconstruct: Sized {
@@ -147,6 +421,11 @@ impl<'a> Loader<'a> {
     pub fn load<T: Load, P: AsRef<Path>>(&self, path: P) -> Result<T> {
         T::load_from(self.path.join(path))
     }
+
+    /// Like `load` but memory maps the file instead of reading it.
+    pub fn map<T: Copy, P: AsRef<Path>>(&self, path: P) -> Result<MmapSlice<T>> {
+        MmapSlice::map_from(self.path.join(path))
+    }
 }
 
 /// A trait to allow deserializing more complex objects of a different type `T` (similar to `Reconstruct`).
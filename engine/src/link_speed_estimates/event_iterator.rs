@@ -1,24 +1,27 @@
 use super::*;
+use std::collections::VecDeque;
+
+// Maximum number of links read ahead while looking for the link of a trace.
+// Traces on links further ahead are reported as unmatched, unless an earlier trace already filled the lookahead.
+const MAX_LOOKAHEAD: usize = 1000;
 
 #[derive(Debug)]
 pub enum Event<'a> {
     Link(&'a LinkData),
     Trace(&'a TraceData),
-}
-
-#[derive(Debug)]
-enum IteratorToPoll {
-    Links,
-    Traces,
+    // trace whose link is none of the remaining links within the lookahead
+    UnmatchedTrace(&'a TraceData),
 }
 
 pub struct EventIterator<'a> {
     links: Box<dyn Iterator<Item = &'a LinkData> + 'a>,
     traces: Box<dyn Iterator<Item = &'a TraceData> + 'a>,
-    iterator_to_poll: IteratorToPoll,
-    next_link: Option<&'a LinkData>,
-    next_trace: &'a TraceData,
-    done: bool,
+    // links which were read while looking for the link of a trace but not yet emitted
+    lookahead: VecDeque<&'a LinkData>,
+    // links up to the one of the next trace, followed by that trace
+    pending: VecDeque<Event<'a>>,
+    current_link_id: Option<u64>,
+    next_trace: Option<&'a TraceData>,
 }
 
 impl<'a> EventIterator<'a> {
@@ -32,57 +35,77 @@ impl<'a> EventIterator<'a> {
         Ok(EventIterator {
             links,
             traces,
-            iterator_to_poll: IteratorToPoll::Links,
-            next_link: Some(next_link),
-            next_trace,
-            done: false,
+            lookahead: std::iter::once(next_link).collect(),
+            pending: VecDeque::new(),
+            current_link_id: None,
+            next_trace: Some(next_trace),
         })
     }
-}
 
-impl<'a> Iterator for EventIterator<'a> {
-    type Item = Event<'a>;
+    // Queue the events up to the next trace.
+    // Links after the last trace are never emitted.
+    fn advance(&mut self) {
+        let trace = match self.next_trace {
+            Some(trace) => trace,
+            None => return,
+        };
+        self.next_trace = self.traces.next();
 
-    fn next(&mut self) -> Option<Event<'a>> {
-        if self.done {
-            return None;
+        if self.current_link_id == Some(trace.link_id) {
+            self.pending.push_back(Event::Trace(trace));
+            return;
         }
 
-        match self.iterator_to_poll {
-            IteratorToPoll::Links => {
-                let result = Some(Event::Link(self.next_link.unwrap()));
-
-                if self.next_link.unwrap().link_id == self.next_trace.link_id {
-                    self.iterator_to_poll = IteratorToPoll::Traces;
-                } else {
-                    self.iterator_to_poll = IteratorToPoll::Links;
-                }
-
-                self.next_link = self.links.next();
-
-                result
+        // the lookahead was filled by an earlier unmatched trace
+        let was_full = self.lookahead.len() >= MAX_LOOKAHEAD;
+        let mut found = self.search(trace.link_id);
+        if found.is_none() && was_full {
+            // Neither the earlier trace nor this one is on the buffered links, so they are given up on
+            // and the search continues after them. The estimator already started over after the unmatched trace.
+            self.lookahead.clear();
+            found = self.search(trace.link_id);
+        }
+        let searched = match found {
+            Some(searched) => searched,
+            None => {
+                // keep the links for the following traces
+                self.pending.push_back(Event::UnmatchedTrace(trace));
+                return;
             }
-            IteratorToPoll::Traces => {
-                let result = Some(Event::Trace(self.next_trace));
+        };
 
-                match self.traces.next() {
-                    Some(trace) => {
-                        if self.next_trace.link_id == trace.link_id {
-                            self.iterator_to_poll = IteratorToPoll::Traces;
-                        } else {
-                            self.iterator_to_poll = IteratorToPoll::Links;
-                        }
+        for link in self.lookahead.drain(..=searched) {
+            self.pending.push_back(Event::Link(link));
+        }
+        self.current_link_id = Some(trace.link_id);
+        self.pending.push_back(Event::Trace(trace));
+    }
 
-                        self.next_trace = trace;
-                    }
-                    None => {
-                        debug_assert_eq!(self.links.next(), None);
-                        self.done = true;
-                    }
+    // Position of the link in the lookahead, reading further links until it is full.
+    fn search(&mut self, link_id: u64) -> Option<usize> {
+        let mut searched = 0;
+        loop {
+            if searched == self.lookahead.len() {
+                if searched >= MAX_LOOKAHEAD {
+                    return None;
                 }
-
-                result
+                self.lookahead.push_back(self.links.next()?);
             }
+            if self.lookahead[searched].link_id == link_id {
+                return Some(searched);
+            }
+            searched += 1;
+        }
+    }
+}
+
+impl<'a> Iterator for EventIterator<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        if self.pending.is_empty() {
+            self.advance();
         }
+        self.pending.pop_front()
     }
 }
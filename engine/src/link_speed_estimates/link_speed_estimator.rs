@@ -1,27 +1,49 @@
 use super::*;
 use std::fmt::Debug;
 use std::iter::{empty, once};
-use std::mem::replace;
 
+type Output<'a> = Box<dyn Iterator<Item = LinkSpeedData> + 'a>;
+type Transition<'a> = Result<(Box<dyn State<'a> + 'a>, Output<'a>), EstimateError>;
+
+// Each transition either moves on to the next state or reports inconsistent input.
+// After an error, the estimator discards the state and starts over with `Init`.
 trait State<'a>: Debug {
-    fn on_link(self: Box<Self>, link: &'a LinkData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData> + 'a>);
-    fn on_trace(self: Box<Self>, trace: &'a TraceData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData> + 'a>);
-    fn on_done(self: Box<Self>) -> Box<dyn Iterator<Item = LinkSpeedData> + 'a>;
+    fn on_link(self: Box<Self>, link: &'a LinkData) -> Transition<'a>;
+    fn on_trace(self: Box<Self>, trace: &'a TraceData) -> Transition<'a>;
+    fn on_done(self: Box<Self>) -> Result<Output<'a>, EstimateError>;
+}
+
+// A trace continuing from `previous` has to be strictly later.
+// The `EventIterator` only emits traces directly after their link, traces on other links are reported as `UnknownLink`.
+fn check_trace(link: &LinkData, previous: &TraceData, trace: &TraceData) -> Result<(), EstimateError> {
+    debug_assert_eq!(trace.link_id, link.link_id);
+    if trace.timestamp <= previous.timestamp {
+        return Err(EstimateError::new(EstimateErrorKind::NonIncreasingTimestamp, trace));
+    }
+    Ok(())
+}
+
+fn check_not_repeated(previous: &LinkData, link: &LinkData, last_trace: &TraceData) -> Result<(), EstimateError> {
+    if previous.link_id == link.link_id {
+        return Err(EstimateError::new(EstimateErrorKind::RepeatedLink, last_trace));
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
 struct Init {}
 impl<'a> State<'a> for Init {
-    fn on_link(self: Box<Self>, link: &'a LinkData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
-        (Box::new(InitialLink { link }), Box::new(empty()))
+    fn on_link(self: Box<Self>, link: &'a LinkData) -> Transition<'a> {
+        Ok((Box::new(InitialLink { link }), Box::new(empty())))
     }
 
-    fn on_trace(self: Box<Self>, _trace: &'a TraceData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
-        panic!("Init: trace before initial link");
+    // remaining traces of a skipped segment
+    fn on_trace(self: Box<Self>, _trace: &'a TraceData) -> Transition<'a> {
+        Ok((self, Box::new(empty())))
     }
 
-    fn on_done(self: Box<Self>) -> Box<dyn Iterator<Item = LinkSpeedData>> {
-        panic!("Init: stream ended, expected link");
+    fn on_done(self: Box<Self>) -> Result<Output<'a>, EstimateError> {
+        Ok(Box::new(empty()))
     }
 }
 
@@ -30,17 +52,18 @@ struct InitialLink<'a> {
     link: &'a LinkData,
 }
 impl<'a> State<'a> for InitialLink<'a> {
-    fn on_link(self: Box<Self>, _link: &'a LinkData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
-        panic!("InitialLink: no trace on initial link");
+    // links without traces before the first trace can not be estimated
+    fn on_link(self: Box<Self>, link: &'a LinkData) -> Transition<'a> {
+        Ok((Box::new(InitialLink { link }), Box::new(empty())))
     }
 
-    fn on_trace(self: Box<Self>, trace: &'a TraceData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
-        assert_eq!(self.link.link_id, trace.link_id);
-        (Box::new(InitialLinkWithTrace { link: self.link, trace }), Box::new(empty()))
+    fn on_trace(self: Box<Self>, trace: &'a TraceData) -> Transition<'a> {
+        debug_assert_eq!(self.link.link_id, trace.link_id);
+        Ok((Box::new(InitialLinkWithTrace { link: self.link, trace }), Box::new(empty())))
     }
 
-    fn on_done(self: Box<Self>) -> Box<dyn Iterator<Item = LinkSpeedData>> {
-        panic!("InitialLink: stream ended, expected trace");
+    fn on_done(self: Box<Self>) -> Result<Output<'a>, EstimateError> {
+        Ok(Box::new(empty()))
     }
 }
 
@@ -50,32 +73,38 @@ struct InitialLinkWithTrace<'a> {
     trace: &'a TraceData,
 }
 impl<'a> State<'a> for InitialLinkWithTrace<'a> {
-    fn on_link(self: Box<Self>, link: &'a LinkData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
-        assert_ne!(self.link.link_id, link.link_id);
-        let mut intermediates = Vec::new();
-        intermediates.push(link);
-        (
+    fn on_link(self: Box<Self>, link: &'a LinkData) -> Transition<'a> {
+        check_not_repeated(self.link, link, self.trace)?;
+        let intermediates = vec![link];
+        Ok((
             Box::new(IntermediateLinkAfterInitial {
                 initial_link: self.link,
                 trace: self.trace,
                 intermediates,
             }),
             Box::new(empty()),
-        )
+        ))
     }
 
-    fn on_trace(self: Box<Self>, trace: &'a TraceData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
-        assert_eq!(trace.link_id, self.trace.link_id);
-        assert_eq!(trace.link_id, self.link.link_id);
+    fn on_trace(mut self: Box<Self>, trace: &'a TraceData) -> Transition<'a> {
+        check_trace(self.link, self.trace, trace)?;
 
         let delta_t = trace.timestamp - self.trace.timestamp;
         let delta_fraction = f64::from(trace.traversed_in_travel_direction_fraction) - f64::from(self.trace.traversed_in_travel_direction_fraction);
+        if delta_fraction < 0.0 {
+            return Err(EstimateError::new(EstimateErrorKind::DecreasingFraction, trace));
+        }
+        if delta_fraction == 0.0 {
+            // standing still, the entry can only be derived from the movement afterwards
+            self.trace = trace;
+            return Ok((self, Box::new(empty())));
+        }
         let t_pre = (delta_t as f64 * f64::from(self.trace.traversed_in_travel_direction_fraction) / delta_fraction) as u64;
 
         debug_assert!(self.trace.timestamp > t_pre, "timestamp underflow");
         let entry_timestamp = self.trace.timestamp - t_pre;
 
-        (
+        Ok((
             Box::new(LinkWithEntryTimestampAndTrace {
                 link: self.link,
                 last_trace: trace,
@@ -83,11 +112,11 @@ impl<'a> State<'a> for InitialLinkWithTrace<'a> {
                 quality: delta_fraction,
             }),
             Box::new(empty()),
-        )
+        ))
     }
 
-    fn on_done(self: Box<Self>) -> Box<dyn Iterator<Item = LinkSpeedData>> {
-        panic!("InitialLinkWithTrace: stream ended, expected link or trace");
+    fn on_done(self: Box<Self>) -> Result<Output<'a>, EstimateError> {
+        Err(EstimateError::new(EstimateErrorKind::SingleTrace, self.trace))
     }
 }
 
@@ -98,14 +127,15 @@ struct IntermediateLinkAfterInitial<'a> {
     intermediates: Vec<&'a LinkData>,
 }
 impl<'a> State<'a> for IntermediateLinkAfterInitial<'a> {
-    fn on_link(mut self: Box<Self>, link: &'a LinkData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
+    fn on_link(mut self: Box<Self>, link: &'a LinkData) -> Transition<'a> {
+        check_not_repeated(self.intermediates.last().unwrap(), link, self.trace)?;
         self.intermediates.push(link);
-        (self, Box::new(empty()))
+        Ok((self, Box::new(empty())))
     }
 
-    fn on_trace(mut self: Box<Self>, trace: &'a TraceData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData> + 'a>) {
+    fn on_trace(mut self: Box<Self>, trace: &'a TraceData) -> Transition<'a> {
         let link = self.intermediates.pop().unwrap();
-        assert_eq!(link.link_id, trace.link_id);
+        check_trace(link, self.trace, trace)?;
 
         let initial_timestamp = self.trace.timestamp;
         let delta_t = trace.timestamp - initial_timestamp;
@@ -156,7 +186,7 @@ impl<'a> State<'a> for IntermediateLinkAfterInitial<'a> {
         let entry_timestamp = trace.timestamp - (f64::from(length_before_current_trace) * 3.6 / (f64::from(link.speed_limit) * velocity_factor)) as u64;
         let quality = f64::from(length_before_current_trace) / f64::from(total_length) * f64::from(trace.traversed_in_travel_direction_fraction);
 
-        (
+        Ok((
             Box::new(LinkWithEntryTimestampAndTrace {
                 link,
                 last_trace: trace,
@@ -164,11 +194,11 @@ impl<'a> State<'a> for IntermediateLinkAfterInitial<'a> {
                 quality,
             }),
             Box::new(output),
-        )
+        ))
     }
 
-    fn on_done(self: Box<Self>) -> Box<dyn Iterator<Item = LinkSpeedData>> {
-        panic!("IntermediateLinkAfterInitial: stream ended, expected link or trace");
+    fn on_done(self: Box<Self>) -> Result<Output<'a>, EstimateError> {
+        Err(EstimateError::new(EstimateErrorKind::SingleTrace, self.trace))
     }
 }
 
@@ -180,35 +210,38 @@ struct LinkWithEntryTimestampAndTrace<'a> {
     quality: f64,
 }
 impl<'a> State<'a> for LinkWithEntryTimestampAndTrace<'a> {
-    fn on_link(self: Box<Self>, link: &'a LinkData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
-        let mut intermediates = Vec::new();
-        intermediates.push(link);
-        (
+    fn on_link(self: Box<Self>, link: &'a LinkData) -> Transition<'a> {
+        check_not_repeated(self.link, link, self.last_trace)?;
+        let intermediates = vec![link];
+        Ok((
             Box::new(IntermediateLink {
                 last_link_with_trace: self,
                 intermediates,
             }),
             Box::new(empty()),
-        )
+        ))
     }
 
-    fn on_trace(mut self: Box<Self>, trace: &'a TraceData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
-        assert_eq!(self.link.link_id, trace.link_id);
+    fn on_trace(mut self: Box<Self>, trace: &'a TraceData) -> Transition<'a> {
+        check_trace(self.link, self.last_trace, trace)?;
+        if trace.traversed_in_travel_direction_fraction < self.last_trace.traversed_in_travel_direction_fraction {
+            return Err(EstimateError::new(EstimateErrorKind::DecreasingFraction, trace));
+        }
         self.quality += f64::from(trace.traversed_in_travel_direction_fraction - self.last_trace.traversed_in_travel_direction_fraction);
         self.last_trace = trace;
-        (self.clone(), Box::new(empty()))
+        Ok((self, Box::new(empty())))
     }
 
-    fn on_done(self: Box<Self>) -> Box<dyn Iterator<Item = LinkSpeedData>> {
+    fn on_done(self: Box<Self>) -> Result<Output<'a>, EstimateError> {
         let delta_t = self.last_trace.timestamp - self.entry_timestamp;
         let delta_s = f64::from(self.last_trace.traversed_in_travel_direction_fraction) * f64::from(self.link.length);
         let velocity = delta_s / delta_t as f64;
-        Box::new(once(LinkSpeedData {
+        Ok(Box::new(once(LinkSpeedData {
             link_id: self.link.link_id,
             link_entered_timestamp: self.entry_timestamp,
             estimate_quality: self.quality as f32,
             velocity: (velocity * 3.6) as f32,
-        }))
+        })))
     }
 }
 
@@ -218,14 +251,15 @@ struct IntermediateLink<'a> {
     intermediates: Vec<&'a LinkData>,
 }
 impl<'a> State<'a> for IntermediateLink<'a> {
-    fn on_link(mut self: Box<Self>, link: &'a LinkData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData>>) {
+    fn on_link(mut self: Box<Self>, link: &'a LinkData) -> Transition<'a> {
+        check_not_repeated(self.intermediates.last().unwrap(), link, self.last_link_with_trace.last_trace)?;
         self.intermediates.push(link);
-        (self, Box::new(empty()))
+        Ok((self, Box::new(empty())))
     }
 
-    fn on_trace(mut self: Box<Self>, trace: &'a TraceData) -> (Box<dyn State + 'a>, Box<dyn Iterator<Item = LinkSpeedData> + 'a>) {
+    fn on_trace(mut self: Box<Self>, trace: &'a TraceData) -> Transition<'a> {
         let link = self.intermediates.pop().unwrap();
-        assert_eq!(link.link_id, trace.link_id);
+        check_trace(link, self.last_link_with_trace.last_trace, trace)?;
 
         let previous_timestamp = self.last_link_with_trace.last_trace.timestamp;
         let delta_t = trace.timestamp - previous_timestamp;
@@ -276,7 +310,7 @@ impl<'a> State<'a> for IntermediateLink<'a> {
         let entry_timestamp = trace.timestamp - (f64::from(length_before_current_trace) * 3.6 / (f64::from(link.speed_limit) * velocity_factor)) as u64;
         let quality = f64::from(length_before_current_trace) / f64::from(total_length) * f64::from(trace.traversed_in_travel_direction_fraction);
 
-        (
+        Ok((
             Box::new(LinkWithEntryTimestampAndTrace {
                 link,
                 last_trace: trace,
@@ -284,54 +318,78 @@ impl<'a> State<'a> for IntermediateLink<'a> {
                 quality,
             }),
             Box::new(output),
-        )
+        ))
     }
 
-    fn on_done(self: Box<Self>) -> Box<dyn Iterator<Item = LinkSpeedData>> {
-        panic!("IntermediateLink: stream ended, expected link or trace");
+    // links after the last trace can not be estimated
+    fn on_done(self: Box<Self>) -> Result<Output<'a>, EstimateError> {
+        self.last_link_with_trace.on_done()
     }
 }
 
+/// Link speed estimates from traces, see `try_estimate_iter`.
 pub struct LinkSpeedEstimator<'a> {
     state: Option<Box<dyn State<'a> + 'a>>,
     event_iterator: EventIterator<'a>,
-    output_iterator: Box<dyn Iterator<Item = LinkSpeedData> + 'a>,
+    output_iterator: Output<'a>,
+    skipped_segments: usize,
 }
 
 impl<'a> LinkSpeedEstimator<'a> {
-    pub fn new(events: EventIterator<'a>) -> LinkSpeedEstimator<'a> {
+    pub(super) fn new(events: EventIterator<'a>) -> LinkSpeedEstimator<'a> {
         LinkSpeedEstimator {
             state: Some(Box::new(Init {})),
             event_iterator: events,
             output_iterator: Box::new(empty()),
+            skipped_segments: 0,
         }
     }
+
+    /// Number of segments discarded because of errors so far.
+    pub fn skipped_segments(&self) -> usize {
+        self.skipped_segments
+    }
+
+    fn skip_segment(&mut self, error: EstimateError) -> Option<Result<LinkSpeedData, EstimateError>> {
+        self.skipped_segments += 1;
+        Some(Err(error))
+    }
 }
 
 impl<'a> Iterator for LinkSpeedEstimator<'a> {
-    type Item = LinkSpeedData;
+    type Item = Result<LinkSpeedData, EstimateError>;
 
-    fn next(&mut self) -> Option<LinkSpeedData> {
+    fn next(&mut self) -> Option<Self::Item> {
         loop {
             match self.output_iterator.next() {
-                Some(out) => return Some(out),
+                Some(out) => return Some(Ok(out)),
                 None => {
-                    let current_state = replace(&mut self.state, None);
+                    let current_state = self.state.take();
 
                     match self.event_iterator.next() {
                         Some(event) => {
-                            let (state, iter) = match event {
-                                Event::Link(link) => current_state.unwrap().on_link(link),
-                                Event::Trace(trace) => current_state.unwrap().on_trace(trace),
+                            let current_state = current_state.unwrap();
+                            let transition = match event {
+                                Event::Link(link) => current_state.on_link(link),
+                                Event::Trace(trace) => current_state.on_trace(trace),
+                                Event::UnmatchedTrace(trace) => Err(EstimateError::new(EstimateErrorKind::UnknownLink, trace)),
                             };
-                            self.state = Some(state);
-                            self.output_iterator = iter;
+                            match transition {
+                                Ok((state, iter)) => {
+                                    self.state = Some(state);
+                                    self.output_iterator = iter;
+                                }
+                                Err(error) => {
+                                    self.state = Some(Box::new(Init {}));
+                                    return self.skip_segment(error);
+                                }
+                            }
                         }
                         None => match current_state {
-                            Some(state) => {
-                                self.output_iterator = state.on_done();
-                                self.state = None;
-                            }
+                            Some(state) => match state.on_done() {
+                                Ok(iter) => self.output_iterator = iter,
+                                Err(error) => return self.skip_segment(error),
+                            },
                             None => return None,
                         },
                     }
@@ -0,0 +1,216 @@
+//! Map matching of raw GPS traces with a hidden Markov model.
+//!
+//! Follows Newson and Krumm, "Hidden Markov Map Matching Through Noise and Sparseness" (2009).
+//! The candidates for each GPS point are the closest positions on the arcs within a search radius (see `SnapIndex::positions`).
+//! The emission probability decays with the distance between GPS point and candidate (gaussian),
+//! the transition probability with the difference between the route length and the great circle distance of two consecutive GPS points (exponential).
+//! The most likely candidate sequence is determined with the Viterbi algorithm.
+//! Route lengths can be obtained with any point to point query, e.g. a CCH or bidirectional Dijkstra on a metric of arc lengths in meters.
+//! When no candidate of a GPS point can be reached from the previous ones, the trace is split and matching starts over.
+
+use super::*;
+use crate::algo::{snapping::*, Query};
+use crate::datastr::graph::*;
+use nav_types::WGS84;
+
+/// A raw GPS measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsPoint {
+    pub timestamp: u64, // [ms]
+    pub latitude: f32,
+    pub longitude: f32,
+}
+
+/// Parameters of the hidden Markov model.
+#[derive(Debug, Clone, Copy)]
+pub struct MapMatchingParams {
+    /// Standard deviation of the GPS noise in meters
+    pub sigma: f64,
+    /// Mean difference between route length and great circle distance of consecutive points in meters
+    pub beta: f64,
+    /// Only arcs within this distance of a GPS point are candidates (in meters)
+    pub search_radius: f32,
+    /// Maximum number of candidates per GPS point
+    pub max_candidates: usize,
+}
+
+impl Default for MapMatchingParams {
+    fn default() -> Self {
+        MapMatchingParams {
+            sigma: 4.07,
+            beta: 5.0,
+            search_radius: 50.0,
+            max_candidates: 8,
+        }
+    }
+}
+
+/// A continuous part of a trace matched to the graph.
+#[derive(Debug)]
+pub struct MatchedTrace {
+    /// Traversed arcs, from the arc of the first to the arc of the last matched GPS point
+    pub path: Vec<EdgeId>,
+    /// The matched GPS points with arc ids as link ids, as expected by `estimate_iter`
+    pub traces: Vec<TraceData>,
+}
+
+impl MatchedTrace {
+    /// Link data for all arcs of the path, as expected by `estimate_iter`.
+    /// `link` should return the length and speed limit of an arc, its id has to be used as link id.
+    pub fn links(&self, mut link: impl FnMut(EdgeId) -> LinkData) -> Vec<LinkData> {
+        self.path.iter().map(|&arc| link(arc)).collect()
+    }
+}
+
+#[derive(Debug)]
+struct Candidate {
+    position: SnappedPosition,
+    // log probability of the most likely candidate sequence ending here
+    score: f64,
+    predecessor: usize,
+    // nodes of the route from the predecessor, see `SnappedRoute::path`
+    path: Vec<NodeId>,
+}
+
+#[derive(Debug)]
+pub struct MapMatcher<'a, G> {
+    graph: &'a G,
+    lengths: Vec<Weight>,
+    snap_index: &'a SnapIndex,
+    params: MapMatchingParams,
+}
+
+impl<'a, G: RandomLinkAccessGraph> MapMatcher<'a, G> {
+    /// `graph` has to contain the arc lengths in meters as weights and `snap_index` has to be built for the same graph.
+    pub fn new(graph: &'a G, snap_index: &'a SnapIndex, params: MapMatchingParams) -> Self {
+        let lengths = (0..graph.num_arcs() as EdgeId).map(|arc| graph.link(arc).weight).collect();
+        MapMatcher {
+            graph,
+            lengths,
+            snap_index,
+            params,
+        }
+    }
+
+    /// Match `points` (ordered by timestamp) to the graph.
+    /// `query` should return the length and path of the shortest route between two nodes, e.g. with `QueryServer::query`.
+    /// GPS points without any arc within the search radius are skipped.
+    pub fn match_trace(&self, points: &[GpsPoint], mut query: impl FnMut(Query) -> Option<(Weight, Vec<NodeId>)>) -> Vec<MatchedTrace> {
+        let snap_params = SnapParams {
+            max_distance: self.params.search_radius,
+            ..SnapParams::default()
+        };
+
+        let mut matched = Vec::new();
+        // index of the GPS point and its candidates for all points of the current part
+        let mut steps: Vec<(usize, Vec<Candidate>)> = Vec::new();
+
+        for (point_idx, point) in points.iter().enumerate() {
+            let positions: Vec<SnappedPosition> = self
+                .snap_index
+                .positions(point.latitude, point.longitude, &snap_params)
+                .take(self.params.max_candidates)
+                .collect();
+            if positions.is_empty() {
+                continue;
+            }
+
+            let mut candidates = match steps.last() {
+                Some((prev_point_idx, prev_candidates)) => {
+                    let great_circle_distance = distance_in_m(&points[*prev_point_idx], point);
+                    positions
+                        .iter()
+                        .filter_map(|&position| {
+                            let mut best: Option<Candidate> = None;
+                            for (prev_idx, prev) in prev_candidates.iter().enumerate() {
+                                if let Some(route) = snapped_route(&[prev.position], &[position], &self.lengths, &mut query) {
+                                    let transition = -(f64::from(route.distance) - great_circle_distance).abs() / self.params.beta;
+                                    let score = prev.score + transition + self.emission(&position);
+                                    if best.as_ref().map(|best| score > best.score).unwrap_or(true) {
+                                        best = Some(Candidate {
+                                            position,
+                                            score,
+                                            predecessor: prev_idx,
+                                            path: route.path,
+                                        });
+                                    }
+                                }
+                            }
+                            best
+                        })
+                        .collect()
+                }
+                None => Vec::new(),
+            };
+
+            if candidates.is_empty() {
+                // first point or none of the candidates is reachable, start a new part
+                if !steps.is_empty() {
+                    matched.push(self.backtrack(points, &steps));
+                    steps.clear();
+                }
+                candidates = positions
+                    .iter()
+                    .map(|&position| Candidate {
+                        position,
+                        score: self.emission(&position),
+                        predecessor: 0,
+                        path: Vec::new(),
+                    })
+                    .collect();
+            }
+
+            steps.push((point_idx, candidates));
+        }
+
+        if !steps.is_empty() {
+            matched.push(self.backtrack(points, &steps));
+        }
+
+        matched
+    }
+
+    fn emission(&self, position: &SnappedPosition) -> f64 {
+        -0.5 * (f64::from(position.distance) / self.params.sigma).powi(2)
+    }
+
+    // Follow the predecessors of the most likely final candidate and collect the traversed arcs.
+    fn backtrack(&self, points: &[GpsPoint], steps: &[(usize, Vec<Candidate>)]) -> MatchedTrace {
+        let (_, last_candidates) = steps.last().unwrap();
+        let mut candidate_idx = (0..last_candidates.len())
+            .max_by(|&a, &b| last_candidates[a].score.total_cmp(&last_candidates[b].score))
+            .unwrap();
+        let mut sequence = Vec::with_capacity(steps.len());
+        for (point_idx, candidates) in steps.iter().rev() {
+            let candidate = &candidates[candidate_idx];
+            sequence.push((*point_idx, candidate));
+            candidate_idx = candidate.predecessor;
+        }
+        sequence.reverse();
+
+        let mut path = Vec::new();
+        let mut traces = Vec::with_capacity(sequence.len());
+        for (point_idx, candidate) in sequence {
+            if path.last() != Some(&candidate.position.edge) || !candidate.path.is_empty() {
+                path.extend(
+                    candidate
+                        .path
+                        .windows(2)
+                        .map(|nodes| self.graph.edge_index(nodes[0], nodes[1]).expect("route along non existing arc")),
+                );
+                path.push(candidate.position.edge);
+            }
+            traces.push(TraceData {
+                timestamp: points[point_idx].timestamp,
+                link_id: u64::from(candidate.position.edge),
+                traversed_in_travel_direction_fraction: candidate.position.fraction,
+            });
+        }
+
+        MatchedTrace { path, traces }
+    }
+}
+
+fn distance_in_m(from: &GpsPoint, to: &GpsPoint) -> f64 {
+    WGS84::new(f64::from(from.latitude), f64::from(from.longitude), 0.0).distance(&WGS84::new(f64::from(to.latitude), f64::from(to.longitude), 0.0))
+}
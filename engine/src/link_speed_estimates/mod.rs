@@ -2,11 +2,15 @@
 
 mod event_iterator;
 mod link_speed_estimator;
+pub mod map_matching;
+pub mod profiles;
 
 use self::event_iterator::{Event, EventIterator};
-use self::link_speed_estimator::LinkSpeedEstimator;
+pub use self::link_speed_estimator::LinkSpeedEstimator;
 
-#[derive(Debug)]
+use std::{error::Error, fmt};
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TraceData {
     pub timestamp: u64, // [ms]
     pub link_id: u64,
@@ -34,10 +38,66 @@ pub struct LinkSpeedData {
     pub velocity: f32,
 }
 
+/// Kinds of inconsistent input, see `EstimateError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EstimateErrorKind {
+    /// The link of the trace is none of the remaining links or too far ahead of the previous trace
+    UnknownLink,
+    /// The same link follows itself
+    RepeatedLink,
+    /// The trace is not later than the previous one
+    NonIncreasingTimestamp,
+    /// The trace lies before the previous one on the same link
+    DecreasingFraction,
+    /// The input ended after a single trace, so no speed can be derived
+    SingleTrace,
+}
+
+/// Inconsistent input detected by `LinkSpeedEstimator`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EstimateError {
+    pub kind: EstimateErrorKind,
+    /// The offending trace, for `RepeatedLink` the last trace before the repeated link
+    pub trace: TraceData,
+}
+
+impl EstimateError {
+    fn new(kind: EstimateErrorKind, trace: &TraceData) -> Self {
+        EstimateError { kind, trace: trace.clone() }
+    }
+}
+
+impl fmt::Display for EstimateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} at trace {:?}", self.kind, self.trace)
+    }
+}
+
+impl Error for EstimateError {}
+
+/// Estimate link speeds, yielding an error for each inconsistency in the input.
+/// After an error, the estimates of the current segment are discarded and estimation continues with the next link with traces.
+/// See `LinkSpeedEstimator::skipped_segments`.
+pub fn try_estimate_iter<'a>(
+    links: Box<dyn Iterator<Item = &'a LinkData> + 'a>,
+    traces: Box<dyn Iterator<Item = &'a TraceData> + 'a>,
+) -> Result<LinkSpeedEstimator<'a>, &'static str> {
+    Ok(LinkSpeedEstimator::new(EventIterator::new(links, traces)?))
+}
+
+/// Estimate link speeds, inconsistent parts of the input are skipped (see `try_estimate_iter`) and logged to stderr.
+/// This used to panic on inconsistent input.
+/// Use `try_estimate_iter` to handle the errors or count them with `LinkSpeedEstimator::skipped_segments`.
 pub fn estimate_iter<'a>(
     links: Box<dyn Iterator<Item = &'a LinkData> + 'a>,
     traces: Box<dyn Iterator<Item = &'a TraceData> + 'a>,
 ) -> Result<impl Iterator<Item = LinkSpeedData> + 'a, &'static str> {
-    let output_iter = LinkSpeedEstimator::new(EventIterator::new(links, traces)?);
+    let output_iter = try_estimate_iter(links, traces)?.filter_map(|result| match result {
+        Ok(estimate) => Some(estimate),
+        Err(error) => {
+            eprintln!("skipping segment: {}", error);
+            None
+        }
+    });
     Ok(output_iter)
 }
@@ -0,0 +1,220 @@
+//! Aggregation of link speed estimates into periodic travel time profiles.
+//!
+//! Each estimate is converted into a travel time of its arc and assigned to a time of day bucket by the time the arc was entered.
+//! Per arc and bucket, the travel times are averaged, weighted by the estimate quality.
+//! Buckets are smoothed with their neighbors, buckets without enough data are interpolated between the surrounding ones
+//! and arcs without any data keep their free flow travel time.
+//! Travel times never drop below the free flow travel time and the profiles are made FIFO by lowering travel times
+//! which would allow arriving earlier by departing later.
+//! The result can be stored as `first_ipp_of_arc`, `ipp_departure_time` and `ipp_travel_time` for the time-dependent algorithms.
+
+use super::*;
+use crate::datastr::graph::{time_dependent::*, *};
+use crate::io::*;
+use std::{
+    cmp::{max, min},
+    collections::HashMap,
+    error::Error,
+    fmt,
+};
+
+/// Parameters for `ProfileBuilder`.
+#[derive(Debug, Clone, Copy)]
+pub struct ProfileParams {
+    /// Length of the time of day buckets in ms, has to divide the period
+    pub bucket_size: Timestamp,
+    /// Buckets with less total estimate quality (after smoothing) are interpolated
+    pub min_quality: f32,
+    /// Number of buckets on each side which are averaged into a bucket
+    pub smoothing_radius: usize,
+}
+
+impl Default for ProfileParams {
+    fn default() -> Self {
+        ProfileParams {
+            bucket_size: 15 * 60 * 1000,
+            min_quality: 1.0,
+            smoothing_radius: 1,
+        }
+    }
+}
+
+/// Estimate for a link id which is not an arc of the graph, see `ProfileBuilder::add`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownArc(pub u64);
+
+impl fmt::Display for UnknownArc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "estimate for unknown arc {}", self.0)
+    }
+}
+
+impl Error for UnknownArc {}
+
+/// Collects link speed estimates and turns them into travel time profiles.
+/// The link ids of the estimates have to be the arc ids, as produced by `map_matching`.
+#[derive(Debug)]
+pub struct ProfileBuilder {
+    params: ProfileParams,
+    length: Vec<u32>,
+    free_flow_travel_time: Vec<Weight>,
+    num_buckets: usize,
+    // quality weighted travel time sum and quality sum of each bucket, only for arcs with estimates
+    sums: HashMap<usize, Vec<(f64, f64)>>,
+}
+
+impl ProfileBuilder {
+    /// `length` of the arcs in mm and `free_flow_travel_time` in ms, as for `LinkData`.
+    pub fn new(length: Vec<u32>, free_flow_travel_time: Vec<Weight>, params: ProfileParams) -> Self {
+        assert_eq!(length.len(), free_flow_travel_time.len());
+        assert!(params.bucket_size > 0);
+        let num_buckets = period() / params.bucket_size;
+        assert_eq!(num_buckets * params.bucket_size, period(), "bucket size has to divide the period");
+
+        ProfileBuilder {
+            params,
+            length,
+            free_flow_travel_time,
+            num_buckets: num_buckets as usize,
+            sums: HashMap::new(),
+        }
+    }
+
+    /// Add a single estimate.
+    /// Estimates without quality or velocity are ignored, estimates for link ids beyond the number of arcs are rejected.
+    pub fn add(&mut self, estimate: &LinkSpeedData) -> Result<(), UnknownArc> {
+        if estimate.link_id >= self.length.len() as u64 {
+            return Err(UnknownArc(estimate.link_id));
+        }
+        let usable = estimate.estimate_quality > 0.0 && estimate.velocity > 0.0;
+        if !usable {
+            return Ok(());
+        }
+        let arc = estimate.link_id as usize;
+        let travel_time = (f64::from(self.length[arc]) * 3.6 / f64::from(estimate.velocity)).min(f64::from(INFINITY - 1));
+        let time_of_day = estimate.link_entered_timestamp % u64::from(period());
+        let bucket = (time_of_day / u64::from(self.params.bucket_size)) as usize;
+
+        let num_buckets = self.num_buckets;
+        let (travel_time_sum, quality_sum) = &mut self.sums.entry(arc).or_insert_with(|| vec![(0.0, 0.0); num_buckets])[bucket];
+        *travel_time_sum += f64::from(estimate.estimate_quality) * travel_time;
+        *quality_sum += f64::from(estimate.estimate_quality);
+        Ok(())
+    }
+
+    /// Build the profiles of all arcs from the estimates added so far.
+    pub fn build(&self) -> Profiles {
+        let mut first_ipp_of_arc = Vec::with_capacity(self.length.len() + 1);
+        first_ipp_of_arc.push(0);
+        let mut ipp_departure_time = Vec::new();
+        let mut ipp_travel_time = Vec::new();
+
+        for arc in 0..self.length.len() {
+            for (departure, travel_time) in self.profile(arc) {
+                ipp_departure_time.push(departure);
+                ipp_travel_time.push(travel_time);
+            }
+            first_ipp_of_arc.push(ipp_departure_time.len() as u32);
+        }
+
+        Profiles {
+            first_ipp_of_arc,
+            ipp_departure_time,
+            ipp_travel_time,
+        }
+    }
+
+    // Interpolation points of one arc, starting at departure time 0.
+    fn profile(&self, arc: usize) -> Vec<(Timestamp, Weight)> {
+        let n = self.num_buckets;
+        let free_flow = self.free_flow_travel_time[arc];
+        let sums = match self.sums.get(&arc) {
+            Some(sums) => sums,
+            None => return vec![(0, free_flow)],
+        };
+
+        // a larger radius would count buckets twice
+        let radius = min(self.params.smoothing_radius, (n - 1) / 2);
+        let smoothed: Vec<Option<f64>> = (0..n)
+            .map(|bucket| {
+                let window = (bucket + n - radius..=bucket + n + radius).map(|b| b % n);
+                let quality: f64 = window.clone().map(|b| sums[b].1).sum();
+                if quality > 0.0 && quality >= f64::from(self.params.min_quality) {
+                    Some(window.map(|b| sums[b].0).sum::<f64>() / quality)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let known: Vec<usize> = (0..n).filter(|&bucket| smoothed[bucket].is_some()).collect();
+        if known.is_empty() {
+            return vec![(0, free_flow)];
+        }
+
+        // fill the gaps between buckets with data linearly, around the end of the period as well
+        let mut travel_times = vec![free_flow; n];
+        for (i, &from) in known.iter().enumerate() {
+            let to = known[(i + 1) % known.len()];
+            let gap = match (to + n - from) % n {
+                0 => n,
+                gap => gap,
+            };
+            let (from_value, to_value) = (smoothed[from].unwrap(), smoothed[to].unwrap());
+            for step in 0..gap {
+                let ratio = step as f64 / gap as f64;
+                let value = (1.0 - ratio) * from_value + ratio * to_value;
+                travel_times[(from + step) % n] = max(value.round() as Weight, free_flow);
+            }
+        }
+
+        // Departing later must not allow arriving earlier.
+        // Lowering a travel time may violate the constraint of the previous bucket, so this is propagated backwards.
+        // The second pass covers the propagation across the end of the period.
+        let departure = |bucket: usize| bucket as Timestamp * self.params.bucket_size;
+        for _ in 0..2 {
+            for bucket in (0..n).rev() {
+                let next_arrival = departure(bucket + 1) + travel_times[(bucket + 1) % n];
+                travel_times[bucket] = min(travel_times[bucket], next_arrival - departure(bucket));
+            }
+        }
+
+        // skip points which lie on a constant segment
+        (0..n)
+            .filter(|&bucket| bucket == 0 || travel_times[bucket - 1] != travel_times[bucket] || travel_times[bucket] != travel_times[(bucket + 1) % n])
+            .map(|bucket| (departure(bucket), travel_times[bucket]))
+            .collect()
+    }
+}
+
+/// Panics on estimates for unknown arcs, use `ProfileBuilder::add` to handle them.
+impl Extend<LinkSpeedData> for ProfileBuilder {
+    fn extend<I: IntoIterator<Item = LinkSpeedData>>(&mut self, estimates: I) {
+        for estimate in estimates {
+            self.add(&estimate).unwrap();
+        }
+    }
+}
+
+/// Travel time profiles of all arcs in the format of `TDGraph`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profiles {
+    pub first_ipp_of_arc: Vec<u32>,
+    pub ipp_departure_time: Vec<Timestamp>,
+    pub ipp_travel_time: Vec<Weight>,
+}
+
+impl Profiles {
+    pub fn into_graph(self, first_out: Vec<EdgeId>, head: Vec<NodeId>) -> TDGraph {
+        TDGraph::new(first_out, head, self.first_ipp_of_arc, self.ipp_departure_time, self.ipp_travel_time)
+    }
+}
+
+impl Deconstruct for Profiles {
+    fn store_each(&self, store: &dyn Fn(&str, &dyn Store) -> std::io::Result<()>) -> std::io::Result<()> {
+        store("first_ipp_of_arc", &self.first_ipp_of_arc)?;
+        store("ipp_departure_time", &self.ipp_departure_time)?;
+        store("ipp_travel_time", &self.ipp_travel_time)?;
+        Ok(())
+    }
+}
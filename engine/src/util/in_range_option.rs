@@ -33,6 +33,7 @@ impl Sentinel for usize {
 /// `InRangeOptions` are constructed from real `Options`.
 /// To work with the encapsulated data, the type has to be converted back into an actual `Option` through the `value` method.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(transparent)]
 pub struct InRangeOption<T: Sentinel + Debug>(T);
 
 impl<T: Sentinel + Debug> InRangeOption<T> {
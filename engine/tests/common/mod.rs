@@ -0,0 +1,94 @@
+//! Fixtures shared by the integration test crates.
+
+// not every test crate uses every fixture
+#![allow(dead_code)]
+
+use rust_road_router::{
+    algo::{
+        customizable_contraction_hierarchy::{self, CCH},
+        dijkstra::{query::dijkstra::Server as DijkServer, DefaultOps},
+        *,
+    },
+    datastr::graph::*,
+};
+use std::path::{Path, PathBuf};
+
+// size x size grid with arcs in both directions and coordinates on the grid points
+pub fn grid_graph(size: usize) -> (OwnedGraph, Vec<f32>, Vec<f32>) {
+    let mut adjacency_lists = vec![Vec::new(); size * size];
+    let mut lat = Vec::new();
+    let mut lng = Vec::new();
+    for row in 0..size {
+        for col in 0..size {
+            let node = row * size + col;
+            lat.push(row as f32);
+            lng.push(col as f32);
+            if col + 1 < size {
+                adjacency_lists[node].push(Link {
+                    node: node as NodeId + 1,
+                    weight: (node % 7 + 1) as Weight,
+                });
+                adjacency_lists[node + 1].push(Link {
+                    node: node as NodeId,
+                    weight: (node % 5 + 1) as Weight,
+                });
+            }
+            if row + 1 < size {
+                adjacency_lists[node].push(Link {
+                    node: (node + size) as NodeId,
+                    weight: (node % 3 + 1) as Weight,
+                });
+                adjacency_lists[node + size].push(Link {
+                    node: node as NodeId,
+                    weight: (node % 4 + 2) as Weight,
+                });
+            }
+        }
+    }
+    (OwnedGraph::from_adjancecy_lists(adjacency_lists), lat, lng)
+}
+
+// CCH contracted along a nested dissection order of the graph
+pub fn cch_for_grid<G: for<'a> LinkIterable<'a, NodeId> + RandomLinkAccessGraph>(graph: &G, lat: &[f32], lng: &[f32]) -> CCH {
+    let order = customizable_contraction_hierarchy::nested_dissection(graph, lat, lng);
+    customizable_contraction_hierarchy::contract(graph, order)
+}
+
+// compares `distance` for all pairs of nodes with the distances Dijkstra finds on `graph`
+pub fn assert_distances_match_dijkstra<G: for<'a> LinkIterable<'a, Link>>(graph: G, mut distance: impl FnMut(NodeId, NodeId) -> Option<Weight>) {
+    let n = graph.num_nodes() as NodeId;
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(graph);
+    for from in 0..n {
+        for to in 0..n {
+            assert_eq!(
+                distance(from, to),
+                QueryServer::query(&mut dijkstra, Query { from, to }).map(|res| res.distance()),
+                "from {} to {}",
+                from,
+                to
+            );
+        }
+    }
+}
+
+// Directory in the system temp dir which is removed on drop, so failing tests don't leave it behind either.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("rust_road_router_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // ignore errors, a panic while unwinding would abort the test binary
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,16 +1,39 @@
 extern crate rust_road_router;
 
+mod common;
+
+use common::*;
 use rust_road_router::{
     algo::{
+        alt::{self, *},
+        ch_potentials::{CCHPotential, Potential, TurnExpandedPotential},
+        contraction_hierarchy::{self, query::Server as CHServer},
+        customizable_contraction_hierarchy::{self, query::Server as CCHServer},
         dijkstra::{
-            query::{bidirectional_dijkstra::Server as BiDijkServer, dijkstra::Server as DijkServer},
+            generic_dijkstra::GenericDijkstra,
+            query::{
+                bidirectional_dijkstra::Server as BiDijkServer, dijkstra::Server as DijkServer, pareto_dijkstra::Server as ParetoServer,
+                td_dijkstra::TDDijkstraOps,
+            },
             *,
         },
         *,
     },
-    datastr::graph::*,
+    datastr::{
+        graph::{
+            bi_criteria::BiCriteriaGraph,
+            time_dependent::TDGraph,
+            turn_costs::{ArcBearings, TrafficSide, TurnCostModel, TurnCostParams},
+            turn_expansion::{TurnExpandedGraph, TurnExpansion, TurnTable, UTurns},
+            *,
+        },
+        node_order::NodeOrder,
+    },
+    io::*,
 };
 
+use rand::prelude::*;
+
 fn graph() -> OwnedGraph {
     // This is the directed graph we're going to use.
     // The node numbers correspond to the different states,
@@ -64,3 +87,1124 @@ fn bidir_dijkstra_stopping_crit() {
 
     assert_eq!(server.query(Query { from: 0, to: 4 }).map(|res| res.distance()), Some(12));
 }
+
+#[test]
+fn cch_with_nested_dissection_order_correct_distances() {
+    let size = 6;
+    let (graph, lat, lng) = grid_graph(size);
+
+    let order = customizable_contraction_hierarchy::nested_dissection(&graph, &lat, &lng);
+    let mut ranks = order.ranks().to_vec();
+    ranks.sort_unstable();
+    assert_eq!(ranks, (0..(size * size) as NodeId).collect::<Vec<_>>());
+
+    let cch = customizable_contraction_hierarchy::contract(&graph, order);
+    let mut cch_server = CCHServer::new(customizable_contraction_hierarchy::customize(&cch, &graph));
+    assert_distances_match_dijkstra(graph.clone(), |from, to| cch_server.query(Query { from, to }).map(|res| res.distance()));
+}
+
+#[test]
+fn ch_with_heuristic_order_correct_distances() {
+    let size = 6;
+    let (grid, _, _) = grid_graph(size);
+
+    let (order, ch) = contraction_hierarchy::order_and_contract(&grid);
+    let mut ranks = order.ranks().to_vec();
+    ranks.sort_unstable();
+    assert_eq!(ranks, (0..(size * size) as NodeId).collect::<Vec<_>>());
+
+    let mut ch_server = CHServer::new(ch, order);
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(grid);
+
+    for from in 0..(size * size) as NodeId {
+        for to in 0..(size * size) as NodeId {
+            assert_eq!(
+                ch_server.query(Query { from, to }).map(|res| res.distance()),
+                QueryServer::query(&mut dijkstra, Query { from, to }).map(|res| res.distance())
+            );
+        }
+    }
+
+    let (order, ch) = contraction_hierarchy::order_and_contract(&graph());
+    let mut ch_server = CHServer::new(ch, order);
+    assert_eq!(ch_server.query(Query { from: 0, to: 3 }).map(|res| res.distance()), Some(3));
+    assert_eq!(ch_server.query(Query { from: 3, to: 0 }).map(|res| res.distance()), Some(7));
+    assert_eq!(ch_server.query(Query { from: 0, to: 4 }).map(|res| res.distance()), Some(5));
+    assert_eq!(ch_server.query(Query { from: 4, to: 0 }).map(|res| res.distance()), None);
+}
+
+#[test]
+fn cch_customized_store_and_reload() {
+    let size = 6;
+    let (graph, lat, lng) = grid_graph(size);
+    let cch = cch_for_grid(&graph, &lat, &lng);
+
+    let temp = TempDir::new("customized");
+    let dir = temp.path();
+    customizable_contraction_hierarchy::CustomizedMetric {
+        customized: &customizable_contraction_hierarchy::customize(&cch, &graph),
+        metric: graph.weight(),
+    }
+    .deconstruct_to(&dir)
+    .unwrap();
+
+    let reloaded = customizable_contraction_hierarchy::CustomizedReconstrctor {
+        cch: &cch,
+        metric: graph.weight(),
+    }
+    .reconstruct_from(&dir)
+    .unwrap();
+    let mut cch_server = CCHServer::new(reloaded);
+    assert_distances_match_dijkstra(graph.clone(), |from, to| cch_server.query(Query { from, to }).map(|res| res.distance()));
+
+    // a CCH with a different order must not accept the stored metric
+    let other_cch = customizable_contraction_hierarchy::contract(&graph, NodeOrder::identity(size * size));
+    assert!(customizable_contraction_hierarchy::CustomizedReconstrctor {
+        cch: &other_cch,
+        metric: graph.weight(),
+    }
+    .reconstruct_from(&dir)
+    .is_err());
+
+    // neither a customization with outdated weights
+    let mut changed_weights = graph.weight().to_vec();
+    changed_weights[0] += 1;
+    assert!(customizable_contraction_hierarchy::CustomizedReconstrctor {
+        cch: &cch,
+        metric: &changed_weights,
+    }
+    .reconstruct_from(&dir)
+    .is_err());
+}
+
+#[test]
+fn cch_and_directed_cch_store_and_reload() {
+    let size = 6;
+    let (graph, lat, lng) = grid_graph(size);
+    let cch = cch_for_grid(&graph, &lat, &lng);
+
+    let temp = TempDir::new("cch");
+    let dir = temp.path();
+    let cch_dir = dir.join("cch");
+    let directed_cch_dir = dir.join("directed_cch");
+    std::fs::create_dir_all(&cch_dir).unwrap();
+    std::fs::create_dir_all(&directed_cch_dir).unwrap();
+
+    cch.deconstruct_to(&cch_dir).unwrap();
+    let reloaded_cch = customizable_contraction_hierarchy::CCH::reconstruct_from(&cch_dir).unwrap();
+    // wrong kind of hierarchy
+    assert!(customizable_contraction_hierarchy::DirectedCCH::reconstruct_from(&cch_dir).is_err());
+
+    let directed_cch = cch.into_directed_cch();
+    directed_cch.deconstruct_to(&directed_cch_dir).unwrap();
+    let reloaded_directed_cch = customizable_contraction_hierarchy::DirectedCCH::reconstruct_from(&directed_cch_dir).unwrap();
+    assert!(customizable_contraction_hierarchy::CCH::reconstruct_from(&directed_cch_dir).is_err());
+
+    let mut cch_server = CCHServer::new(customizable_contraction_hierarchy::customize(&reloaded_cch, &graph));
+    let mut directed_cch_server = CCHServer::new(customizable_contraction_hierarchy::customize_directed(&reloaded_directed_cch, &graph));
+    assert_distances_match_dijkstra(graph.clone(), |from, to| cch_server.query(Query { from, to }).map(|res| res.distance()));
+    assert_distances_match_dijkstra(graph.clone(), |from, to| {
+        directed_cch_server.query(Query { from, to }).map(|res| res.distance())
+    });
+
+    // corrupted data has to be rejected
+    let mut head: Vec<NodeId> = Vec::load_from(cch_dir.join("cch_head")).unwrap();
+    head.swap(0, 1);
+    head.write_to(&cch_dir.join("cch_head")).unwrap();
+    assert!(customizable_contraction_hierarchy::CCH::reconstruct_from(&cch_dir).is_err());
+
+    // a header of the current version but with missing fields
+    let mut header: Vec<u64> = Vec::load_from(cch_dir.join("cch_header")).unwrap();
+    header.pop();
+    header.write_to(&cch_dir.join("cch_header")).unwrap();
+    let error = customizable_contraction_hierarchy::CCH::reconstruct_from(&cch_dir).unwrap_err();
+    assert!(error.to_string().contains("malformed"), "{}", error);
+}
+
+#[test]
+fn cch_paths_on_asymmetric_graph_match_distances() {
+    let size = 6;
+    let (grid, lat, lng) = grid_graph(size);
+    let n = (size * size) as NodeId;
+    // some roads are one-way and both directions of the others get independent weights,
+    // so the upward and downward arcs of the directed CCH differ
+    let mut rng = StdRng::from_seed([11; 32]);
+    let mut first_out = vec![0];
+    let mut head = Vec::new();
+    let mut weights = Vec::new();
+    for node in 0..n {
+        for neighbor in LinkIterable::<NodeId>::link_iter(&grid, node) {
+            if rng.gen_bool(0.8) {
+                head.push(neighbor);
+                weights.push(rng.gen_range(1, 20));
+            }
+        }
+        first_out.push(head.len() as EdgeId);
+    }
+    let graph = OwnedGraph::new(first_out, head, weights);
+    let cch = cch_for_grid(&graph, &lat, &lng);
+
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(graph.clone());
+    let mut ground_truth = Vec::new();
+    for from in 0..n {
+        for to in 0..n {
+            ground_truth.push(QueryServer::query(&mut dijkstra, Query { from, to }).map(|res| res.distance()));
+        }
+    }
+
+    // the weights along the unpacked path have to add up to the distance
+    let check = |res: Option<(Weight, Vec<NodeId>)>, from: NodeId, to: NodeId| {
+        assert_eq!(res.as_ref().map(|&(distance, _)| distance), ground_truth[(from * n + to) as usize]);
+        if let Some((distance, path)) = res {
+            assert_eq!((path[0], *path.last().unwrap()), (from, to));
+            let path_length: Weight = path
+                .windows(2)
+                .map(|arc| graph.weight()[graph.edge_index(arc[0], arc[1]).unwrap() as usize])
+                .sum();
+            assert_eq!(path_length, distance);
+        }
+    };
+
+    let mut cch_server = CCHServer::new(customizable_contraction_hierarchy::customize(&cch, &graph));
+    for from in 0..n {
+        for to in 0..n {
+            check(cch_server.query(Query { from, to }).map(|mut res| (res.distance(), res.path())), from, to);
+        }
+    }
+
+    let directed_cch = cch.into_directed_cch();
+    let mut directed_cch_server = CCHServer::new(customizable_contraction_hierarchy::customize_directed(&directed_cch, &graph));
+    for from in 0..n {
+        for to in 0..n {
+            check(
+                directed_cch_server.query(Query { from, to }).map(|mut res| (res.distance(), res.path())),
+                from,
+                to,
+            );
+        }
+    }
+}
+
+#[test]
+fn mmap_backed_graph_correct_distances() {
+    let temp = TempDir::new("mmap");
+    let dir = temp.path();
+    graph().deconstruct_to(&dir).unwrap();
+    Vec::<u32>::new().write_to(&dir.join("empty")).unwrap();
+
+    let graph = FirstOutGraph::new(
+        MmapSlice::<EdgeId>::map_from(dir.join("first_out")).unwrap(),
+        MmapSlice::<NodeId>::map_from(dir.join("head")).unwrap(),
+        MmapSlice::<Weight>::map_from(dir.join("weights")).unwrap(),
+    );
+    assert_eq!(graph.first_out(), &[0, 2, 3, 6, 8, 8, 8]);
+    assert!(MmapSlice::<u32>::map_from(dir.join("empty")).unwrap().is_empty());
+
+    let mut server = DijkServer::<DefaultOps, _, _>::new(graph);
+    assert_eq!(QueryServer::query(&mut server, Query { from: 0, to: 4 }).map(|res| res.distance()), Some(5));
+    assert_eq!(QueryServer::query(&mut server, Query { from: 4, to: 0 }).map(|res| res.distance()), None);
+}
+
+#[test]
+fn headers_are_validated() {
+    let temp = TempDir::new("header");
+    let dir = temp.path();
+
+    let data: Vec<u32> = vec![1, 2, 3, 42];
+    data.write_with_header_to(&dir.join("with_header")).unwrap();
+    data.write_to(&dir.join("legacy")).unwrap();
+    vec![1u8, 2, 3].write_to(&dir.join("odd_size")).unwrap();
+
+    assert_eq!(Vec::<u32>::load_with_header_from(dir.join("with_header")).unwrap(), data);
+    assert_eq!(&MmapSlice::<u32>::map_with_header_from(dir.join("with_header")).unwrap()[..], &data[..]);
+    assert_eq!(Vec::<u32>::load_with_header_from(dir.join("legacy")).unwrap(), data);
+    assert_eq!(&MmapSlice::<u32>::map_with_header_from(dir.join("legacy")).unwrap()[..], &data[..]);
+
+    assert_eq!(
+        Vec::<u64>::load_with_header_from(dir.join("with_header")).unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+    assert!(Vec::<i32>::load_with_header_from(dir.join("with_header")).is_err());
+    assert!(Vec::<f32>::load_with_header_from(dir.join("with_header")).is_err());
+    assert!(MmapSlice::<u64>::map_with_header_from(dir.join("with_header")).is_err());
+    assert!(Vec::<u32>::load_with_header_from(dir.join("odd_size")).is_err());
+}
+
+#[test]
+fn validation_detects_violations() {
+    let graph = graph();
+    assert!(validate::index_array("first_out", graph.first_out(), graph.num_arcs()).is_ok());
+    assert!(validate::head_in_range(graph.head(), graph.num_nodes()).is_ok());
+    assert!(validate::weights("weight", graph.weight()).is_ok());
+
+    assert_eq!(validate::index_array("first_out", &[0, 2, 1, 4], 3).num_violations, 2);
+    assert_eq!(validate::head_in_range(&[0, 5, 1, 7], 5).num_violations, 2);
+    assert_eq!(validate::weights("weight", &[1, INFINITY]).num_violations, 1);
+    assert_eq!(validate::strictly_increasing("osm_node_ids", &[1u64, 3, 3, 2]).violations[0].0, 2);
+    assert!(validate::permutation("cch_perm", &[2, 0, 1]).is_ok());
+    assert_eq!(validate::permutation("cch_perm", &[2, 0, 2, 4]).num_violations, 2);
+    assert_eq!(validate::coordinates(&[52.5, 91.0, std::f32::NAN], &[13.4, 0.0, 0.0]).num_violations, 2);
+    let load_error = std::io::Error::new(std::io::ErrorKind::InvalidData, "header does not match");
+    assert_eq!(validate::loadable("travel_time", &load_error).num_violations, 1);
+
+    let first_ipp_of_arc = [0, 2, 4, 5];
+    let ipp_departure_time = [0, 50_000, 0, 50_000, 0];
+    let fifo_violating = [10, 100, 100_000, 10, 10];
+    let checks = validate::travel_time_functions(&first_ipp_of_arc, &ipp_departure_time, &fifo_violating);
+    assert_eq!(checks.iter().map(|check| check.num_violations).sum::<usize>(), 1);
+    assert_eq!(checks[3].violations[0].0, 3);
+
+    let valid = [10, 100, 100, 10, 10];
+    assert!(validate::travel_time_functions(&first_ipp_of_arc, &ipp_departure_time, &valid)
+        .iter()
+        .all(|check| check.is_ok()));
+}
+
+#[test]
+fn many_to_many_matches_dijkstra() {
+    let size = 6;
+    let (grid, lat, lng) = grid_graph(size);
+    let n = (size * size) as NodeId;
+    let sources: Vec<NodeId> = (0..n).step_by(3).collect();
+    let targets: Vec<NodeId> = (0..n).rev().step_by(2).collect();
+
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(grid.clone());
+    let mut expected = Vec::new();
+    for &from in &sources {
+        for &to in &targets {
+            expected.push(
+                QueryServer::query(&mut dijkstra, Query { from, to })
+                    .map(|res| res.distance())
+                    .unwrap_or(INFINITY),
+            );
+        }
+    }
+
+    let cch = cch_for_grid(&grid, &lat, &lng);
+    let cch_server = customizable_contraction_hierarchy::many_to_many::Server::new(customizable_contraction_hierarchy::customize(&cch, &grid));
+    assert_eq!(cch_server.many_to_many(&sources, &targets), expected);
+    assert_eq!(cch_server.one_to_many(sources[1], &targets)[..], expected[targets.len()..2 * targets.len()]);
+
+    let (order, ch) = contraction_hierarchy::order_and_contract(&grid);
+    let ch_server = contraction_hierarchy::many_to_many::Server::new(ch, order);
+    assert_eq!(ch_server.many_to_many(&sources, &targets), expected);
+
+    let (order, ch) = contraction_hierarchy::order_and_contract(&graph());
+    let ch_server = contraction_hierarchy::many_to_many::Server::new(ch, order);
+    assert_eq!(ch_server.one_to_many(4, &[0, 4]), vec![INFINITY, 0]);
+    assert_eq!(ch_server.many_to_many(&[0, 3], &[3, 0]), vec![3, 0, 0, 7]);
+    assert!(ch_server.many_to_many(&[], &[0]).is_empty());
+}
+
+#[test]
+fn phast_matches_dijkstra() {
+    let size = 6;
+    let (grid, lat, lng) = grid_graph(size);
+    let n = (size * size) as NodeId;
+    let targets = [3, 17, 35];
+
+    let cch = cch_for_grid(&grid, &lat, &lng);
+    let mut cch_phast = customizable_contraction_hierarchy::phast::Server::new(customizable_contraction_hierarchy::customize(&cch, &grid));
+    let (order, ch) = contraction_hierarchy::order_and_contract(&grid);
+    let mut ch_phast = contraction_hierarchy::phast::Server::new(ch, order);
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(grid);
+
+    for from in 0..n {
+        let dijkstra_result = dijkstra.one_to_all(from);
+        let cch_result = cch_phast.one_to_all(from);
+        for node in 0..n {
+            assert_eq!(cch_result.distance(node), dijkstra_result.distance(node));
+        }
+        let ch_result = ch_phast.one_to_all(from);
+        for node in 0..n {
+            assert_eq!(ch_result.distance(node), dijkstra_result.distance(node));
+        }
+    }
+
+    cch_phast.select_targets(&targets);
+    ch_phast.select_targets(&targets);
+    for from in 0..n {
+        let dijkstra_result = dijkstra.one_to_all(from);
+        let cch_result = cch_phast.one_to_selected(from);
+        for &target in &targets {
+            assert_eq!(cch_result.distance(target), dijkstra_result.distance(target));
+        }
+        let ch_result = ch_phast.one_to_selected(from);
+        for &target in &targets {
+            assert_eq!(ch_result.distance(target), dijkstra_result.distance(target));
+        }
+    }
+
+    let (order, ch) = contraction_hierarchy::order_and_contract(&graph());
+    let mut ch_phast = contraction_hierarchy::phast::Server::new(ch, order);
+    assert_eq!(ch_phast.one_to_all(4).distance(0), INFINITY);
+    assert_eq!(ch_phast.one_to_all(0).distance(4), 5);
+}
+
+#[test]
+fn isochrones_cut_boundary_arcs() {
+    let (order, ch) = contraction_hierarchy::order_and_contract(&graph());
+    let mut phast = contraction_hierarchy::phast::Server::new(ch, order);
+    let lat: Vec<f32> = (0..6).map(|node| node as f32).collect();
+    let lng = vec![0.0; 6];
+
+    let distances = phast.one_to_all(0);
+    let static_result = isochrone::static_isochrone(&graph(), |node| distances.distance(node), 3, &lat, &lng);
+    assert_eq!(static_result.reached_nodes, vec![0, 1, 3]);
+    assert_eq!(static_result.boundary.iter().map(|point| point.edge).collect::<Vec<_>>(), vec![0, 6, 7]);
+    assert!((static_result.boundary[0].fraction - 0.3).abs() < 1e-6);
+    assert!((static_result.boundary[0].latitude - 0.6).abs() < 1e-6);
+
+    let td_graph = TDGraph::new(
+        graph().first_out().to_vec(),
+        graph().head().to_vec(),
+        (0..=8).collect(),
+        vec![0; 8],
+        graph().weight().to_vec(),
+    );
+    let mut dijkstra = GenericDijkstra::<TDDijkstraOps, _>::new(td_graph);
+    let mut td_result = isochrone::td_isochrone(&mut dijkstra, 0, 1000, 3, &lat, &lng);
+    td_result.reached_nodes.sort_unstable();
+    assert_eq!(td_result, static_result);
+}
+
+#[test]
+fn concave_hull_digs_into_gaps() {
+    // U shape opening upwards
+    let points: Vec<(f32, f32)> = (0..5)
+        .flat_map(|x| (0..5).map(move |y| (x as f32, y as f32)))
+        .filter(|&(x, y)| !(x > 0.0 && x < 4.0 && y > 1.0))
+        .collect();
+    let area = |polygon: &[(f32, f32)]| {
+        polygon
+            .iter()
+            .zip(polygon.iter().cycle().skip(1))
+            .map(|(a, b)| a.0 * b.1 - a.1 * b.0)
+            .sum::<f32>()
+            / 2.0
+    };
+
+    let convex = isochrone::concave_hull(&points, 10.0);
+    assert_eq!(convex.len(), 4);
+    assert!((area(&convex) - 16.0).abs() < 1e-6);
+
+    let concave = isochrone::concave_hull(&points, 1.5);
+    assert!(area(&concave) > 0.0 && area(&concave) < 16.0);
+    assert!(concave.iter().all(|point| points.contains(point)));
+}
+
+#[test]
+fn cch_alternatives_are_admissible() {
+    let size = 8;
+    let (grid, lat, lng) = grid_graph(size);
+    let n = (size * size) as NodeId;
+    let cch = cch_for_grid(&grid, &lat, &lng);
+    let mut server = CCHServer::new(customizable_contraction_hierarchy::customize(&cch, &grid));
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(grid.clone());
+    let params = customizable_contraction_hierarchy::query::AlternativeParams::default();
+
+    let mut num_alternatives = 0;
+    for from in (0..n).step_by(5) {
+        for to in (0..n).step_by(7) {
+            let routes = server.alternatives(Query { from, to }, 2, params);
+            let shortest = QueryServer::query(&mut dijkstra, Query { from, to }).unwrap().distance();
+            assert!(!routes.is_empty() && routes.len() <= 3);
+            assert_eq!(routes[0].distance, shortest);
+            for route in &routes {
+                assert_eq!(route.path.first(), Some(&from));
+                assert_eq!(route.path.last(), Some(&to));
+                let length: Weight = route
+                    .path
+                    .windows(2)
+                    .map(|arc| grid.link(grid.edge_index(arc[0], arc[1]).unwrap()).weight)
+                    .sum();
+                assert_eq!(length, route.distance);
+                assert!(f64::from(route.distance) <= 1.25 * f64::from(shortest));
+            }
+            for (i, route) in routes.iter().enumerate() {
+                assert!(routes[..i].iter().all(|other| other.path != route.path));
+            }
+            num_alternatives += routes.len() - 1;
+        }
+    }
+    assert!(num_alternatives > 0);
+}
+
+#[test]
+fn turn_aware_cch_respects_forbidden_turns() {
+    let (grid, lat, lng) = grid_graph(5);
+    let expansion = TurnExpansion::new(&grid);
+
+    let mut forbidden_turn_from_arc = Vec::new();
+    let mut forbidden_turn_to_arc = Vec::new();
+    for from_arc in 0..grid.num_arcs() as EdgeId {
+        for to_arc in expansion.outgoing(expansion.head(from_arc)) {
+            if (from_arc + to_arc) % 3 == 0 {
+                forbidden_turn_from_arc.push(from_arc);
+                forbidden_turn_to_arc.push(to_arc);
+            }
+        }
+    }
+    let forbidden_turns = TurnTable::new(&forbidden_turn_from_arc, &forbidden_turn_to_arc);
+    let is_allowed = |from_arc, to_arc| forbidden_turns.find(from_arc, to_arc).is_none() && !expansion.is_u_turn(from_arc, to_arc);
+    let exp_graph = grid.line_graph(|from_arc, to_arc| if is_allowed(from_arc, to_arc) { Some(0) } else { None });
+
+    let (exp_lat, exp_lng) = expansion.edge_node_coordinates(&lat, &lng);
+    let cch = cch_for_grid(&exp_graph, &exp_lat, &exp_lng);
+    let order = customizable_contraction_hierarchy::CCHReordering {
+        cch: &cch,
+        latitude: &[],
+        longitude: &[],
+    }
+    .reorder_for_seperator_based_customization();
+    let cch = customizable_contraction_hierarchy::contract(&exp_graph, order).into_directed_cch();
+    let mut server = customizable_contraction_hierarchy::query::turns::Server::new(
+        customizable_contraction_hierarchy::customize_directed(&cch, &exp_graph),
+        &expansion,
+        grid.weight().to_vec(),
+    );
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(exp_graph.clone());
+
+    for from in 0..grid.num_nodes() as NodeId {
+        for to in 0..grid.num_nodes() as NodeId {
+            let mut expected = if from == to { Some(0) } else { None };
+            for first in expansion.outgoing(from) {
+                let distances = dijkstra.one_to_all(first);
+                for &last in expansion.incoming(to) {
+                    if distances.distance(last) < INFINITY {
+                        let distance = distances.distance(last) + grid.weight()[last as usize];
+                        expected = Some(expected.map_or(distance, |best: Weight| best.min(distance)));
+                    }
+                }
+            }
+
+            let result = server.node_query(from, to);
+            assert_eq!(result.as_ref().map(|(distance, _)| *distance), expected);
+            if let Some((distance, path)) = result {
+                assert_eq!(path.iter().map(|&arc| grid.weight()[arc as usize]).sum::<Weight>(), distance);
+                assert!(path.windows(2).all(|turn| is_allowed(turn[0], turn[1])));
+                let nodes = expansion.node_path(&path);
+                if from != to {
+                    assert_eq!((nodes.first(), nodes.last()), (Some(&from), Some(&to)));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn geometric_turn_costs() {
+    // node 4 is the center of the grid, coming from the south (node 1)
+    let (grid, lat, lng) = grid_graph(3);
+    let expansion = TurnExpansion::new(&grid);
+    let bearings = ArcBearings::from_coordinates(&expansion, &lat, &lng);
+    let arc = |from, to| grid.edge_index(from, to).unwrap();
+    let (incoming, straight, right, left, u_turn) = (arc(1, 4), arc(4, 7), arc(4, 5), arc(4, 3), arc(4, 1));
+
+    let right_hand = TurnCostModel::new(TurnCostParams::default(), &expansion, &bearings);
+    assert!(right_hand.turn_angle(incoming, straight).abs() < 0.1);
+    assert!((right_hand.turn_angle(incoming, right) - 90.0).abs() < 0.1);
+    assert!((right_hand.turn_angle(incoming, left) + 90.0).abs() < 0.1);
+
+    let approx = |cost: Weight, expected: Weight| (i64::from(cost) - i64::from(expected)).abs() <= 1;
+    assert_eq!(right_hand.cost(incoming, straight), 0);
+    assert!(approx(right_hand.cost(incoming, right), 3000));
+    assert!(approx(right_hand.cost(incoming, left), 6000));
+    assert_eq!(right_hand.cost(incoming, u_turn), 20000);
+
+    let left_hand = TurnCostModel::new(
+        TurnCostParams {
+            traffic_side: TrafficSide::Left,
+            ..TurnCostParams::default()
+        },
+        &expansion,
+        &bearings,
+    );
+    assert!(approx(left_hand.cost(incoming, right), 6000));
+    assert!(approx(left_hand.cost(incoming, left), 3000));
+
+    let mut road_classes = vec![1; grid.num_arcs()];
+    road_classes[straight as usize] = 3;
+    let with_classes = TurnCostModel::new(TurnCostParams::default(), &expansion, &bearings).with_road_classes(&road_classes);
+    assert_eq!(with_classes.cost(incoming, straight), 2000);
+}
+
+#[test]
+fn implicit_turn_expanded_graph_matches_line_graph() {
+    let (grid, lat, lng) = grid_graph(5);
+    let expansion = TurnExpansion::new(&grid);
+
+    let mut forbidden_turn_from_arc = Vec::new();
+    let mut forbidden_turn_to_arc = Vec::new();
+    let mut turn_cost_from_arc = Vec::new();
+    let mut turn_cost_to_arc = Vec::new();
+    let mut turn_cost = Vec::new();
+    for from_arc in 0..grid.num_arcs() as EdgeId {
+        for to_arc in expansion.outgoing(expansion.head(from_arc)) {
+            if (from_arc + to_arc) % 5 == 0 {
+                forbidden_turn_from_arc.push(from_arc);
+                forbidden_turn_to_arc.push(to_arc);
+            } else if (from_arc * to_arc) % 3 == 0 {
+                turn_cost_from_arc.push(from_arc);
+                turn_cost_to_arc.push(to_arc);
+                turn_cost.push(from_arc % 4 + 1);
+            }
+        }
+    }
+    let forbidden_turns = TurnTable::new(&forbidden_turn_from_arc, &forbidden_turn_to_arc);
+    let turn_costs = TurnTable::new(&turn_cost_from_arc, &turn_cost_to_arc);
+
+    let implicit = TurnExpandedGraph::new(&grid, &expansion, forbidden_turns, turn_costs, &turn_cost, UTurns::AtDeadEnds);
+    let exp_graph = grid.line_graph(|from_arc, to_arc| {
+        let next_arcs = expansion.outgoing(expansion.head(from_arc));
+        if forbidden_turns.find(from_arc, to_arc).is_some() || (expansion.is_u_turn(from_arc, to_arc) && next_arcs.end - next_arcs.start > 1) {
+            return None;
+        }
+        Some(turn_costs.find(from_arc, to_arc).map(|idx| turn_cost[idx]).unwrap_or(0))
+    });
+
+    assert_eq!(implicit.num_nodes(), exp_graph.num_nodes());
+    assert_eq!(implicit.num_arcs(), exp_graph.num_arcs());
+    for edge_node in 0..exp_graph.num_nodes() as NodeId {
+        assert_eq!(implicit.neighbor_edge_indices(edge_node), exp_graph.neighbor_edge_indices(edge_node));
+        for edge in exp_graph.neighbor_edge_indices(edge_node) {
+            let (expected, link) = (exp_graph.link(edge), implicit.link(edge));
+            assert_eq!((link.node, link.weight), (expected.node, expected.weight));
+            assert_eq!(implicit.edge_index(edge_node, link.node), Some(edge));
+        }
+    }
+
+    let (exp_lat, exp_lng) = expansion.edge_node_coordinates(&lat, &lng);
+    let order = customizable_contraction_hierarchy::nested_dissection(&exp_graph, &exp_lat, &exp_lng);
+    let cch = customizable_contraction_hierarchy::contract(&implicit, order);
+    let order = customizable_contraction_hierarchy::CCHReordering {
+        cch: &cch,
+        latitude: &[],
+        longitude: &[],
+    }
+    .reorder_for_seperator_based_customization();
+    let cch = customizable_contraction_hierarchy::contract(&implicit, order).into_directed_cch();
+    let mut server = CCHServer::new(customizable_contraction_hierarchy::customize_directed(&cch, &implicit));
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(implicit);
+
+    // distances between the tails of the arcs in the original graph are lower bounds in the turn expanded graph
+    let grid_cch = cch_for_grid(&grid, &lat, &lng);
+    let potential = TurnExpandedPotential::new(&grid, CCHPotential::new(&grid_cch, &grid));
+    let implicit = TurnExpandedGraph::new(&grid, &expansion, forbidden_turns, turn_costs, &turn_cost, UTurns::AtDeadEnds);
+    let mut astar = DijkServer::<DefaultOps, _, _>::with_potential(implicit, potential);
+
+    for from in 0..exp_graph.num_nodes() as NodeId {
+        for to in 0..exp_graph.num_nodes() as NodeId {
+            let ground_truth = QueryServer::query(&mut dijkstra, Query { from, to }).map(|res| res.distance());
+            assert_eq!(server.query(Query { from, to }).map(|res| res.distance()), ground_truth);
+            assert_eq!(QueryServer::query(&mut astar, Query { from, to }).map(|res| res.distance()), ground_truth);
+        }
+    }
+}
+
+#[test]
+fn alt_lower_bounds_and_bidirectional_astar() {
+    let size = 8;
+    let (grid, lat, lng) = grid_graph(size);
+    let n = (size * size) as NodeId;
+    let selections = vec![
+        avoid_landmarks(&grid, 4, &mut StdRng::from_seed(Default::default())),
+        farthest_landmarks(&grid, 4, 0),
+        planar_landmarks(&grid, 4, &lat, &lng),
+    ];
+
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(grid.clone());
+    for selection in selections {
+        assert_eq!(selection.len(), 4);
+        let landmarks = Landmarks::new(&grid, selection);
+        let mut potential = ALTPotential::new(&landmarks);
+        let mut server = alt::Server::new(grid.clone(), &landmarks);
+
+        for from in 0..n {
+            let distances = dijkstra.one_to_all(from);
+            for to in 0..n {
+                potential.init(to);
+                assert!(potential.potential(from).unwrap() <= distances.distance(to));
+
+                let mut result = server.query(Query { from, to }).unwrap();
+                assert_eq!(result.distance(), distances.distance(to));
+                let path = result.path();
+                assert_eq!((path[0], *path.last().unwrap()), (from, to));
+                let path_length: Weight = path
+                    .windows(2)
+                    .map(|arc| {
+                        LinkIterable::<Link>::link_iter(&grid, arc[0])
+                            .filter(|link| link.node == arc[1])
+                            .map(|link| link.weight)
+                            .min()
+                            .unwrap()
+                    })
+                    .sum();
+                assert_eq!(path_length, distances.distance(to));
+            }
+        }
+    }
+
+    // node 4 has no outgoing arcs, so the landmark distances prove that nothing is reachable from it
+    let graph = graph();
+    let landmarks = Landmarks::new(&graph, vec![0, 4]);
+    assert_eq!(landmarks.lower_bound(4, 0), None);
+    let mut server = alt::Server::new(graph.clone(), &landmarks);
+    assert_eq!(server.query(Query { from: 4, to: 0 }).map(|res| res.distance()), None);
+    assert_eq!(server.query(Query { from: 0, to: 4 }).map(|res| res.distance()), Some(5));
+
+    let temp = TempDir::new("alt");
+    let dir = temp.path();
+    landmarks.deconstruct_to(&dir).unwrap();
+    let reloaded = Landmarks::reconstruct_from(&dir).unwrap();
+    assert_eq!(reloaded.landmarks(), landmarks.landmarks());
+    for from in 0..graph.num_nodes() as NodeId {
+        for to in 0..graph.num_nodes() as NodeId {
+            assert_eq!(reloaded.lower_bound(from, to), landmarks.lower_bound(from, to));
+        }
+    }
+
+    // truncated distances and landmarks beyond the nodes have to be rejected
+    let mut forward: Vec<Weight> = Vec::load_from(dir.join("landmark_forward_distances")).unwrap();
+    forward.pop();
+    forward.write_to(&dir.join("landmark_forward_distances")).unwrap();
+    let mut backward: Vec<Weight> = Vec::load_from(dir.join("landmark_backward_distances")).unwrap();
+    backward.pop();
+    backward.write_to(&dir.join("landmark_backward_distances")).unwrap();
+    assert_eq!(Landmarks::reconstruct_from(&dir).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    landmarks.deconstruct_to(&dir).unwrap();
+    vec![0 as NodeId, graph.num_nodes() as NodeId].write_to(&dir.join("landmarks")).unwrap();
+    assert_eq!(Landmarks::reconstruct_from(&dir).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn pareto_routes_for_two_metrics() {
+    let size = 6;
+    let (grid, lat, lng) = grid_graph(size);
+    let n = (size * size) as NodeId;
+    let second_weight: Vec<Weight> = (0..grid.num_arcs()).map(|arc| (arc * 7 % 11 + 1) as Weight).collect();
+    let graph = BiCriteriaGraph::new(grid.first_out(), grid.head(), grid.weight(), &second_weight[..]);
+    let second_graph = FirstOutGraph::new(grid.first_out(), grid.head(), &second_weight[..]);
+
+    let mut pareto = ParetoServer::new(graph.clone());
+    let mut first_dijkstra = DijkServer::<DefaultOps, _, _>::new(grid.clone());
+    let mut second_dijkstra = DijkServer::<DefaultOps, _, _>::new(second_graph);
+
+    let cch = cch_for_grid(&grid, &lat, &lng);
+    let combinations = [(100, 1), (3, 1), (1, 1), (1, 3), (1, 100)];
+    let mut weighted_sum = customizable_contraction_hierarchy::weighted_sum::Server::new(&cch, &graph, &combinations);
+
+    for from in 0..n {
+        for to in 0..n {
+            let routes = pareto.query(Query { from, to });
+            assert!(!routes.is_empty());
+            assert_eq!(
+                Some(routes[0].first),
+                QueryServer::query(&mut first_dijkstra, Query { from, to }).map(|res| res.distance())
+            );
+            assert_eq!(
+                Some(routes.last().unwrap().second),
+                QueryServer::query(&mut second_dijkstra, Query { from, to }).map(|res| res.distance())
+            );
+
+            for (route, next) in routes.iter().zip(routes.iter().skip(1)) {
+                assert!(route.first < next.first && route.second > next.second);
+            }
+            for route in &routes {
+                assert_eq!((route.path[0], *route.path.last().unwrap()), (from, to));
+                let (mut first, mut second) = (0, 0);
+                for nodes in route.path.windows(2) {
+                    let arc = graph.graph().edge_index(nodes[0], nodes[1]).unwrap() as usize;
+                    first += graph.first_weight()[arc];
+                    second += graph.second_weight()[arc];
+                }
+                assert_eq!((first, second), (route.first, route.second));
+            }
+
+            // weighted sum routes are supported pareto optimal routes
+            let supported = weighted_sum.query(Query { from, to });
+            assert!(!supported.is_empty());
+            for route in supported {
+                assert!(routes
+                    .iter()
+                    .any(|pareto_route| (pareto_route.first, pareto_route.second) == (route.first, route.second)));
+            }
+        }
+    }
+}
+
+#[test]
+fn blended_metrics_are_customized_and_cached() {
+    use customizable_contraction_hierarchy::metric::*;
+
+    let metric: MetricBuilder = "travel_time + 0.5 * geo_distance + penalty(4, 100) + block(2)".parse().unwrap();
+    let weights = metric
+        .build_with(
+            |attribute| {
+                Ok(if attribute == "travel_time" {
+                    vec![10, 10, INFINITY, 10]
+                } else {
+                    vec![3, 4, 4, 4]
+                })
+            },
+            || Ok(vec![0, 4, 0, 6]),
+        )
+        .unwrap();
+    assert_eq!(weights, vec![12, 112, INFINITY, INFINITY]);
+
+    let (grid, lat, lng) = grid_graph(6);
+    let geo_distance: Vec<Weight> = (0..grid.num_arcs()).map(|arc| (arc * 7 % 11 + 1) as Weight).collect();
+    let arc_category: Vec<u8> = (0..grid.num_arcs()).map(|arc| if arc % 9 == 0 { FREEWAY_BIT } else { 0 }).collect();
+
+    let temp = TempDir::new("metric");
+    let dir = temp.path();
+    grid.weight().write_to(&dir.join("travel_time")).unwrap();
+    geo_distance.write_to(&dir.join("geo_distance")).unwrap();
+    arc_category.write_to(&dir.join("arc_category")).unwrap();
+
+    let cch = cch_for_grid(&grid, &lat, &lng);
+    let mut cache = MetricCache::new(&cch, grid.first_out(), grid.head(), &dir);
+
+    for expression in &["travel_time", "2*travel_time + geo_distance + block(2)"] {
+        let metric: MetricBuilder = expression.parse().unwrap();
+        assert!(!cache.contains(&metric));
+        let mut cch_server = CCHServer::new(cache.get(&metric).unwrap().clone());
+        assert!(cache.contains(&metric));
+
+        let blended = FirstOutGraph::new(grid.first_out(), grid.head(), metric.build(&dir).unwrap());
+        assert_distances_match_dijkstra(blended, |from, to| cch_server.query(Query { from, to }).map(|res| res.distance()));
+    }
+
+    assert!(cache.get(&"missing_attribute".parse().unwrap()).is_err());
+}
+
+#[test]
+fn multi_metric_server_selects_metric_per_query() {
+    use customizable_contraction_hierarchy::{customize, query::multi_metric::MultiMetricServer};
+
+    let size = 6;
+    let (grid, lat, lng) = grid_graph(size);
+    let n = (size * size) as NodeId;
+    let cch = cch_for_grid(&grid, &lat, &lng);
+
+    let metrics: Vec<(&str, Vec<Weight>)> = vec![
+        ("car", grid.weight().to_vec()),
+        ("truck", grid.weight().iter().enumerate().map(|(arc, &w)| w * 2 + (arc % 5) as Weight).collect()),
+        ("avoid_tolls", (0..grid.num_arcs()).map(|arc| if arc % 7 == 0 { INFINITY } else { 3 }).collect()),
+    ];
+    let mut server = MultiMetricServer::new(
+        metrics[..2]
+            .iter()
+            .map(|(name, weights)| {
+                (
+                    name.to_string(),
+                    customize(&cch, &FirstOutGraph::new(grid.first_out(), grid.head(), &weights[..])),
+                )
+            })
+            .collect(),
+    );
+    server.insert(
+        metrics[2].0.to_string(),
+        customize(&cch, &FirstOutGraph::new(grid.first_out(), grid.head(), &metrics[2].1[..])),
+    );
+    assert_eq!(server.metric_names().collect::<Vec<_>>(), vec!["car", "truck", "avoid_tolls"]);
+    assert!(server.select("bike").is_none());
+
+    let mut dijkstras: Vec<_> = metrics
+        .iter()
+        .map(|(_, weights)| DijkServer::<DefaultOps, _, _>::new(FirstOutGraph::new(grid.first_out(), grid.head(), &weights[..])))
+        .collect();
+    for from in 0..n {
+        for to in 0..n {
+            // interleave the metrics to exercise swapping back and forth
+            for ((name, _), dijkstra) in metrics.iter().zip(dijkstras.iter_mut()).rev() {
+                assert_eq!(
+                    server.select(name).unwrap().query(Query { from, to }).map(|res| res.distance()),
+                    QueryServer::query(dijkstra, Query { from, to }).map(|res| res.distance())
+                );
+            }
+        }
+    }
+
+    // replacing the active and an inactive metric
+    server.select("car").unwrap();
+    server.insert(
+        "car".to_string(),
+        customize(&cch, &FirstOutGraph::new(grid.first_out(), grid.head(), &metrics[1].1[..])),
+    );
+    server.insert(
+        "truck".to_string(),
+        customize(&cch, &FirstOutGraph::new(grid.first_out(), grid.head(), &metrics[0].1[..])),
+    );
+    for (name, dijkstra) in [("truck", 0), ("car", 1)].iter() {
+        for from in 0..n {
+            assert_eq!(
+                server.select(name).unwrap().query(Query { from, to: n - 1 }).map(|res| res.distance()),
+                QueryServer::query(&mut dijkstras[*dijkstra], Query { from, to: n - 1 }).map(|res| res.distance())
+            );
+        }
+    }
+}
+
+#[test]
+fn partial_customization_matches_full_customization() {
+    use customizable_contraction_hierarchy::{customize, customize_partial, Customized};
+
+    let size = 8;
+    let (grid, lat, lng) = grid_graph(size);
+    let cch = cch_for_grid(&grid, &lat, &lng);
+    let ch_weights = |customized: Customized<_>| {
+        let (upward, downward) = customized.into_ch_graphs();
+        (upward.weight().to_vec(), downward.weight().to_vec())
+    };
+
+    let mut weights = grid.weight().to_vec();
+    let mut customized = customize(&cch, &grid);
+    let mut rng = StdRng::from_seed([7; 32]);
+
+    for round in 0..20 {
+        let changed_arcs: Vec<EdgeId> = (0..3).map(|_| rng.gen_range(0, grid.num_arcs()) as EdgeId).collect();
+        for &arc in &changed_arcs {
+            weights[arc as usize] = match round % 3 {
+                0 => INFINITY,
+                1 => 1,
+                _ => rng.gen_range(1, 100),
+            };
+        }
+        let metric = FirstOutGraph::new(grid.first_out(), grid.head(), &weights[..]);
+
+        let stats = customize_partial(&mut customized, &metric, &changed_arcs, 10);
+        assert!(!stats.full_customization);
+        assert!(stats.changed_shortcuts <= stats.touched_shortcuts);
+        assert!(stats.touched_shortcuts < cch.num_arcs());
+        assert_eq!(ch_weights(customized.clone()), ch_weights(customize(&cch, &metric)));
+    }
+
+    // too many changes fall back to a full customization
+    let changed_arcs: Vec<EdgeId> = (0..grid.num_arcs() as EdgeId).step_by(2).collect();
+    for &arc in &changed_arcs {
+        weights[arc as usize] = std::cmp::min(weights[arc as usize] + 5, INFINITY);
+    }
+    let metric = FirstOutGraph::new(grid.first_out(), grid.head(), &weights[..]);
+    let stats = customize_partial(&mut customized, &metric, &changed_arcs, 10);
+    assert!(stats.full_customization);
+    assert_eq!(stats.touched_shortcuts, cch.num_arcs());
+    assert_eq!(ch_weights(customized), ch_weights(customize(&cch, &metric)));
+}
+
+#[test]
+fn perfect_customization_and_arc_elimination() {
+    use customizable_contraction_hierarchy::{customize, customize_directed, customize_perfect, CCHT};
+
+    let size = 7;
+    let (grid, lat, lng) = grid_graph(size);
+    let n = (size * size) as NodeId;
+    let mut rng = StdRng::from_seed([3; 32]);
+    let weights: Vec<Weight> = (0..grid.num_arcs()).map(|_| rng.gen_range(1, 20)).collect();
+    let graph = FirstOutGraph::new(grid.first_out(), grid.head(), weights);
+    let order = customizable_contraction_hierarchy::nested_dissection(&graph, &lat, &lng);
+    let cch = customizable_contraction_hierarchy::contract(&graph, order.clone());
+
+    // perfect weights are the shortest distances between the endpoints of each edge
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(graph.clone());
+    let (upward, downward) = customize_perfect(&cch, &graph).into_ch_graphs();
+    for tail in 0..n {
+        for (head, edge) in LinkIterable::<NodeId>::link_iter(&upward, tail).zip(upward.neighbor_edge_indices(tail)) {
+            let (from, to) = (cch.node_order().node(tail), cch.node_order().node(head));
+            let distance =
+                |from, to, dijkstra: &mut DijkServer<_, _, _>| QueryServer::query(dijkstra, Query { from, to }).map(|res| res.distance()).unwrap_or(INFINITY);
+            assert_eq!(upward.weight()[edge as usize], distance(from, to, &mut dijkstra));
+            assert_eq!(downward.weight()[edge as usize], distance(to, from, &mut dijkstra));
+        }
+    }
+    let num_arcs = cch.num_arcs();
+
+    let perfect_cch = cch.into_perfect_directed_cch(&graph);
+    assert!(perfect_cch.forward_head().len() < num_arcs);
+    assert!(perfect_cch.backward_head().len() < num_arcs);
+
+    let mut perfect_server = CCHServer::new(customize_directed(&perfect_cch, &graph));
+    let cch = customizable_contraction_hierarchy::contract(&graph, order);
+    let mut basic_server = CCHServer::new(customize(&cch, &graph));
+    for from in 0..n {
+        for to in 0..n {
+            let expected = QueryServer::query(&mut dijkstra, Query { from, to }).map(|res| res.distance());
+            assert_eq!(basic_server.query(Query { from, to }).map(|res| res.distance()), expected);
+
+            let mut result = perfect_server.query(Query { from, to });
+            assert_eq!(result.as_ref().map(|res| res.distance()), expected);
+            let path = result.as_mut().unwrap().path();
+            assert_eq!(path.first(), Some(&from));
+            assert_eq!(path.last(), Some(&to));
+            let path_length: Weight = path.windows(2).map(|arc| graph.link(graph.edge_index(arc[0], arc[1]).unwrap()).weight).sum();
+            assert_eq!(Some(path_length), expected);
+        }
+    }
+}
+
+#[test]
+fn concurrent_queries_during_metric_updates() {
+    use customizable_contraction_hierarchy::{customize, query::concurrent::SharedMetric};
+    use rayon::prelude::*;
+
+    let size = 6;
+    let (grid, lat, lng) = grid_graph(size);
+    let n = (size * size) as NodeId;
+    let cch = cch_for_grid(&grid, &lat, &lng);
+
+    let metrics: Vec<Vec<Weight>> = vec![
+        grid.weight().to_vec(),
+        grid.weight().iter().enumerate().map(|(arc, &w)| w + (arc % 3) as Weight).collect(),
+    ];
+    let expected: Vec<Vec<Option<Weight>>> = metrics
+        .iter()
+        .map(|weights| {
+            let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(FirstOutGraph::new(grid.first_out(), grid.head(), &weights[..]));
+            (0..n * n)
+                .map(|query| {
+                    QueryServer::query(
+                        &mut dijkstra,
+                        Query {
+                            from: query / n,
+                            to: query % n,
+                        },
+                    )
+                    .map(|res| res.distance())
+                })
+                .collect()
+        })
+        .collect();
+    let customized = |metric: usize| customize(&cch, &FirstOutGraph::new(grid.first_out(), grid.head(), &metrics[metric][..]));
+
+    let shared = SharedMetric::new(customized(0));
+
+    // a running query keeps its weights when the metric is updated
+    let mut workspace = shared.workspace();
+    let server = workspace.server(&shared);
+    shared.update(customized(1));
+    for query in 0..n * n {
+        let (from, to) = (query / n, query % n);
+        assert_eq!(server.query(Query { from, to }).map(|res| res.distance()), expected[0][query as usize]);
+    }
+    for query in 0..n * n {
+        let (from, to) = (query / n, query % n);
+        assert_eq!(
+            workspace.server(&shared).query(Query { from, to }).map(|res| res.distance()),
+            expected[1][query as usize]
+        );
+    }
+
+    // queries from several threads while the metric is switched back and forth
+    rayon::scope(|s| {
+        s.spawn(|_| {
+            for round in 0..10 {
+                shared.update(customized(round % 2));
+            }
+        });
+        (0..n * n).into_par_iter().for_each_init(
+            || shared.workspace(),
+            |workspace, query| {
+                let distance = workspace
+                    .server(&shared)
+                    .query(Query {
+                        from: query / n,
+                        to: query % n,
+                    })
+                    .map(|res| res.distance());
+                assert!(distance == expected[0][query as usize] || distance == expected[1][query as usize]);
+            },
+        );
+    });
+
+    let customized = shared.customized();
+    let mut server = CCHServer::new(customized);
+    for query in 0..n * n {
+        let (from, to) = (query / n, query % n);
+        assert_eq!(server.query(Query { from, to }).map(|res| res.distance()), expected[1][query as usize]);
+    }
+}
+
+#[test]
+fn edges_are_snapped_in_driving_direction() {
+    use rust_road_router::algo::snapping::*;
+    use rust_road_router::datastr::graph::arc_geometry::ArcGeometry;
+
+    // about 111m between neighboring grid points
+    let (grid, lat, lng) = grid_graph(5);
+    let lat: Vec<f32> = lat.iter().map(|lat| lat * 0.001).collect();
+    let lng: Vec<f32> = lng.iter().map(|lng| lng * 0.001).collect();
+    let index = SnapIndex::new(&grid, &lat, &lng, None);
+    let east = grid.edge_index(2, 3).unwrap();
+    let west = grid.edge_index(3, 2).unwrap();
+
+    // both directions of the road between node 2 and 3
+    let positions = index.snap(0.0001, 0.0025, &SnapParams::default());
+    let mut edges: Vec<EdgeId> = positions.iter().map(|position| position.edge).collect();
+    edges.sort();
+    assert_eq!(edges, vec![std::cmp::min(east, west), std::cmp::max(east, west)]);
+    for position in &positions {
+        assert!((position.fraction - 0.5).abs() < 0.01);
+        assert!((position.distance - 11.1).abs() < 0.5);
+        assert!(position.latitude.abs() < 1e-6);
+    }
+
+    let snap_with_heading = |lat, lng, heading| {
+        index.snap(
+            lat,
+            lng,
+            &SnapParams {
+                heading: Some(heading),
+                ..SnapParams::default()
+            },
+        )
+    };
+    let eastbound = snap_with_heading(0.0001, 0.0025, 80.0);
+    assert_eq!(eastbound.len(), 1);
+    assert_eq!((eastbound[0].edge, eastbound[0].tail, eastbound[0].head), (east, 2, 3));
+    let westbound = snap_with_heading(0.0001, 0.0025, 265.0);
+    assert_eq!(westbound.len(), 1);
+    assert_eq!(westbound[0].edge, west);
+
+    let far_away = SnapParams {
+        max_distance: 100.0,
+        ..SnapParams::default()
+    };
+    assert!(index.snap(0.01, 0.01, &far_away).is_empty());
+
+    // partial weights of the snapped arc
+    let weights = grid.weight();
+    let partial = |fraction: f32| (weights[east as usize] as f32 * fraction).round() as Weight;
+    assert_eq!(eastbound[0].source(weights), (3, partial(1.0 - eastbound[0].fraction)));
+    assert_eq!(eastbound[0].target(weights), (2, partial(eastbound[0].fraction)));
+
+    // on the same arc, the route just follows the arc
+    let source = snap_with_heading(0.0, 0.0021, 90.0);
+    let target = snap_with_heading(0.0, 0.0029, 90.0);
+    let mut dijkstra = DijkServer::<DefaultOps, _, _>::new(grid.clone());
+    let mut query = |query| QueryServer::query(&mut dijkstra, query).map(|mut res| (res.distance(), res.path()));
+    let route = snapped_route(&source, &target, weights, &mut query).unwrap();
+    assert!(route.path.is_empty());
+    assert_eq!(route.distance, partial(target[0].fraction - source[0].fraction));
+
+    // in the opposite direction, the route has to turn around somewhere
+    let route = snapped_route(&target, &source, weights, &mut query).unwrap();
+    let (from, source_weight) = target[0].source(weights);
+    let (to, target_weight) = source[0].target(weights);
+    assert_eq!((from, to), (3, 2));
+    assert_eq!(route.distance, query(Query { from, to }).unwrap().0 + source_weight + target_weight);
+    assert_eq!(route.path.first(), Some(&3));
+    assert_eq!(route.path.last(), Some(&2));
+
+    // one-way roads are only snapped in their direction, detailed geometry is respected
+    let one_way = OwnedGraph::new(vec![0, 1, 1], vec![1], vec![100]);
+    let (lat, lng) = (vec![0.0, 0.0], vec![0.0, 0.002]);
+    let straight = SnapIndex::new(&one_way, &lat, &lng, None);
+    let positions = straight.snap(0.001, 0.001, &SnapParams::default());
+    assert_eq!(positions.len(), 1);
+    assert!(positions[0].distance > 100.0);
+    let bent = SnapIndex::new(&one_way, &lat, &lng, Some(&ArcGeometry::new(&[vec![(0.001, 0.001)]])));
+    let positions = bent.snap(0.001, 0.001, &SnapParams::default());
+    assert!(positions[0].distance < 1.0);
+    assert!((positions[0].fraction - 0.5).abs() < 0.01);
+    assert_eq!(positions[0].source(one_way.weight()), (1, 50));
+}
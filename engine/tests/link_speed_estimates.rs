@@ -1,6 +1,13 @@
 extern crate rust_road_router;
 
-use rust_road_router::link_speed_estimates::*;
+mod common;
+
+use common::{cch_for_grid, grid_graph};
+use rust_road_router::{
+    algo::{customizable_contraction_hierarchy, dijkstra::query::bidirectional_dijkstra::Server as BiDijkServer, snapping::SnapIndex, *},
+    datastr::graph::{time_dependent::Timestamp, validate::travel_time_functions, *},
+    link_speed_estimates::{map_matching::*, profiles::*, *},
+};
 
 #[test]
 fn check_for_empty_errors() {
@@ -334,3 +341,321 @@ fn speed_limits_after_initial() {
         ]
     );
 }
+
+// the shared grid with uniform travel times on a 0.001 degree spacing, which is roughly 111m
+fn grid(size: usize) -> (OwnedGraph, Vec<f32>, Vec<f32>) {
+    let (graph, lat, lng) = grid_graph(size);
+    let (first_out, head, weight) = graph.decompose();
+    let scale = |coords: Vec<f32>| coords.into_iter().map(|coord| coord * 0.001).collect();
+    (OwnedGraph::new(first_out, head, vec![111; weight.len()]), scale(lat), scale(lng))
+}
+
+#[test]
+fn map_matched_traces_can_be_estimated() {
+    let (graph, lat, lng) = grid(5);
+    let snap_index = SnapIndex::new(&graph, &lat, &lng, None);
+    let matcher = MapMatcher::new(&graph, &snap_index, MapMatchingParams::default());
+
+    // east along the southmost road from node 0 to node 4, then north to node 14, a point every 37m with a few meters of noise
+    let mut points: Vec<GpsPoint> = (0..12)
+        .map(|step| (0.00004 - 0.00002 * (step % 3) as f32, 0.00015 + 0.000333 * step as f32))
+        .chain((0..6).map(|step| (0.00015 + 0.000333 * step as f32, 0.00403 - 0.00002 * (step % 2) as f32)))
+        .enumerate()
+        .map(|(idx, (latitude, longitude))| GpsPoint {
+            timestamp: 100_000 + 3000 * idx as u64,
+            latitude,
+            longitude,
+        })
+        .collect();
+    // an outlier without roads nearby
+    points.insert(
+        7,
+        GpsPoint {
+            timestamp: 120_500,
+            latitude: 0.0015,
+            longitude: 0.0025,
+        },
+    );
+
+    let cch = cch_for_grid(&graph, &lat, &lng);
+    let mut cch_server = customizable_contraction_hierarchy::query::Server::new(customizable_contraction_hierarchy::customize(&cch, &graph));
+    let matched = matcher.match_trace(&points, |query| {
+        cch_server.query(query).as_mut().map(|result| (result.distance(), result.path()))
+    });
+
+    let mut dijkstra = BiDijkServer::new(graph.clone());
+    let matched_with_dijkstra = matcher.match_trace(&points, |query| {
+        QueryServer::query(&mut dijkstra, query)
+            .as_mut()
+            .map(|result| (result.distance(), result.path()))
+    });
+
+    assert_eq!(matched.len(), 1);
+    assert_eq!(matched_with_dijkstra.len(), 1);
+    let matched = &matched[0];
+    assert_eq!(matched.path, matched_with_dijkstra[0].path);
+
+    let expected_path: Vec<EdgeId> = [(0, 1), (1, 2), (2, 3), (3, 4), (4, 9), (9, 14)]
+        .iter()
+        .map(|&(tail, head)| graph.edge_index(tail, head).unwrap())
+        .collect();
+    assert_eq!(matched.path, expected_path);
+    assert_eq!(matched.traces.len(), points.len() - 1);
+    assert!(matched.traces.iter().all(|trace| trace.timestamp != 120_500));
+    for pair in matched.traces.windows(2) {
+        if pair[0].link_id == pair[1].link_id {
+            assert!(pair[0].traversed_in_travel_direction_fraction < pair[1].traversed_in_travel_direction_fraction);
+        }
+    }
+
+    let links = matched.links(|arc| LinkData {
+        link_id: u64::from(arc),
+        length: graph.link(arc).weight * 1000,
+        speed_limit: 50,
+    });
+    let estimates: Vec<LinkSpeedData> = estimate_iter(Box::new(links.iter()), Box::new(matched.traces.iter())).unwrap().collect();
+    assert!(!estimates.is_empty());
+    for estimate in &estimates {
+        assert!(matched.path.contains(&(estimate.link_id as EdgeId)));
+        // 37m in 3s
+        assert!((estimate.velocity - 44.4).abs() < 10.0, "{:?}", estimate);
+    }
+}
+
+#[test]
+fn estimates_are_aggregated_into_fifo_profiles() {
+    const HOUR: u64 = 3_600_000;
+    let day = 24 * HOUR;
+    let estimate = |link_id, time_of_day, estimate_quality, velocity| LinkSpeedData {
+        link_id,
+        link_entered_timestamp: 3 * day + time_of_day,
+        estimate_quality,
+        velocity,
+    };
+
+    // three arcs of 1km with a free flow travel time of 72s (50km/h)
+    let params = ProfileParams {
+        bucket_size: HOUR as Timestamp,
+        min_quality: 1.0,
+        smoothing_radius: 0,
+    };
+    let mut builder = ProfileBuilder::new(vec![1_000_000; 3], vec![72_000; 3], params);
+    builder.extend(vec![
+        estimate(0, 3 * HOUR, 1.0, 50.0),
+        estimate(0, 8 * HOUR, 0.5, 10.0),
+        estimate(0, 8 * HOUR + 1000, 0.5, 10.0),
+        // not enough quality, will be interpolated
+        estimate(0, 12 * HOUR, 0.5, 5.0),
+        estimate(0, 17 * HOUR, 3.0, 20.0),
+        estimate(0, 17 * HOUR + HOUR / 2, 1.0, 60.0),
+        // takes two hours, but leaving an hour later is much faster
+        estimate(1, 5 * HOUR, 1.0, 0.5),
+        estimate(1, 6 * HOUR, 1.0, 50.0),
+    ]);
+    let profiles = builder.build();
+
+    assert!(
+        travel_time_functions(&profiles.first_ipp_of_arc, &profiles.ipp_departure_time, &profiles.ipp_travel_time)
+            .iter()
+            .all(|check| check.is_ok())
+    );
+    assert_eq!(profiles.first_ipp_of_arc[3] - profiles.first_ipp_of_arc[2], 1);
+
+    let graph = profiles.into_graph(vec![0, 2, 3], vec![1, 1, 0]);
+    let eval = |arc, time_of_day: u64| graph.travel_time_function(arc).eval(time_of_day as Timestamp);
+    assert_eq!(eval(0, 3 * HOUR), 72_000);
+    assert_eq!(eval(0, 8 * HOUR), 360_000);
+    assert_eq!(eval(0, 12 * HOUR), 266_667);
+    assert_eq!(eval(0, 17 * HOUR), 150_000);
+    assert_eq!(eval(1, 5 * HOUR), 3_672_000);
+    assert_eq!(eval(1, 6 * HOUR), 72_000);
+    assert_eq!(eval(2, 8 * HOUR), 72_000);
+
+    assert_eq!(builder.add(&estimate(3, 8 * HOUR, 1.0, 10.0)), Err(UnknownArc(3)));
+}
+
+fn link(link_id: u64) -> LinkData {
+    LinkData {
+        link_id,
+        length: 10000,
+        speed_limit: 50,
+    }
+}
+
+fn trace(link_id: u64, timestamp: u64, traversed_in_travel_direction_fraction: f32) -> TraceData {
+    TraceData {
+        timestamp,
+        link_id,
+        traversed_in_travel_direction_fraction,
+    }
+}
+
+// estimate for traces at 10% and 90% of a link one second apart, see `two_points_one_link`
+fn estimate_for_two_traces(link_id: u64, first_timestamp: u64) -> LinkSpeedData {
+    LinkSpeedData {
+        link_id,
+        link_entered_timestamp: first_timestamp - 125,
+        estimate_quality: 0.9 - 0.1,
+        velocity: 28.8,
+    }
+}
+
+fn try_estimate(links: &[LinkData], traces: &[TraceData]) -> (Vec<Result<LinkSpeedData, EstimateError>>, usize) {
+    let mut estimator = try_estimate_iter(Box::new(links.iter()), Box::new(traces.iter())).unwrap();
+    let results = estimator.by_ref().collect();
+    (results, estimator.skipped_segments())
+}
+
+#[test]
+fn links_without_traces_at_the_ends_are_ignored() {
+    let links = vec![link(0), link(1), link(2)];
+    let traces = vec![trace(1, 100_000, 0.1), trace(1, 101_000, 0.9)];
+    assert_eq!(try_estimate(&links, &traces), (vec![Ok(estimate_for_two_traces(1, 100_000))], 0));
+}
+
+#[test]
+fn traces_on_unknown_links_are_skipped() {
+    let links = vec![link(1), link(2)];
+    let traces = vec![
+        trace(1, 100_000, 0.1),
+        trace(1, 101_000, 0.9),
+        trace(7, 101_500, 0.5),
+        trace(2, 200_000, 0.1),
+        trace(2, 201_000, 0.9),
+    ];
+    assert_eq!(
+        try_estimate(&links, &traces),
+        (
+            vec![
+                Err(EstimateError {
+                    kind: EstimateErrorKind::UnknownLink,
+                    trace: trace(7, 101_500, 0.5),
+                }),
+                Ok(estimate_for_two_traces(2, 200_000)),
+            ],
+            1
+        )
+    );
+    assert_eq!(
+        estimate_iter(Box::new(links.iter()), Box::new(traces.iter())).unwrap().collect::<Vec<_>>(),
+        vec![estimate_for_two_traces(2, 200_000)]
+    );
+}
+
+#[test]
+fn traces_far_ahead_are_matched_with_bounded_lookahead() {
+    let links: Vec<LinkData> = (0..3000).map(link).collect();
+    let traces = vec![
+        trace(1, 100_000, 0.1),
+        trace(1, 101_000, 0.9),
+        // unknown link, the following traces are still matched
+        trace(7000, 101_500, 0.5),
+        trace(500, 200_000, 0.1),
+        trace(500, 201_000, 0.9),
+        // unknown link, fills the lookahead
+        trace(7000, 201_500, 0.5),
+        // not within the lookahead, the search resynchronizes after it
+        trace(2000, 300_000, 0.1),
+        trace(2000, 301_000, 0.9),
+    ];
+    // the segments interrupted by the unmatched traces are discarded
+    let (results, skipped_segments) = try_estimate(&links, &traces);
+    assert_eq!(skipped_segments, 2);
+    assert_eq!(
+        results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .map(|error| error.trace.link_id)
+            .collect::<Vec<_>>(),
+        vec![7000, 7000]
+    );
+    assert_eq!(
+        estimate_iter(Box::new(links.iter()), Box::new(traces.iter())).unwrap().collect::<Vec<_>>(),
+        vec![estimate_for_two_traces(2000, 300_000)]
+    );
+}
+
+#[test]
+fn traces_going_back_in_time_are_skipped() {
+    let links = vec![link(1), link(2)];
+    let traces = vec![
+        trace(1, 100_000, 0.1),
+        trace(1, 99_000, 0.5),
+        trace(1, 101_000, 0.9),
+        trace(2, 200_000, 0.1),
+        trace(2, 201_000, 0.9),
+    ];
+    assert_eq!(
+        try_estimate(&links, &traces),
+        (
+            vec![
+                Err(EstimateError {
+                    kind: EstimateErrorKind::NonIncreasingTimestamp,
+                    trace: trace(1, 99_000, 0.5),
+                }),
+                Ok(estimate_for_two_traces(2, 200_000)),
+            ],
+            1
+        )
+    );
+}
+
+#[test]
+fn traces_going_backwards_on_a_link_are_skipped() {
+    let links = vec![link(1), link(2)];
+    let traces = vec![
+        trace(1, 100_000, 0.1),
+        trace(1, 101_000, 0.9),
+        trace(1, 102_000, 0.5),
+        trace(2, 200_000, 0.1),
+        trace(2, 201_000, 0.9),
+    ];
+    assert_eq!(
+        try_estimate(&links, &traces),
+        (
+            vec![
+                Err(EstimateError {
+                    kind: EstimateErrorKind::DecreasingFraction,
+                    trace: trace(1, 102_000, 0.5),
+                }),
+                Ok(estimate_for_two_traces(2, 200_000)),
+            ],
+            1
+        )
+    );
+}
+
+#[test]
+fn repeated_links_are_skipped() {
+    let links = vec![link(1), link(1), link(2)];
+    let traces = vec![trace(1, 100_000, 0.1), trace(1, 101_000, 0.9), trace(2, 200_000, 0.1), trace(2, 201_000, 0.9)];
+    assert_eq!(
+        try_estimate(&links, &traces),
+        (
+            vec![
+                Err(EstimateError {
+                    kind: EstimateErrorKind::RepeatedLink,
+                    trace: trace(1, 101_000, 0.9),
+                }),
+                Ok(estimate_for_two_traces(2, 200_000)),
+            ],
+            1
+        )
+    );
+}
+
+#[test]
+fn single_traces_are_skipped() {
+    let links = vec![link(1), link(2)];
+    let traces = vec![trace(1, 100_000, 0.5)];
+    assert_eq!(
+        try_estimate(&links, &traces),
+        (
+            vec![Err(EstimateError {
+                kind: EstimateErrorKind::SingleTrace,
+                trace: trace(1, 100_000, 0.5),
+            })],
+            1
+        )
+    );
+}
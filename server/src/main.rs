@@ -12,24 +12,36 @@ use std::{
     error::Error,
     iter::once,
     path::{Path, PathBuf},
-    sync::mpsc::{self, Sender},
-    sync::{Arc, Mutex},
+    sync::mpsc::{self, Receiver, Sender},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
     thread,
 };
 
 use rocket::{request::Form, response::NamedFile, State};
 use rocket_contrib::json::Json;
 
-use kdtree::kdtree::{Kdtree, KdtreePointTrait};
-
 use rust_road_router::{
     algo::{
-        customizable_contraction_hierarchy::{contract, customize as cch_customize, query::Server, CCHReordering},
+        customizable_contraction_hierarchy::{
+            contract, customize as cch_customize, customize_directed, customize_partial,
+            metric::MetricBuilder,
+            nested_dissection,
+            phast::Server as PhastServer,
+            query::{concurrent::SharedMetric, turns::Server as TurnServer, AlternativeParams},
+            CCHReconstrctor, CCHReordering, CustomizedReconstrctor, CCH,
+        },
+        snapping::{snapped_route, SnapIndex, SnapParams, SnappedRoute},
         *,
     },
     cli::CliErr,
     datastr::{
-        graph::{link_id_to_tail_mapper::*, *},
+        graph::{
+            arc_geometry::ArcGeometry,
+            link_id_to_tail_mapper::*,
+            turn_costs::{ArcBearings, TrafficSide, TurnCostModel, TurnCostParams},
+            turn_expansion::{TurnExpansion, TurnTable},
+            *,
+        },
         node_order::NodeOrder,
         rank_select_map::*,
     },
@@ -38,34 +50,33 @@ use rust_road_router::{
     report::report_time,
 };
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-struct NodeCoord {
-    coords: [f64; 2],
-    node_id: NodeId,
-}
-
-impl KdtreePointTrait for NodeCoord {
-    #[inline] // the inline on this method is important! Without it there is ~25% speed loss on the tree when cross-crate usage.
-    fn dims(&self) -> &[f64] {
-        &self.coords
-    }
-}
-
-#[derive(Debug, FromForm, Copy, Clone)]
+#[derive(Debug, FromForm, Clone)]
 struct GeoQuery {
     from_lat: f32,
     from_lng: f32,
     to_lat: f32,
     to_lng: f32,
+    from_heading: Option<f32>,
+    to_heading: Option<f32>,
+    alternatives: Option<usize>,
+    profile: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct GeoResponse {
     distance: Weight,
     path: Vec<(f32, f32)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    alternatives: Vec<GeoRoute>,
 }
 
-#[derive(Debug, FromForm, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize)]
+struct GeoRoute {
+    distance: Weight,
+    path: Vec<(f32, f32)>,
+}
+
+#[derive(Debug, FromForm, Clone)]
 struct HereQuery {
     from_link_id: u64,
     from_direction: bool,
@@ -73,18 +84,59 @@ struct HereQuery {
     to_link_id: u64,
     to_direction: bool,
     to_link_fraction: f32,
+    profile: Option<String>,
 }
 
+// Travel time metric, the only one updated by `/customize` and the only one available with turns.
+const DEFAULT_PROFILE: &str = "default";
+
+// Number of query worker threads if not given as second argument.
+const DEFAULT_NUM_WORKERS: usize = 4;
+
+// Updates changing more than this fraction (one over the divisor) of all links are customized from scratch.
+const PARTIAL_CUSTOMIZATION_MAX_CHANGED_ARCS_DIVISOR: usize = 100;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct HereResponse {
     distance: Weight,
     path: Vec<(u64, bool)>,
 }
 
+#[derive(Debug, FromForm, Copy, Clone)]
+struct IsochroneQuery {
+    lat: f32,
+    lng: f32,
+    time_budget: Weight,
+    max_edge_length: Option<f32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IsochroneResponse {
+    reached: Vec<(f32, f32)>,
+    boundary: Vec<(f32, f32)>,
+    polygon: Option<Vec<(f32, f32)>>,
+}
+
+// Turn restrictions and turn costs as sparse turn tables, see `TurnTable`.
+// Geometric turn costs are only used with a traffic side, they are computed from bearings and road classes, see `TurnCostModel`.
+// The order is a nested dissection order for the turn expanded graph.
+struct Turns {
+    forbidden_turn_from_arc: Vec<EdgeId>,
+    forbidden_turn_to_arc: Vec<EdgeId>,
+    turn_cost_from_arc: Vec<EdgeId>,
+    turn_cost_to_arc: Vec<EdgeId>,
+    turn_cost: Vec<Weight>,
+    traffic_side: Option<TrafficSide>,
+    bearings: Option<ArcBearings>,
+    road_classes: Option<Vec<u8>>,
+    order: Option<NodeOrder>,
+}
+
 #[derive(Debug)]
 enum Request {
     Geo((GeoQuery, Sender<Option<GeoResponse>>)),
     Here((HereQuery, Sender<Option<HereResponse>>)),
+    Isochrone((IsochroneQuery, Sender<IsochroneResponse>)),
     Customize(Vec<(u64, bool, SerializedWeight)>),
 }
 
@@ -103,10 +155,10 @@ fn query(query_params: Form<GeoQuery>, state: State<Mutex<Sender<Request>>>) ->
     let result = report_time("Total Query Request Time", || {
         println!("Received Query: {:?}", query_params);
 
-        let tx_query = state.lock().unwrap();
+        let tx_query = state.lock().unwrap().clone();
         let (tx_result, rx_result) = mpsc::channel::<Option<GeoResponse>>();
 
-        tx_query.send(Request::Geo((*query_params, tx_result))).unwrap();
+        tx_query.send(Request::Geo((query_params.into_inner(), tx_result))).unwrap();
         rx_result.recv().expect("routing engine crashed or hung up")
     });
 
@@ -119,10 +171,26 @@ fn here_query(query_params: Form<HereQuery>, state: State<Mutex<Sender<Request>>
     let result = report_time("Total Query Request Time", || {
         println!("Received Query: {:?}", query_params);
 
-        let tx_query = state.lock().unwrap();
+        let tx_query = state.lock().unwrap().clone();
         let (tx_result, rx_result) = mpsc::channel::<Option<HereResponse>>();
 
-        tx_query.send(Request::Here((*query_params, tx_result))).unwrap();
+        tx_query.send(Request::Here((query_params.into_inner(), tx_result))).unwrap();
+        rx_result.recv().expect("routing engine crashed or hung up")
+    });
+
+    println!();
+    Json(result)
+}
+
+#[get("/isochrone?<query_params..>", format = "application/json")]
+fn isochrone_query(query_params: Form<IsochroneQuery>, state: State<Mutex<Sender<Request>>>) -> Json<IsochroneResponse> {
+    let result = report_time("Total Isochrone Request Time", || {
+        println!("Received Query: {:?}", query_params);
+
+        let tx_query = state.lock().unwrap().clone();
+        let (tx_result, rx_result) = mpsc::channel::<IsochroneResponse>();
+
+        tx_query.send(Request::Isochrone((*query_params, tx_result))).unwrap();
         rx_result.recv().expect("routing engine crashed or hung up")
     });
 
@@ -178,29 +246,99 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let arg = &args.next().ok_or(CliErr("No directory arg given"))?;
     let path = Path::new(arg);
+    let num_workers = match args.next() {
+        Some(arg) => arg.parse()?,
+        None => DEFAULT_NUM_WORKERS,
+    };
 
-    let first_out = Vec::load_from(path.join("first_out"))?;
-    let head = Vec::load_from(path.join("head"))?;
+    let first_out = MmapSlice::<EdgeId>::map_from(path.join("first_out"))?;
+    let head = MmapSlice::<NodeId>::map_from(path.join("head"))?;
     let travel_time = Vec::load_from(path.join("travel_time"))?;
 
     let lat = Vec::load_from(path.join("latitude"))?;
     let lng = Vec::load_from(path.join("longitude"))?;
 
-    let mut coords: Vec<NodeCoord> = lat
-        .iter()
-        .zip(lng.iter())
-        .enumerate()
-        .map(|(node_id, (&lat, &lng))| NodeCoord {
-            node_id: node_id as NodeId,
-            coords: [f64::from(lat), f64::from(lng)],
-        })
-        .collect();
-    let tree = report_time("build kd tree", || Kdtree::new(&mut coords));
+    // queries are snapped to the closest arcs, following the HERE link geometry if available
+    let arc_geometry = if path.join("arc_geometry_first_point").exists() {
+        Some(ArcGeometry::reconstruct_from(&path)?)
+    } else {
+        None
+    };
+    let snap_index = report_time("build snap index", || {
+        SnapIndex::new(
+            &FirstOutGraph::new(&first_out[..], &head[..], &travel_time[..]),
+            &lat,
+            &lng,
+            arc_geometry.as_ref(),
+        )
+    });
 
     let link_id_mapping = BitVec::load_from(path.join("link_id_mapping"))?;
     let link_id_mapping = InvertableRankSelectMap::new(RankSelectMap::new(link_id_mapping));
     let here_rank_to_link_id = Vec::load_from(path.join("here_rank_to_link_id"))?;
     let cch_order = NodeOrder::from_node_order(Vec::load_from(path.join("cch_perm"))?);
+    let cch_folder = path.join("cch");
+
+    // with turn restrictions, all queries will be answered on the turn expanded graph
+    let turns = if path.join("forbidden_turn_from_arc").exists() {
+        let load_or_empty = |file: &str| -> std::io::Result<Vec<u32>> {
+            if path.join(file).exists() {
+                Vec::load_from(path.join(file))
+            } else {
+                Ok(Vec::new())
+            }
+        };
+        Some(Turns {
+            forbidden_turn_from_arc: Vec::load_from(path.join("forbidden_turn_from_arc"))?,
+            forbidden_turn_to_arc: Vec::load_from(path.join("forbidden_turn_to_arc"))?,
+            turn_cost_from_arc: load_or_empty("turn_cost_from_arc")?,
+            turn_cost_to_arc: load_or_empty("turn_cost_to_arc")?,
+            turn_cost: load_or_empty("turn_cost")?,
+            traffic_side: if path.join("geometric_turn_costs").exists() {
+                match std::fs::read_to_string(path.join("geometric_turn_costs"))?.trim() {
+                    "right" => Some(TrafficSide::Right),
+                    "left" => Some(TrafficSide::Left),
+                    _ => return Err(Box::new(CliErr("geometric_turn_costs has to contain the traffic side, either right or left"))),
+                }
+            } else {
+                None
+            },
+            bearings: if path.join("arc_start_bearing").exists() && path.join("arc_end_bearing").exists() {
+                Some(ArcBearings {
+                    start: Vec::load_from(path.join("arc_start_bearing"))?,
+                    end: Vec::load_from(path.join("arc_end_bearing"))?,
+                })
+            } else {
+                None
+            },
+            road_classes: if path.join("functional_road_classes").exists() {
+                Some(Vec::load_from(path.join("functional_road_classes"))?)
+            } else if path.join("arc_category").exists() {
+                Some(Vec::load_from(path.join("arc_category"))?)
+            } else {
+                None
+            },
+            order: if path.join("cch_exp_perm").exists() {
+                Some(NodeOrder::from_node_order(Vec::load_from(path.join("cch_exp_perm"))?))
+            } else {
+                None
+            },
+        })
+    } else {
+        None
+    };
+
+    // additional metrics selectable with the `profile` query parameter, not supported on the turn expanded graph
+    let profiles = if turns.is_none() && path.join("profiles").exists() {
+        report_time("build profile metrics", || -> Result<Vec<(String, Vec<Weight>)>, Box<dyn Error>> {
+            load_profiles(&path.join("profiles"))?
+                .into_iter()
+                .map(|(name, metric)| Ok((name, metric.build(path)?)))
+                .collect()
+        })?
+    } else {
+        Vec::new()
+    };
 
     // all further preprocessing happening asynchronous
     thread::spawn(move || {
@@ -208,141 +346,603 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         let graph = FirstOutGraph::new(&first_out[..], &head[..], travel_time.clone());
 
-        let link_id_to_tail_mapper = LinkIdToTailMapper::new(&graph);
+        let coords = |node: NodeId| -> (f32, f32) { (lat[node as usize], lng[node as usize]) };
 
-        let cch = contract(&graph, cch_order);
-        let cch_order = CCHReordering {
-            cch: &cch,
-            latitude: &[],
-            longitude: &[],
+        let snap = |p_lat: f32, p_lng: f32, heading: Option<f32>| {
+            snap_index.snap(
+                p_lat,
+                p_lng,
+                &SnapParams {
+                    heading,
+                    ..SnapParams::default()
+                },
+            )
+        };
+        // nothing snaps for invalid coordinates
+        let closest_node = |(p_lat, p_lng): (f32, f32)| -> Option<NodeId> { snap(p_lat, p_lng, None).first().map(|position| position.closest_node()) };
+
+        if let Some(turns) = turns {
+            serve_with_turns(rx_query, graph, &id_mapper, turns, &lat, &lng, closest_node);
+            return;
         }
-        .reorder_for_seperator_based_customization();
-        let cch = contract(&graph, cch_order);
 
-        let server = Arc::new(Mutex::new(Server::new(cch_customize(&cch, &graph))));
+        let link_id_to_tail_mapper = LinkIdToTailMapper::new(&graph);
 
-        let coords = |node: NodeId| -> (f32, f32) { (lat[node as usize], lng[node as usize]) };
+        // use the CCH and metric stored by `cch_customization` if available
+        let cch = if cch_folder.join("cch_header").exists() {
+            CCH::reconstruct_from(&cch_folder).unwrap()
+        } else if cch_folder.join("cch_first_out").exists() {
+            CCHReconstrctor {
+                original_graph: &graph,
+                node_order: NodeOrder::reconstruct_from(&cch_folder).unwrap(),
+            }
+            .reconstruct_from(&cch_folder)
+            .unwrap()
+        } else {
+            let cch = contract(&graph, cch_order);
+            let cch_order = CCHReordering {
+                cch: &cch,
+                latitude: &[],
+                longitude: &[],
+            }
+            .reorder_for_seperator_based_customization();
+            contract(&graph, cch_order)
+        };
 
-        let closest_node = |(p_lat, p_lng): (f32, f32)| -> NodeId {
-            tree.nearest_search(&NodeCoord {
-                coords: [f64::from(p_lat), f64::from(p_lng)],
-                node_id: 0,
-            })
-            .node_id
+        let customized_folder = cch_folder.join("customized_travel_time");
+        let customized = if customized_folder.exists() {
+            let stored = CustomizedReconstrctor {
+                cch: &cch,
+                metric: graph.weight(),
+            }
+            .reconstruct_from(&customized_folder);
+            match stored {
+                Ok(customized) => customized,
+                Err(e) => {
+                    eprintln!("Stored customization can not be used ({}), customizing from scratch", e);
+                    cch_customize(&cch, &graph)
+                }
+            }
+        } else {
+            cch_customize(&cch, &graph)
         };
+        let phast = Mutex::new(PhastServer::new(customized.clone()));
+        // the travel times of the current default metric, needed for the partial costs of the first and last arc
+        let default_weights = RwLock::new(Arc::new(travel_time.clone()));
+        // current travel times and their customization, updated incrementally by `/customize`
+        let current_travel_time = Mutex::new((travel_time, customized.clone()));
+        // the weights of each profile are shared by all workers and replaced atomically by `/customize`
+        let mut metrics = vec![(DEFAULT_PROFILE.to_string(), SharedMetric::new(customized))];
+        let mut profile_weights = Vec::with_capacity(profiles.len());
+        for (name, weights) in profiles {
+            let customized = report_time("customize profile", || {
+                cch_customize(&cch, &FirstOutGraph::new(&first_out[..], &head[..], &weights[..]))
+            });
+            metrics.push((name.clone(), SharedMetric::new(customized)));
+            profile_weights.push((name, Arc::new(weights)));
+        }
+        // the metric and the weights of a profile, the weights are needed for the partial costs of the first and last arc
+        let metric = |profile: Option<String>| {
+            let profile = profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+            let weights = match profile_weights.iter().find(|(name, _)| name == profile) {
+                Some((_, weights)) => weights.clone(),
+                None => read(&default_weights).clone(),
+            };
+            metrics.iter().find(|(name, _)| name == profile).map(|(_, metric)| (metric, weights))
+        };
+        let rx_query = Mutex::new(rx_query);
 
-        // Customization should run asynchronous to queries but needs all the data,
-        // Thus we create a scope here, so we can later spawn new threads
-        // without the risk of data going out of scope.
+        // Each worker answers queries with its own workspace.
+        // Customization runs in the worker which received the request while the others continue on the previous metric.
+        // The scope makes sure, the workers can borrow all the data.
         crossbeam_utils::thread::scope(|scope| {
-            for query_params in rx_query {
-                match query_params {
-                    Request::Geo((
-                        GeoQuery {
-                            from_lat,
-                            from_lng,
-                            to_lat,
-                            to_lng,
-                        },
-                        tx_result,
-                    )) => {
-                        let (from, to) = report_time("match nodes", || (closest_node((from_lat, from_lng)), closest_node((to_lat, to_lng))));
-
-                        let mut server = server.lock().unwrap();
-                        let result = report_time("cch query", || {
-                            server.query(Query { from, to }).as_mut().map(|result| {
-                                let distance = result.distance();
-                                let path = result.path().iter().map(|&node| coords(node)).collect();
-                                GeoResponse { distance, path }
-                            })
-                        });
+            for _ in 0..num_workers {
+                scope.spawn(|| {
+                    let mut workspace = metrics[0].1.workspace();
+
+                    loop {
+                        // the lock is released before the request is processed
+                        let query_params = match lock(&rx_query).recv() {
+                            Ok(query_params) => query_params,
+                            Err(_) => break,
+                        };
+
+                        match query_params {
+                            Request::Geo((
+                                GeoQuery {
+                                    from_lat,
+                                    from_lng,
+                                    to_lat,
+                                    to_lng,
+                                    from_heading,
+                                    to_heading,
+                                    alternatives,
+                                    profile,
+                                },
+                                tx_result,
+                            )) => {
+                                let (sources, targets) =
+                                    report_time("snap to arcs", || (snap(from_lat, from_lng, from_heading), snap(to_lat, to_lng, to_heading)));
+
+                                let (server, weights) = match metric(profile) {
+                                    Some((metric, weights)) => (workspace.server(metric), weights),
+                                    None => {
+                                        tx_result.send(None).unwrap();
+                                        continue;
+                                    }
+                                };
+                                // the path starts and ends at the snapped positions
+                                let route_coords = |route: &SnappedRoute, path: &[NodeId]| -> Vec<(f32, f32)> {
+                                    once((route.source.latitude, route.source.longitude))
+                                        .chain(path.iter().map(|&node| coords(node)))
+                                        .chain(once((route.target.latitude, route.target.longitude)))
+                                        .collect()
+                                };
+
+                                let route = report_time("cch query", || {
+                                    snapped_route(&sources, &targets, &weights, |query| {
+                                        server.query(query).as_mut().map(|result| (result.distance(), result.path()))
+                                    })
+                                });
+                                let result = route.map(|route| {
+                                    let alternatives = match alternatives {
+                                        Some(k) if k > 0 && !route.path.is_empty() => report_time("cch alternatives query", || {
+                                            let (from, source_weight) = route.source.source(&weights);
+                                            let (to, target_weight) = route.target.target(&weights);
+                                            server
+                                                .alternatives(Query { from, to }, k, AlternativeParams::default())
+                                                .into_iter()
+                                                .skip(1)
+                                                .map(|alternative| GeoRoute {
+                                                    distance: alternative.distance + source_weight + target_weight,
+                                                    path: route_coords(&route, &alternative.path),
+                                                })
+                                                .collect()
+                                        }),
+                                        _ => Vec::new(),
+                                    };
+                                    GeoResponse {
+                                        distance: route.distance,
+                                        path: route_coords(&route, &route.path),
+                                        alternatives,
+                                    }
+                                });
 
-                        tx_result.send(result).unwrap();
-                    }
-                    Request::Here((
-                        HereQuery {
-                            from_link_id,
-                            from_direction,
-                            from_link_fraction,
-                            to_link_id,
-                            to_direction,
-                            to_link_fraction,
-                        },
-                        tx_result,
-                    )) => {
-                        let from_link_direction = if from_direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
-                        let from_link_local_id = id_mapper.here_to_local_link_id(from_link_id, from_link_direction).expect("non existing link");
-                        let from_link = graph.link(from_link_local_id);
-                        let from = from_link.node;
-
-                        let to_link_direction = if to_direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
-                        let to_link_local_id = id_mapper.here_to_local_link_id(to_link_id, to_link_direction).expect("non existing link");
-                        let to_link = graph.link(to_link_local_id);
-                        let to = link_id_to_tail_mapper.link_id_to_tail(to_link_local_id);
-
-                        let mut server = server.lock().unwrap();
-                        let result = report_time("cch query", || {
-                            server.query(Query { from, to }).as_mut().map(|result| {
-                                let distance = result.distance()
-                                    + (from_link_fraction * from_link.weight as f32) as u32
-                                    + (to_link_fraction * to_link.weight as f32) as u32;
-
-                                let path = result.path();
-                                let path_iter = path.iter();
-                                let mut second_node_iter = path_iter.clone();
-                                second_node_iter.next();
-
-                                let path = once((from_link_id, from_direction))
-                                    .chain(
-                                        path_iter
-                                            .zip(second_node_iter)
-                                            .map(|(first_node, second_node)| graph.edge_index(*first_node, *second_node).unwrap())
-                                            .map(|link_id| {
-                                                let (id, dir) = id_mapper.local_to_here_link_id(link_id);
-                                                (id, dir == LinkDirection::FromRef)
-                                            }),
-                                    )
-                                    .chain(once((to_link_id, to_direction)))
-                                    .collect();
-
-                                HereResponse { distance, path }
-                            })
-                        });
+                                tx_result.send(result).unwrap();
+                            }
+                            Request::Here((
+                                HereQuery {
+                                    from_link_id,
+                                    from_direction,
+                                    from_link_fraction,
+                                    to_link_id,
+                                    to_direction,
+                                    to_link_fraction,
+                                    profile,
+                                },
+                                tx_result,
+                            )) => {
+                                // the fractions of the first and last link are weighted with the metric of the profile
+                                let (server, weights) = match metric(profile) {
+                                    Some((metric, weights)) => (workspace.server(metric), weights),
+                                    None => {
+                                        tx_result.send(None).unwrap();
+                                        continue;
+                                    }
+                                };
+
+                                let from_link_direction = if from_direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
+                                let to_link_direction = if to_direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
+                                let (from_link_local_id, to_link_local_id) = match (
+                                    id_mapper.here_to_local_link_id(from_link_id, from_link_direction),
+                                    id_mapper.here_to_local_link_id(to_link_id, to_link_direction),
+                                ) {
+                                    (Some(from_link), Some(to_link)) => (from_link, to_link),
+                                    _ => {
+                                        tx_result.send(None).unwrap();
+                                        continue;
+                                    }
+                                };
+                                let from = graph.link(from_link_local_id).node;
+                                let to = link_id_to_tail_mapper.link_id_to_tail(to_link_local_id);
+
+                                let result = report_time("cch query", || {
+                                    server.query(Query { from, to }).as_mut().map(|result| {
+                                        let distance = result.distance()
+                                            + (from_link_fraction * weights[from_link_local_id as usize] as f32) as u32
+                                            + (to_link_fraction * weights[to_link_local_id as usize] as f32) as u32;
+
+                                        let path = result.path();
+                                        let path_iter = path.iter();
+                                        let mut second_node_iter = path_iter.clone();
+                                        second_node_iter.next();
+
+                                        let path = once((from_link_id, from_direction))
+                                            .chain(
+                                                path_iter
+                                                    .zip(second_node_iter)
+                                                    .map(|(first_node, second_node)| graph.edge_index(*first_node, *second_node).unwrap())
+                                                    .map(|link_id| {
+                                                        let (id, dir) = id_mapper.local_to_here_link_id(link_id);
+                                                        (id, dir == LinkDirection::FromRef)
+                                                    }),
+                                            )
+                                            .chain(once((to_link_id, to_direction)))
+                                            .collect();
+
+                                        HereResponse { distance, path }
+                                    })
+                                });
+
+                                tx_result.send(result).unwrap();
+                            }
+                            Request::Isochrone((
+                                IsochroneQuery {
+                                    lat: from_lat,
+                                    lng: from_lng,
+                                    time_budget,
+                                    max_edge_length,
+                                },
+                                tx_result,
+                            )) => {
+                                let from = match report_time("match nodes", || closest_node((from_lat, from_lng))) {
+                                    Some(from) => from,
+                                    None => {
+                                        tx_result.send(IsochroneResponse::default()).unwrap();
+                                        continue;
+                                    }
+                                };
+
+                                let mut phast = lock(&phast);
+                                let result = report_time("isochrone query", || {
+                                    let distances = phast.one_to_all(from);
+                                    let isochrone = isochrone::static_isochrone(&graph, |node| distances.distance(node), time_budget, &lat, &lng);
+                                    IsochroneResponse {
+                                        reached: isochrone.reached_nodes.iter().map(|&node| coords(node)).collect(),
+                                        boundary: isochrone.boundary.iter().map(|point| (point.latitude, point.longitude)).collect(),
+                                        polygon: max_edge_length.map(|max_edge_length| isochrone.polygon(&lat, &lng, max_edge_length)),
+                                    }
+                                });
 
-                        tx_result.send(result).unwrap();
-                    }
-                    Request::Customize(updates) => {
-                        let server = server.clone();
-                        let mut travel_time = travel_time.clone();
-                        let id_mapper = &id_mapper;
-                        let cch = &cch;
-                        let first_out = &first_out;
-                        let head = &head;
-
-                        // asynchronous customization
-                        scope.spawn(move || {
-                            for (here_link_id, is_from_ref, weight) in updates.into_iter() {
-                                if is_from_ref {
-                                    if let Some(link_idx) = id_mapper.here_to_local_link_id(here_link_id, LinkDirection::FromRef) {
-                                        travel_time[link_idx as usize] = weight.0
+                                tx_result.send(result).unwrap();
+                            }
+                            Request::Customize(updates) => {
+                                // only the shortcuts affected by the changed links are updated
+                                let mut current_travel_time = lock(&current_travel_time);
+                                let (travel_time, customized) = &mut *current_travel_time;
+
+                                let mut changed_arcs = Vec::new();
+                                for (here_link_id, is_from_ref, weight) in updates.into_iter() {
+                                    let direction = if is_from_ref { LinkDirection::FromRef } else { LinkDirection::ToRef };
+                                    if let Some(link_idx) = id_mapper.here_to_local_link_id(here_link_id, direction) {
+                                        if travel_time[link_idx as usize] != weight.0 {
+                                            travel_time[link_idx as usize] = weight.0;
+                                            changed_arcs.push(link_idx);
+                                        }
                                     }
-                                } else if let Some(link_idx) = id_mapper.here_to_local_link_id(here_link_id, LinkDirection::ToRef) {
-                                    travel_time[link_idx as usize] = weight.0
                                 }
+
+                                let stats = report_time("partial customization", || {
+                                    let metric = FirstOutGraph::new(&first_out[..], &head[..], &travel_time[..]);
+                                    customize_partial(customized, &metric, &changed_arcs, head.len() / PARTIAL_CUSTOMIZATION_MAX_CHANGED_ARCS_DIVISOR)
+                                });
+                                println!("{} links changed, {:?}", changed_arcs.len(), stats);
+
+                                *lock(&phast) = PhastServer::new(customized.clone());
+                                metrics[0].1.update(customized.clone());
+                                *write(&default_weights) = Arc::new(travel_time.clone());
                             }
-                            let customized = cch_customize(&cch, &FirstOutGraph::new(&first_out[..], &head[..], travel_time));
-                            server.lock().unwrap().update(customized);
-                        });
+                        }
                     }
-                }
+                });
             }
         });
     });
 
     rocket::ignite()
-        .mount("/", routes![index, files, query, here_query, customize])
+        .mount("/", routes![index, files, query, here_query, isochrone_query, customize])
         .manage(Mutex::new(tx_query))
         .launch();
 
     Ok(())
 }
+
+// Parse the profile definitions, one `name = metric expression` per line, see `MetricBuilder`.
+// Empty lines and lines starting with `#` are skipped.
+fn load_profiles(file: &Path) -> Result<Vec<(String, MetricBuilder)>, Box<dyn Error>> {
+    let mut profiles: Vec<(String, MetricBuilder)> = Vec::new();
+    for line in std::fs::read_to_string(file)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(2, '=');
+        let name = parts.next().unwrap().trim();
+        let metric = parts.next().ok_or(CliErr("Profile definition without metric"))?.parse()?;
+        if name.is_empty() || name == DEFAULT_PROFILE || profiles.iter().any(|(other, _)| other == name) {
+            return Err(Box::new(CliErr("Invalid or duplicate profile name")));
+        }
+        profiles.push((name.to_string(), metric));
+    }
+    Ok(profiles)
+}
+
+// A panic during one request must not take the workers down with it, so poisoned locks are used anyway.
+// At worst, the protected data reflects a partially applied `/customize` request.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Build the turn expanded graph.
+// U-turns are only allowed at dead ends, where they are the only way to continue.
+// Explicitly given turn costs take precedence over the geometric ones, other turns are free without a turn cost model.
+fn turn_expanded_graph(
+    graph: &FirstOutGraph<&[EdgeId], &[NodeId], Vec<Weight>>,
+    expansion: &TurnExpansion,
+    turns: &Turns,
+    turn_cost_model: Option<&TurnCostModel>,
+) -> OwnedGraph {
+    let forbidden_turns = TurnTable::new(&turns.forbidden_turn_from_arc, &turns.forbidden_turn_to_arc);
+    let turn_costs = TurnTable::new(&turns.turn_cost_from_arc, &turns.turn_cost_to_arc);
+
+    graph.line_graph(|from_arc, to_arc| {
+        let next_arcs = expansion.outgoing(expansion.head(from_arc));
+        if forbidden_turns.find(from_arc, to_arc).is_some() || (expansion.is_u_turn(from_arc, to_arc) && next_arcs.end - next_arcs.start > 1) {
+            return None;
+        }
+        Some(
+            turn_costs
+                .find(from_arc, to_arc)
+                .map(|idx| turns.turn_cost[idx])
+                .or_else(|| turn_cost_model.map(|model| model.cost(from_arc, to_arc)))
+                .unwrap_or(0),
+        )
+    })
+}
+
+// Same as the engine thread in `main` but on a directed CCH of the turn expanded graph.
+// Node queries are routed between the best outgoing arc of the source and incoming arc of the target,
+// HERE queries directly between the given links.
+fn serve_with_turns(
+    rx_query: Receiver<Request>,
+    graph: FirstOutGraph<&[EdgeId], &[NodeId], Vec<Weight>>,
+    id_mapper: &LinkIdMapper,
+    turns: Turns,
+    lat: &[f32],
+    lng: &[f32],
+    closest_node: impl Fn((f32, f32)) -> Option<NodeId>,
+) {
+    let expansion = TurnExpansion::new(&graph);
+    let bearings = turns.traffic_side.map(|_| match &turns.bearings {
+        Some(bearings) => bearings.clone(),
+        None => ArcBearings::from_coordinates(&expansion, lat, lng),
+    });
+    let turn_cost_model = turns.traffic_side.map(|traffic_side| {
+        let params = TurnCostParams {
+            traffic_side,
+            ..TurnCostParams::default()
+        };
+        let turn_cost_model = TurnCostModel::new(params, &expansion, bearings.as_ref().unwrap());
+        match &turns.road_classes {
+            Some(road_classes) => turn_cost_model.with_road_classes(road_classes),
+            None => turn_cost_model,
+        }
+    });
+    let exp_graph = report_time("build turn expanded graph", || {
+        turn_expanded_graph(&graph, &expansion, &turns, turn_cost_model.as_ref())
+    });
+
+    let cch_order = match &turns.order {
+        Some(order) => order.clone(),
+        None => {
+            let (exp_lat, exp_lng) = expansion.edge_node_coordinates(lat, lng);
+            nested_dissection(&exp_graph, &exp_lat, &exp_lng)
+        }
+    };
+    let cch = contract(&exp_graph, cch_order);
+    let cch_order = CCHReordering {
+        cch: &cch,
+        latitude: &[],
+        longitude: &[],
+    }
+    .reorder_for_seperator_based_customization();
+    let cch = contract(&exp_graph, cch_order).into_directed_cch();
+
+    let customized = customize_directed(&cch, &exp_graph);
+    let phast = Arc::new(Mutex::new(PhastServer::new(customized.clone())));
+    let server = Arc::new(Mutex::new(TurnServer::new(customized, &expansion, graph.weight().to_vec())));
+    // current travel times, updated by `/customize` one batch after the other
+    let current_travel_time = Mutex::new(graph.weight().to_vec());
+
+    let coords = |node: NodeId| -> (f32, f32) { (lat[node as usize], lng[node as usize]) };
+    let arc_coords = |arcs: &[EdgeId]| -> Vec<(f32, f32)> { expansion.node_path(arcs).into_iter().map(coords).collect() };
+
+    crossbeam_utils::thread::scope(|scope| {
+        for query_params in rx_query {
+            match query_params {
+                Request::Geo((
+                    GeoQuery {
+                        from_lat,
+                        from_lng,
+                        to_lat,
+                        to_lng,
+                        alternatives,
+                        profile,
+                        ..
+                    },
+                    tx_result,
+                )) => {
+                    if profile.as_deref().unwrap_or(DEFAULT_PROFILE) != DEFAULT_PROFILE {
+                        tx_result.send(None).unwrap();
+                        continue;
+                    }
+                    let (from, to) = match report_time("match nodes", || (closest_node((from_lat, from_lng)), closest_node((to_lat, to_lng)))) {
+                        (Some(from), Some(to)) => (from, to),
+                        _ => {
+                            tx_result.send(None).unwrap();
+                            continue;
+                        }
+                    };
+
+                    // the arc path of a route from a node to itself is empty
+                    let route_coords = |path: &[EdgeId]| if from == to { vec![coords(from)] } else { arc_coords(path) };
+
+                    let mut server = lock(&server);
+                    let result = match alternatives {
+                        Some(k) if k > 0 => report_time("turn aware cch alternatives query", || {
+                            let mut routes = server
+                                .alternatives(from, to, k, AlternativeParams::default())
+                                .into_iter()
+                                .map(|route| GeoRoute {
+                                    distance: route.distance,
+                                    path: route_coords(&route.path),
+                                });
+                            routes.next().map(|GeoRoute { distance, path }| GeoResponse {
+                                distance,
+                                path,
+                                alternatives: routes.collect(),
+                            })
+                        }),
+                        _ => report_time("turn aware cch query", || {
+                            server.node_query(from, to).map(|(distance, path)| GeoResponse {
+                                distance,
+                                path: route_coords(&path),
+                                alternatives: Vec::new(),
+                            })
+                        }),
+                    };
+
+                    tx_result.send(result).unwrap();
+                }
+                Request::Here((
+                    HereQuery {
+                        from_link_id,
+                        from_direction,
+                        from_link_fraction,
+                        to_link_id,
+                        to_direction,
+                        to_link_fraction,
+                        profile,
+                    },
+                    tx_result,
+                )) => {
+                    if profile.as_deref().unwrap_or(DEFAULT_PROFILE) != DEFAULT_PROFILE {
+                        tx_result.send(None).unwrap();
+                        continue;
+                    }
+                    let from_link_direction = if from_direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
+                    let to_link_direction = if to_direction { LinkDirection::FromRef } else { LinkDirection::ToRef };
+                    let (from_link, to_link) = match (
+                        id_mapper.here_to_local_link_id(from_link_id, from_link_direction),
+                        id_mapper.here_to_local_link_id(to_link_id, to_link_direction),
+                    ) {
+                        (Some(from_link), Some(to_link)) => (from_link, to_link),
+                        _ => {
+                            tx_result.send(None).unwrap();
+                            continue;
+                        }
+                    };
+
+                    let mut server = lock(&server);
+                    let result = report_time("turn aware cch query", || {
+                        server.arc_query(from_link, to_link).map(|(distance, path)| {
+                            // the arc query includes the complete from link, the rest is accounted for like in the node based case
+                            let distance = (distance
+                                + (from_link_fraction * graph.weight()[from_link as usize] as f32) as u32
+                                + (to_link_fraction * graph.weight()[to_link as usize] as f32) as u32)
+                                .saturating_sub(graph.weight()[from_link as usize]);
+
+                            let path = path
+                                .into_iter()
+                                .map(|link_id| {
+                                    let (id, dir) = id_mapper.local_to_here_link_id(link_id);
+                                    (id, dir == LinkDirection::FromRef)
+                                })
+                                .collect();
+
+                            HereResponse { distance, path }
+                        })
+                    });
+
+                    tx_result.send(result).unwrap();
+                }
+                Request::Isochrone((
+                    IsochroneQuery {
+                        lat: from_lat,
+                        lng: from_lng,
+                        time_budget,
+                        max_edge_length,
+                    },
+                    tx_result,
+                )) => {
+                    let from = match report_time("match nodes", || closest_node((from_lat, from_lng))) {
+                        Some(from) => from,
+                        None => {
+                            tx_result.send(IsochroneResponse::default()).unwrap();
+                            continue;
+                        }
+                    };
+
+                    let mut phast = lock(&phast);
+                    let result = report_time("turn aware isochrone query", || {
+                        // one PHAST query per outgoing arc, a node is reached when the first of its incoming arcs is completely traversed
+                        let mut node_distances = vec![INFINITY; graph.num_nodes()];
+                        node_distances[from as usize] = 0;
+                        for first_arc in expansion.outgoing(from) {
+                            let distances = phast.one_to_all(first_arc);
+                            for arc in 0..graph.num_arcs() as EdgeId {
+                                let distance = distances.distance(arc).saturating_add(graph.weight()[arc as usize]);
+                                let head = expansion.head(arc) as usize;
+                                node_distances[head] = std::cmp::min(node_distances[head], distance);
+                            }
+                        }
+                        // the boundary ignores turn restrictions at the last reached node
+                        let isochrone = isochrone::static_isochrone(&graph, |node| node_distances[node as usize], time_budget, lat, lng);
+                        IsochroneResponse {
+                            reached: isochrone.reached_nodes.iter().map(|&node| coords(node)).collect(),
+                            boundary: isochrone.boundary.iter().map(|point| (point.latitude, point.longitude)).collect(),
+                            polygon: max_edge_length.map(|max_edge_length| isochrone.polygon(lat, lng, max_edge_length)),
+                        }
+                    });
+
+                    tx_result.send(result).unwrap();
+                }
+                Request::Customize(updates) => {
+                    let server = server.clone();
+                    let phast = phast.clone();
+                    let current_travel_time = &current_travel_time;
+                    let cch = &cch;
+                    let expansion = &expansion;
+                    let turns = &turns;
+                    let turn_cost_model = turn_cost_model.as_ref();
+                    let graph = &graph;
+
+                    // asynchronous customization, the turn expanded graph has to be rebuilt with the new arc weights
+                    // the lock is held until the new metric is in place, so later batches can not be overwritten by earlier ones
+                    scope.spawn(move || {
+                        let mut travel_time = lock(&current_travel_time);
+                        for (here_link_id, is_from_ref, weight) in updates.into_iter() {
+                            let direction = if is_from_ref { LinkDirection::FromRef } else { LinkDirection::ToRef };
+                            if let Some(link_idx) = id_mapper.here_to_local_link_id(here_link_id, direction) {
+                                travel_time[link_idx as usize] = weight.0
+                            }
+                        }
+                        let updated_graph = FirstOutGraph::new(graph.first_out(), graph.head(), travel_time.clone());
+                        let exp_graph = turn_expanded_graph(&updated_graph, expansion, turns, turn_cost_model);
+                        let customized = customize_directed(cch, &exp_graph);
+                        *lock(&phast) = PhastServer::new(customized.clone());
+                        let (_, _, travel_time) = updated_graph.decompose();
+                        lock(&server).update(customized, travel_time);
+                    });
+                }
+            }
+        }
+    });
+}